@@ -0,0 +1,220 @@
+//! Backends for compiling QCs into models. Studiomdl is a Windows tool, so besides running it
+//! directly it can be run through Wine, or compiling can be skipped entirely.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use slog::Logger;
+use soto::task::{TaskError, TaskErrorKind};
+use soto::{Error, SotoLocalFile};
+
+use output;
+
+/// Compiles QCs into models in the game's content directory, at the path $modelname gives.
+pub trait Compiler {
+    fn name(&self) -> &str;
+
+    /// If the compiler creates a model, otherwise there's nothing to collect after compiling.
+    fn creates_model(&self) -> bool {
+        true
+    }
+
+    fn compile(&self, log: &Logger, qc: &Path, game_dir: &Path, model_name: &str)
+        -> Result<(), Error>;
+}
+
+/// Creates the compiler selected in SoTo.Local.toml.
+pub fn from_local(local: &SotoLocalFile) -> Result<Box<Compiler>, Error> {
+    let settings = local.compiler.clone().unwrap_or_default();
+    let backend = settings.backend.as_deref().unwrap_or("studiomdl");
+
+    let compiler: Box<Compiler> = match backend {
+        "studiomdl" => Box::new(Studiomdl {
+            studiomdl: settings.studiomdl.unwrap_or_else(|| {
+                local.game.bin.join(format!("studiomdl{}", env::consts::EXE_SUFFIX))
+            }),
+        }),
+        "wine" => Box::new(Wine {
+            wine: settings.wine.unwrap_or_else(|| "wine".into()),
+            prefix: settings.wine_prefix,
+            studiomdl: settings.studiomdl.unwrap_or_else(|| local.game.bin.join("studiomdl.exe")),
+        }),
+        "qc-only" => Box::new(QcOnly),
+        "mock" => Box::new(Mock),
+        _ => return Err(Error::Runner(TaskError::new(TaskErrorKind::InvalidTask, format!(
+            "Unknown compiler backend \"{}\", expected \"studiomdl\", \"wine\", \"qc-only\" or \
+             \"mock\"",
+            backend
+        )))),
+    };
+
+    Ok(compiler)
+}
+
+/// Runs studiomdl directly.
+pub struct Studiomdl {
+    pub studiomdl: PathBuf,
+}
+
+impl Compiler for Studiomdl {
+    fn name(&self) -> &str {
+        "studiomdl"
+    }
+
+    fn compile(&self, log: &Logger, qc: &Path, game_dir: &Path, _model_name: &str)
+        -> Result<(), Error> {
+        find_studiomdl(log, &self.studiomdl)?;
+
+        let mut command = Command::new(&self.studiomdl);
+        command.arg("-game").arg(game_dir).arg(qc);
+        run_studiomdl(log, command, qc)
+    }
+}
+
+/// Runs the Windows version of studiomdl through Wine.
+pub struct Wine {
+    pub wine: PathBuf,
+    /// The Wine prefix to run in, instead of Wine's default.
+    pub prefix: Option<PathBuf>,
+    pub studiomdl: PathBuf,
+}
+
+impl Compiler for Wine {
+    fn name(&self) -> &str {
+        "wine"
+    }
+
+    fn compile(&self, log: &Logger, qc: &Path, game_dir: &Path, _model_name: &str)
+        -> Result<(), Error> {
+        find_studiomdl(log, &self.studiomdl)?;
+
+        let mut command = Command::new(&self.wine);
+        if let Some(ref prefix) = self.prefix {
+            command.env("WINEPREFIX", prefix);
+        }
+        command.arg(&self.studiomdl)
+            .arg("-game").arg(windows_path(game_dir))
+            .arg(windows_path(qc));
+        run_studiomdl(log, command, qc)
+    }
+}
+
+/// Doesn't compile anything, for when only the QC and SMDs are needed.
+pub struct QcOnly;
+
+impl Compiler for QcOnly {
+    fn name(&self) -> &str {
+        "qc-only"
+    }
+
+    fn creates_model(&self) -> bool {
+        false
+    }
+
+    fn compile(&self, log: &Logger, qc: &Path, _game_dir: &Path, _model_name: &str)
+        -> Result<(), Error> {
+        info!(log, "Not compiling \"{}\", the compiler is set to QC only", qc.display());
+        Ok(())
+    }
+}
+
+/// Creates placeholder files where studiomdl would put the model, for testing everything around
+/// studiomdl without having it.
+pub struct Mock;
+
+impl Compiler for Mock {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn compile(&self, log: &Logger, qc: &Path, game_dir: &Path, model_name: &str)
+        -> Result<(), Error> {
+        for extension in &["mdl", "vvd", "dx90.vtx", "phy"] {
+            let file = output::output_file(game_dir, model_name, extension);
+            debug!(log, "Creating placeholder \"{}\"", file.display());
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            writeln!(File::create(&file)?, "Mock compiled from \"{}\"", qc.display())?;
+        }
+        Ok(())
+    }
+}
+
+fn find_studiomdl(log: &Logger, studiomdl: &Path) -> Result<(), Error> {
+    if studiomdl.exists() {
+        debug!(log, "Found studiomdl at \"{}\"", studiomdl.display());
+        Ok(())
+    } else {
+        Err(Error::Runner(TaskError::new(
+            TaskErrorKind::InvalidTask,
+            format!("Unable to find studiomdl at \"{}\"", studiomdl.display())
+        )))
+    }
+}
+
+fn run_studiomdl(log: &Logger, mut command: Command, qc: &Path) -> Result<(), Error> {
+    debug!(log, "Running {:?}", command);
+    let output = command.output()?;
+
+    // Make sure it completed successfully
+    if !output.status.success() {
+        return Err(Error::Runner(TaskError::new(TaskErrorKind::Tool, format!(
+            "Error during studiomdl compilation.\nStdout:\n{}\nStderr:\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )).with_file(qc.to_path_buf())));
+    }
+
+    Ok(())
+}
+
+/// Turns an absolute path into the path Windows programs running in Wine see it at. Wine's Z:
+/// drive is the root of the file system by default.
+fn windows_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path.starts_with('/') {
+        format!("Z:{}", path.replace('/', "\\"))
+    } else {
+        path.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use soto::{SotoLocalFile, SotoLocalFileCompiler, SotoLocalFileGame};
+    use super::{from_local, windows_path};
+
+    fn local(backend: Option<&str>) -> SotoLocalFile {
+        SotoLocalFile {
+            game: SotoLocalFileGame {
+                bin: "game/bin".into(),
+                content: "game/tf".into(),
+                install: None,
+            },
+            compiler: backend.map(|b| SotoLocalFileCompiler {
+                backend: Some(b.into()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn it_selects_compiler_backends() {
+        assert!(from_local(&local(None)).unwrap().name() == "studiomdl");
+        assert!(from_local(&local(Some("wine"))).unwrap().name() == "wine");
+        assert!(!from_local(&local(Some("qc-only"))).unwrap().creates_model());
+        assert!(from_local(&local(Some("mock"))).unwrap().name() == "mock");
+        assert!(from_local(&local(Some("hammer"))).is_err());
+    }
+
+    #[test]
+    fn it_converts_paths_for_wine() {
+        assert!(windows_path(Path::new("/home/me/tf")) == "Z:\\home\\me\\tf");
+        assert!(windows_path(Path::new("C:\\tf")) == "C:\\tf");
+    }
+}
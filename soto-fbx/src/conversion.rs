@@ -0,0 +1,81 @@
+use cgmath::{Matrix, Matrix3, Matrix4, SquareMatrix, Vector3};
+use soto::Error;
+use sotolib_fbx::simple::{GlobalSettings, SignedAxis};
+
+use task::Model;
+
+/// Centimeters in an inch, FBX unit scales are in centimeters and Source uses inches.
+const CENTIMETERS_PER_INCH: f32 = 2.54;
+
+/// Converts from the axis system and units of an FBX file to the Z-up inch space of SMD files.
+/// Models face -Y in SMD space, which studiomdl turns into +X in the engine.
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    pub rotation: Matrix3<f32>,
+    pub scale: f32,
+}
+
+impl Conversion {
+    /// Creates a conversion from the file's global settings, with the task's overrides applied.
+    pub fn new(settings: &GlobalSettings, model: &Model) -> Result<Self, Error> {
+        // Overriding the axes means we also need to find the matching right axis
+        let mut up = settings.up_axis;
+        let mut front = settings.front_axis;
+        let mut coord = settings.coord_axis;
+        if model.up_axis.is_some() || model.front_axis.is_some() {
+            if let Some(ref value) = model.up_axis {
+                up = parse_axis(value)?;
+            }
+            if let Some(ref value) = model.front_axis {
+                front = parse_axis(value)?;
+            }
+            coord = cross(up, front)
+                .ok_or_else(|| Error::Task("Up axis and front axis can't be the same axis".into()))?;
+        }
+
+        // The columns of the file's axis system are the directions in file space, we want those
+        // directions to end up pointing right (+X), up (+Z) and to the front (-Y)
+        let file_axes = Matrix3::from_cols(
+            axis_vector(coord), axis_vector(up), axis_vector(front),
+        );
+        if file_axes.determinant() <= 0.0 {
+            return Err(Error::Task(
+                "The FBX uses a left-handed or invalid axis system, which is not supported".into()
+            ));
+        }
+        let smd_axes = Matrix3::from_cols(
+            Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, -1.0, 0.0),
+        );
+
+        let scale = model.scale
+            .unwrap_or(settings.unit_scale_factor as f32 / CENTIMETERS_PER_INCH);
+
+        Ok(Conversion {
+            // The file's axes are orthonormal, so the transpose is the inverse
+            rotation: smd_axes * file_axes.transpose(),
+            scale,
+        })
+    }
+
+    /// Gets the full conversion, including the scale.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from(self.rotation) * Matrix4::from_scale(self.scale)
+    }
+}
+
+fn parse_axis(value: &str) -> Result<SignedAxis, Error> {
+    SignedAxis::parse(value).ok_or_else(|| Error::Task(format!(
+        "Invalid axis \"{}\", expected X, Y or Z with an optional sign", value
+    )))
+}
+
+fn axis_vector(axis: SignedAxis) -> Vector3<f32> {
+    axis.to_vector().into()
+}
+
+/// Finds the axis perpendicular to both axes in a right-handed system, if they're not parallel.
+fn cross(a: SignedAxis, b: SignedAxis) -> Option<SignedAxis> {
+    let result = axis_vector(a).cross(axis_vector(b));
+    (0..3).find(|i| result[*i] != 0.0)
+        .map(|i| SignedAxis::new(i, result[i] as i32))
+}
@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+use std::f64;
+
+use cgmath::{InnerSpace, Vector3};
+
+pub type Point = Vector3<f64>;
+
+/// A convex hull, made of outward facing triangles referring to its points.
+#[derive(Debug, Clone)]
+pub struct Hull {
+    pub points: Vec<Point>,
+    pub triangles: Vec<[usize; 3]>,
+    /// Plane of every triangle, as its normal and the distance of the plane from the origin.
+    planes: Vec<(Point, f64)>,
+}
+
+impl Hull {
+    /// Checks if a point is inside the hull, or at most `tolerance` outside of it.
+    pub fn contains(&self, point: Point, tolerance: f64) -> bool {
+        self.planes.iter().all(|&(normal, offset)| normal.dot(point) - offset <= tolerance)
+    }
+
+    /// The normal of a triangle, pointing out of the hull.
+    pub fn normal(&self, triangle: usize) -> Point {
+        self.planes[triangle].0
+    }
+}
+
+struct Face {
+    vertices: [usize; 3],
+    normal: Point,
+    offset: f64,
+    /// The points in front of this face, that still need to be added to the hull.
+    outside: Vec<usize>,
+}
+
+impl Face {
+    fn new(points: &[Point], vertices: [usize; 3]) -> Self {
+        let [a, b, c] = vertices;
+        let normal = (points[b] - points[a]).cross(points[c] - points[a]);
+        let length = normal.magnitude();
+        let normal = if length > 0.0 { normal / length } else { normal };
+
+        Face {
+            vertices,
+            normal,
+            offset: normal.dot(points[a]),
+            outside: Vec::new(),
+        }
+    }
+
+    fn distance(&self, point: Point) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+}
+
+/// Finds the convex hull of points using quickhull. The points furthest out are added first, so
+/// if the hull would have more than `max_vertices` vertices, the hull of the most important
+/// points is returned. Returns None if the points are all on the same plane.
+pub fn convex_hull(points: &[Point], max_vertices: usize) -> Option<Hull> {
+    let epsilon = tolerance(points);
+    let simplex = initial_simplex(points, epsilon)?;
+
+    // Start out with a tetrahedron, with all faces pointing away from its center
+    let center = simplex.iter().fold(Point::new(0.0, 0.0, 0.0), |c, i| c + points[*i]) / 4.0;
+    let mut faces = Vec::new();
+    for &(a, b, c) in &[(0, 1, 2), (0, 1, 3), (0, 2, 3), (1, 2, 3)] {
+        let mut face = Face::new(points, [simplex[a], simplex[b], simplex[c]]);
+        if face.distance(center) > 0.0 {
+            face = Face::new(points, [simplex[a], simplex[c], simplex[b]]);
+        }
+        faces.push(Some(face));
+    }
+    let candidates: Vec<_> = (0..points.len()).filter(|i| !simplex.contains(i)).collect();
+    assign_outside(points, &mut faces, 0, candidates, epsilon);
+
+    let mut vertex_count = 4;
+    while vertex_count < max_vertices {
+        // Add the point furthest out of the hull
+        let furthest = faces.iter()
+            .filter_map(|f| f.as_ref())
+            .flat_map(|f| f.outside.iter().map(move |p| (*p, f.distance(points[*p]))))
+            .fold(None, |best: Option<(usize, f64)>, (p, d)| match best {
+                Some((_, best_distance)) if best_distance >= d => best,
+                _ => Some((p, d)),
+            });
+        let point = match furthest {
+            Some((point, _)) => point,
+            None => break,
+        };
+
+        // Remove all faces the point can see, the edges around them form the horizon
+        let mut edges = HashSet::new();
+        let mut orphans = Vec::new();
+        for slot in &mut faces {
+            let visible = slot.as_ref().map(|f| f.distance(points[point]) > epsilon);
+            if visible == Some(true) {
+                let face = slot.take().unwrap();
+                let [a, b, c] = face.vertices;
+                edges.insert((a, b));
+                edges.insert((b, c));
+                edges.insert((c, a));
+                orphans.extend(face.outside.into_iter().filter(|p| *p != point));
+            }
+        }
+
+        // Connect the point to the horizon, the orphaned points may be outside of the new faces
+        let first_new = faces.len();
+        for &(a, b) in &edges {
+            if !edges.contains(&(b, a)) {
+                faces.push(Some(Face::new(points, [a, b, point])));
+            }
+        }
+        assign_outside(points, &mut faces, first_new, orphans, epsilon);
+        vertex_count += 1;
+    }
+
+    // Only keep the points the hull actually uses
+    let mut mapping = vec!(None; points.len());
+    let mut hull = Hull {
+        points: Vec::new(),
+        triangles: Vec::new(),
+        planes: Vec::new(),
+    };
+    for face in faces.into_iter().flatten() {
+        let mut triangle = [0; 3];
+        for (i, vertex) in face.vertices.iter().enumerate() {
+            triangle[i] = *mapping[*vertex].get_or_insert_with(|| {
+                hull.points.push(points[*vertex]);
+                hull.points.len() - 1
+            });
+        }
+        hull.triangles.push(triangle);
+        hull.planes.push((face.normal, face.offset));
+    }
+
+    Some(hull)
+}
+
+/// Gives every point to the first face from `first` on that it's in front of, points that aren't
+/// in front of any face are inside the hull.
+fn assign_outside(
+    points: &[Point], faces: &mut [Option<Face>], first: usize, candidates: Vec<usize>,
+    epsilon: f64,
+) {
+    for point in candidates {
+        let face = faces[first..].iter_mut()
+            .filter_map(|f| f.as_mut())
+            .find(|f| f.distance(points[point]) > epsilon);
+        if let Some(face) = face {
+            face.outside.push(point);
+        }
+    }
+}
+
+/// How far off a point can be and still count as being on a plane. Points come from 32-bit
+/// floats, so this is a lot more forgiving than the precision we calculate with.
+fn tolerance(points: &[Point]) -> f64 {
+    let scale = points.iter()
+        .map(|p| p.x.abs() + p.y.abs() + p.z.abs())
+        .fold(0.0, f64::max);
+    scale.max(1.0) * 1e-6
+}
+
+/// Finds four points that form a tetrahedron as large as reasonably possible.
+fn initial_simplex(points: &[Point], epsilon: f64) -> Option<[usize; 4]> {
+    if points.len() < 4 {
+        return None;
+    }
+
+    // The two extreme points furthest apart
+    let mut extremes = Vec::new();
+    for axis in 0..3 {
+        let value = |i: &usize| points[*i][axis];
+        let indices = 0..points.len();
+        extremes.push(indices.clone().min_by(|a, b| value(a).total_cmp(&value(b)))?);
+        extremes.push(indices.max_by(|a, b| value(a).total_cmp(&value(b)))?);
+    }
+    let mut pairs = Vec::new();
+    for a in &extremes {
+        for b in &extremes {
+            pairs.push((*a, *b, (points[*a] - points[*b]).magnitude()));
+        }
+    }
+    let (a, b, length) = furthest(pairs.into_iter(), |&(_, _, d)| d)?;
+    if length <= epsilon {
+        return None;
+    }
+
+    // The point furthest from the line between them
+    let direction = (points[b] - points[a]) / length;
+    let line_distance = |p: &usize| {
+        let offset = points[*p] - points[a];
+        (offset - direction * offset.dot(direction)).magnitude()
+    };
+    let c = furthest(0..points.len(), line_distance)?;
+    if line_distance(&c) <= epsilon {
+        return None;
+    }
+
+    // The point furthest from the plane through all three
+    let face = Face::new(points, [a, b, c]);
+    let plane_distance = |p: &usize| face.distance(points[*p]).abs();
+    let d = furthest(0..points.len(), plane_distance)?;
+    if plane_distance(&d) <= epsilon {
+        return None;
+    }
+
+    Some([a, b, c, d])
+}
+
+fn furthest<T, I: Iterator<Item = T>, F: Fn(&T) -> f64>(values: I, distance: F) -> Option<T> {
+    values.max_by(|a, b| distance(a).total_cmp(&distance(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convex_hull, Point};
+
+    fn cube() -> Vec<Point> {
+        let mut points = Vec::new();
+        for i in 0..8 {
+            let corner = |bit| if i & bit != 0 { 1.0 } else { -1.0 };
+            points.push(Point::new(corner(1), corner(2), corner(4)));
+        }
+        points
+    }
+
+    #[test]
+    fn it_finds_the_hull_of_a_cube() {
+        // Points inside the cube and on its faces shouldn't end up in the hull
+        let mut points = cube();
+        points.push(Point::new(0.0, 0.0, 0.0));
+        points.push(Point::new(0.5, -0.25, 0.75));
+        points.push(Point::new(1.0, 0.0, 0.0));
+
+        let hull = convex_hull(&points, 100).unwrap();
+
+        assert!(hull.points.len() == 8);
+        assert!(hull.triangles.len() == 12);
+        assert!(hull.contains(Point::new(0.9, 0.9, -0.9), 0.0));
+        assert!(!hull.contains(Point::new(1.1, 0.0, 0.0), 0.0));
+    }
+
+    #[test]
+    fn it_limits_hull_vertices() {
+        let mut points = Vec::new();
+        for i in 0..20 {
+            for j in 1..10 {
+                let (theta, phi) = (i as f64 * 0.314, j as f64 * 0.314);
+                let (x, y) = (phi.sin() * theta.cos(), phi.sin() * theta.sin());
+                points.push(Point::new(x, y, phi.cos()));
+            }
+        }
+        points.push(Point::new(0.0, 0.0, 1.0));
+        points.push(Point::new(0.0, 0.0, -1.0));
+
+        let hull = convex_hull(&points, 12).unwrap();
+
+        assert!(hull.points.len() == 12);
+        assert!(points.iter().filter(|p| hull.contains(**p, 1e-9)).count() < points.len());
+    }
+
+    #[test]
+    fn it_rejects_flat_points() {
+        let points: Vec<_> = cube().into_iter().map(|p| Point::new(p.x, p.y, 0.0)).collect();
+        assert!(convex_hull(&points, 100).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+extern crate cgmath;
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate slog;
+extern crate soto;
+extern crate sotolib_fbx;
+extern crate sotolib_smd;
+#[cfg(test)] extern crate toml;
+
+mod compiler;
+mod conversion;
+mod hull;
+mod output;
+mod physics;
+mod qc;
+mod smd;
+mod task;
+mod transform;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::PathBuf;
+
+use slog::Logger;
+use soto::task::{
+    log_progress, Artifact, RunnerInfo, TaskError, TaskErrorKind, TaskParameters, TaskRunner,
+    CAPABILITY_ARTIFACTS,
+};
+use soto::{Error};
+use sotolib_smd::{SmdExportExt};
+
+use qc::QcSmds;
+use task::SotoFbxTask;
+
+/// Runs soto-fbx tasks, turning FBX files into Source models. Can be registered as an in-process
+/// runner, the soto-fbx executable wraps this.
+pub struct SotoFbxRunner;
+
+impl TaskRunner for SotoFbxRunner {
+    fn info(&self) -> RunnerInfo {
+        RunnerInfo::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+            .with_capability(CAPABILITY_ARTIFACTS)
+    }
+
+    fn run(&self, log: &Logger, params: TaskParameters) -> Result<Vec<Artifact>, TaskError> {
+        Ok(task_main(log, params)?)
+    }
+}
+
+/// Builds a soto-fbx task, returns the compiled model's files in the target directory.
+pub fn task_main(log: &Logger, params: TaskParameters) -> Result<Vec<Artifact>, Error> {
+    // First, read in the toml we got told to read, paths in it are relative to the project
+    let mut toml: SotoFbxTask = soto::read_toml(&params.target_toml)?;
+    toml.resolve_paths(&params.project_dir);
+    toml.validate()?;
+    let compiler = compiler::from_local(&params.local)?;
+
+    // Every SMD is a step, and so are generating and compiling the QC
+    let meshes: Vec<PathBuf> = toml.meshes().into_iter().cloned().collect();
+    let sequence_count = toml.sequences.as_ref().map(|s| s.len()).unwrap_or(0) as u32;
+    let total_steps = sequence_count + meshes.len() as u32 + 4;
+    let mut step = 0;
+
+    // Generate the reference SDM
+    log_progress(log, step, total_steps, "Generating reference SMD");
+    let visual_filter = toml.collision.visual_filter();
+    let reference_smd = smd::create_reference_smd(
+        log, &toml.model, &toml.materials, visual_filter
+    )?;
+
+    // Export the reference SMD
+    let mut reference_smd_file = params.working_dir.clone();
+    reference_smd_file.push("reference.smd");
+    let export_file = File::create(reference_smd_file)?;
+    reference_smd.export(export_file).unwrap();
+
+    // Generate the SMDs for the other meshes the QC uses, like body group options and LODs
+    let mut mesh_smds = Vec::new();
+    let mut smds = QcSmds {
+        reference: "reference.smd".into(),
+        collision: "phys.smd".into(),
+        meshes: HashMap::new(),
+    };
+    for (index, mesh) in meshes.iter().enumerate() {
+        step += 1;
+        log_progress(log, step, total_steps, &format!("Generating mesh \"{}\"", mesh.display()));
+
+        let mut model = toml.model.clone();
+        model.reference = mesh.clone();
+        let smd = smd::create_reference_smd(log, &model, &toml.materials, visual_filter)?;
+
+        let name = format!("mesh_{}.smd", index);
+        smd.export(File::create(params.working_dir.join(&name))?).unwrap();
+        smds.meshes.insert(mesh.clone(), name);
+        mesh_smds.push(smd);
+    }
+
+    // Generate the collision mesh, either from its own file or from the reference's
+    step += 1;
+    log_progress(log, step, total_steps, "Generating collision mesh");
+    let mut collision_model = toml.model.clone();
+    if let Some(ref file) = toml.collision.file {
+        collision_model.reference = file.clone();
+    }
+    let mut collision_smd = smd::create_reference_smd(
+        log, &collision_model, &toml.materials, toml.collision.filter()
+    )?;
+    if collision_smd.triangles.is_empty() {
+        return Err(Error::Runner(
+            TaskError::new(TaskErrorKind::Input, "No collision meshes found in FBX")
+                .with_file(collision_model.reference)
+        ));
+    }
+
+    // Make sure studiomdl will accept the collision mesh, so problems show up before compiling
+    let in_collision_file = |error| match error {
+        Error::Runner(error) => Error::Runner(error.with_file(collision_model.reference.clone())),
+        error => error,
+    };
+    let limits = match toml.collision.decomposition() {
+        Some(decomposition) => {
+            let (decomposed, pieces) = physics::decompose(&collision_smd, &decomposition)
+                .map_err(&in_collision_file)?;
+            debug!(log, "Decomposed collision mesh into {} convex pieces", pieces);
+            collision_smd = decomposed;
+            decomposition.limits
+        },
+        None => toml.collision.limits(),
+    };
+    physics::check_limits(&collision_smd, &limits).map_err(&in_collision_file)?;
+    collision_smd.export(File::create(params.working_dir.join(&smds.collision))?).unwrap();
+
+    // The QC can only refer to bones the meshes have
+    let bones: HashSet<_> = mesh_smds.iter()
+        .chain(Some(&reference_smd))
+        .flat_map(|s| s.bones.iter())
+        .map(|b| b.name.as_str())
+        .collect();
+    toml.check_bones(&bones)?;
+
+    // Generate the animation SDMs, keeping track of the frame rates for the QC
+    let mut frame_rates = HashMap::new();
+    for sequence in toml.sequences.as_ref().unwrap_or(&::std::collections::BTreeMap::new()) {
+        step += 1;
+        log_progress(log, step, total_steps, &format!("Generating animation \"{}\"", sequence.0));
+
+        // Generate the SMD
+        let (animation_smd, frame_rate) = smd::create_animation_smd(
+            log, &reference_smd, sequence.1, &toml.model
+        )?;
+        frame_rates.insert(sequence.0.clone(), frame_rate);
+
+        // Export the SMD
+        let mut animation_smd_file = params.working_dir.clone();
+        animation_smd_file.push(format!("animation_{}.smd", sequence.0));
+        let export_file = File::create(animation_smd_file)?;
+        animation_smd.export(export_file).unwrap();
+    }
+
+    // Generate the QC
+    step += 1;
+    log_progress(log, step, total_steps, "Generating QC");
+    let mut target_qc = params.working_dir.clone();
+    target_qc.push("script.qc");
+    qc::generate_qc(&target_qc, &toml, &smds, &frame_rates)?;
+
+    // Finally, run the model build, studiomdl puts the model in the game so we have to collect it
+    // from there afterwards
+    step += 1;
+    log_progress(log, step, total_steps, &format!("Compiling model using {}", compiler.name()));
+    let game_dir = &params.local.game.content;
+    let artifacts = if compiler.creates_model() {
+        // The model the game already has is only replaced if compiling succeeds
+        let backup = output::OutputBackup::create(
+            log, game_dir, &toml.prop.name, &params.working_dir.join("previous")
+        )?;
+        let result = compiler.compile(log, &target_qc, game_dir, &toml.prop.name)
+            .and_then(|_| output::collect_outputs(log, &params, &toml.prop.name));
+        match result {
+            Ok(artifacts) => {
+                backup.discard()?;
+                artifacts
+            },
+            Err(error) => {
+                backup.restore(log)?;
+                return Err(error);
+            },
+        }
+    } else {
+        compiler.compile(log, &target_qc, game_dir, &toml.prop.name)?;
+        Vec::new()
+    };
+    log_progress(log, total_steps, total_steps, "Done");
+
+    Ok(artifacts)
+}
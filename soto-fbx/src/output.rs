@@ -0,0 +1,235 @@
+//! Collecting the files studiomdl compiles. Studiomdl always writes them into the game's content
+//! directory, at the path the QC's $modelname gives.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use slog::Logger;
+use soto::task::{Artifact, TaskError, TaskErrorKind, TaskParameters};
+use soto::Error;
+
+/// Extensions of the files studiomdl can compile a model into, along with their kind of artifact.
+/// Which of these get created depends on the model and on the game.
+const OUTPUTS: &[(&str, &str)] = &[
+    ("mdl", "mdl"),
+    ("vvd", "vvd"),
+    ("phy", "phy"),
+    ("ani", "ani"),
+    ("dx90.vtx", "vtx"),
+    ("dx80.vtx", "vtx"),
+    ("sw.vtx", "vtx"),
+    ("vtx", "vtx"),
+];
+
+/// The path of a model's files in a content directory, without an extension.
+pub fn model_path(name: &str) -> PathBuf {
+    Path::new("models").join(name.replace('\\', "/"))
+}
+
+/// The path of one of a model's files in a content directory.
+pub fn output_file(directory: &Path, name: &str, extension: &str) -> PathBuf {
+    let path = directory.join(model_path(name));
+    let file_name = format!(
+        "{}.{}", path.file_name().unwrap_or_default().to_string_lossy(), extension
+    );
+    path.with_file_name(file_name)
+}
+
+/// The directory compiled models are collected in, mirroring the game's content directory.
+pub fn dist_dir(params: &TaskParameters) -> PathBuf {
+    params.project_dir.join("target").join("dist")
+}
+
+/// Removes a model's compiled files from a content directory. Files left behind by an earlier
+/// compile would otherwise end up with the new model, like a .phy after removing collision.
+pub fn remove_outputs(log: &Logger, directory: &Path, name: &str) -> Result<(), Error> {
+    for &(extension, _) in OUTPUTS {
+        let file = output_file(directory, name, extension);
+        if file.exists() {
+            debug!(log, "Removing old \"{}\"", file.display());
+            fs::remove_file(&file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the files studiomdl compiled out of the game into the dist directory, or copies them
+/// if they should stay installed in the game, replacing the ones collected before. Returns the
+/// files in the dist directory.
+pub fn collect_outputs(
+    log: &Logger, params: &TaskParameters, name: &str
+) -> Result<Vec<Artifact>, Error> {
+    let game_dir = &params.local.game.content;
+    let dist_dir = dist_dir(params);
+    let install = params.local.game.install.unwrap_or(false);
+
+    let mdl = output_file(game_dir, name, "mdl");
+    if !mdl.exists() {
+        return Err(Error::Runner(TaskError::new(
+            TaskErrorKind::Tool, "Studiomdl finished without creating the model"
+        ).with_file(mdl)));
+    }
+    remove_outputs(log, &dist_dir, name)?;
+
+    let mut artifacts = Vec::new();
+    for &(extension, kind) in OUTPUTS {
+        let source = output_file(game_dir, name, extension);
+        if !source.exists() {
+            continue;
+        }
+
+        let destination = output_file(&dist_dir, name, extension);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if install {
+            debug!(log, "Copying \"{}\" to \"{}\"", source.display(), destination.display());
+            fs::copy(&source, &destination)?;
+        } else {
+            debug!(log, "Moving \"{}\" to \"{}\"", source.display(), destination.display());
+            move_file(&source, &destination)?;
+        }
+
+        artifacts.push(Artifact {
+            path: destination,
+            kind: Some(kind.into()),
+        });
+    }
+
+    if install {
+        info!(log, "Installed model in \"{}\"", game_dir.display());
+    }
+
+    Ok(artifacts)
+}
+
+/// A model's compiled files, moved out of a content directory while the model is compiled again.
+/// If compiling fails they're put back, so the game keeps the model it had.
+pub struct OutputBackup {
+    directory: PathBuf,
+    name: String,
+    /// The moved files, along with where they were moved from.
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl OutputBackup {
+    /// Moves a model's compiled files from a content directory into a backup directory.
+    pub fn create(
+        log: &Logger, directory: &Path, name: &str, backup_dir: &Path
+    ) -> Result<Self, Error> {
+        remove_outputs(log, backup_dir, name)?;
+
+        let mut backup = OutputBackup {
+            directory: directory.to_path_buf(),
+            name: name.into(),
+            files: Vec::new(),
+        };
+        for &(extension, _) in OUTPUTS {
+            let file = output_file(directory, name, extension);
+            if !file.exists() {
+                continue;
+            }
+
+            let backup_file = output_file(backup_dir, name, extension);
+            debug!(log, "Moving old \"{}\" to \"{}\"", file.display(), backup_file.display());
+            let moved = backup_file.parent().map(fs::create_dir_all).unwrap_or(Ok(()))
+                .and_then(|_| move_file(&file, &backup_file));
+            if let Err(error) = moved {
+                backup.restore(log)?;
+                return Err(error.into());
+            }
+            backup.files.push((backup_file, file));
+        }
+
+        Ok(backup)
+    }
+
+    /// Puts the files back, replacing anything compiled in the meantime.
+    pub fn restore(self, log: &Logger) -> Result<(), Error> {
+        remove_outputs(log, &self.directory, &self.name)?;
+        for (backup_file, file) in self.files {
+            debug!(log, "Restoring \"{}\"", file.display());
+            move_file(&backup_file, &file)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the backed up files, once they've been replaced successfully.
+    pub fn discard(self) -> Result<(), Error> {
+        for (backup_file, _) in self.files {
+            fs::remove_file(&backup_file)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renames a file, or copies it if it's moved to another file system.
+fn move_file(source: &Path, destination: &Path) -> io::Result<()> {
+    if fs::rename(source, destination).is_err() {
+        fs::copy(source, destination)?;
+        fs::remove_file(source)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use slog::{Logger, Discard};
+    use super::{output_file, OutputBackup};
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        let mut text = String::new();
+        File::open(path).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn it_finds_outputs_from_the_model_name() {
+        let file = output_file(Path::new("tf"), "props\\crate.v2", "dx90.vtx");
+        assert!(file == Path::new("tf/models/props/crate.v2.dx90.vtx"));
+    }
+
+    #[test]
+    fn it_restores_outputs_if_compiling_fails() {
+        let log = Logger::root(Discard, o!());
+        let directory = env::temp_dir().join("soto-fbx-test-output-backup");
+        let _ = fs::remove_dir_all(&directory);
+        let game_dir = directory.join("game");
+        let backup_dir = directory.join("backup");
+        let mdl = output_file(&game_dir, "props/crate", "mdl");
+        let phy = output_file(&game_dir, "props/crate", "phy");
+        write(&mdl, "installed");
+        write(&phy, "installed");
+
+        // A failed compile may leave some of its files behind, those shouldn't stay
+        let backup = OutputBackup::create(&log, &game_dir, "props/crate", &backup_dir).unwrap();
+        assert!(!mdl.exists() && !phy.exists());
+        write(&mdl, "broken");
+        backup.restore(&log).unwrap();
+        assert!(read(&mdl) == "installed");
+        assert!(read(&phy) == "installed");
+
+        // After a successful compile only the new files are left
+        let backup = OutputBackup::create(&log, &game_dir, "props/crate", &backup_dir).unwrap();
+        write(&mdl, "compiled");
+        backup.discard().unwrap();
+        assert!(read(&mdl) == "compiled");
+        assert!(!phy.exists());
+        assert!(!output_file(&backup_dir, "props/crate", "mdl").exists());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,714 @@
+//! Convex decomposition and validation of collision meshes. Source's physics only works with
+//! convex pieces, studiomdl turns every connected part of a collision mesh into one, so concave
+//! parts end up as their convex hull. Decomposition splits collision meshes into convex pieces
+//! the way V-HACD does, by voxelizing the mesh and cutting it with planes until every piece is
+//! close enough to its convex hull.
+
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+use soto::task::{TaskError, TaskErrorKind};
+use soto::Error;
+use sotolib_smd::{BoneId, Smd, SmdTriangle, SmdVertex};
+
+use hull::{self, Hull, Point};
+
+/// The most convex pieces studiomdl accepts in a collision model, unless $maxconvexpieces raises
+/// the limit.
+pub const DEFAULT_MAX_CONVEX_PIECES: u32 = 20;
+
+/// Default for the most vertices a decomposed piece can have. This isn't a limit of studiomdl,
+/// but pieces with a lot of vertices make for slow and unreliable physics so decomposed pieces are
+/// simplified down to this.
+pub const DEFAULT_MAX_HULL_VERTICES: u32 = 64;
+
+/// Default for how much of a piece's hull can be empty space before the piece gets split, as a
+/// fraction of the volume of the part it's in.
+pub const DEFAULT_MAX_CONCAVITY: f32 = 0.01;
+
+/// Default for the amount of voxels along the longest side of a part.
+pub const DEFAULT_RESOLUTION: u32 = 32;
+
+/// The material decomposed collision meshes use, studiomdl doesn't use it for anything.
+const MATERIAL: &str = "phys";
+
+/// How many cutting planes along every axis are tried when splitting a piece.
+const CUT_CANDIDATES: usize = 8;
+
+/// How far decomposed pieces are moved apart, in inches. Pieces that share vertex positions can
+/// end up welded together into one piece that isn't convex.
+const SEPARATION: f64 = 0.01;
+
+/// Limits a collision model has to stay within.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// If the collision mesh can be made of multiple convex pieces, otherwise studiomdl turns all
+    /// of it into one convex hull.
+    pub concave: bool,
+    pub max_pieces: usize,
+    /// The most vertices a convex piece can have, None if pieces can have any amount.
+    pub max_hull_vertices: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Decomposition {
+    pub limits: Limits,
+    pub max_concavity: f64,
+    pub resolution: usize,
+}
+
+/// Splits the collision mesh in an SMD into convex pieces, as a new SMD with the same bones along
+/// with the amount of pieces. Every connected part of the mesh gets at least one piece, parts are
+/// split further for as long as the limits allow it and the pieces aren't convex enough.
+pub fn decompose(smd: &Smd, settings: &Decomposition) -> Result<(Smd, usize), Error> {
+    check_positions(smd)?;
+    let mut parts = find_parts(smd);
+    if settings.limits.concave {
+        check_piece_count(parts.len(), settings.limits.max_pieces)?;
+    } else {
+        // Everything ends up in one piece, which only needs to be simplified
+        let triangles = parts.iter().flat_map(|p| p.triangles.iter().cloned()).collect();
+        let bone = parts.first().map(|p| p.bone).unwrap_or(0);
+        parts = vec!(Part { bone, triangles });
+    }
+
+    // Start out with one piece for every part
+    let mut voxels = Vec::new();
+    let mut pieces = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        let part_voxels = match Voxels::new(&part.triangles, settings.resolution) {
+            Some(v) => v,
+            None => return Err(invalid_mesh(format!(
+                "Collision part{} has all its vertices in the same position",
+                bone_location(smd, part.bone)
+            ))),
+        };
+        let all: Vec<_> = (0..part.triangles.len()).collect();
+        let (lo, hi) = ([0; 3], part_voxels.dimensions);
+        let piece = evaluate(part, &part_voxels, index, &all, lo, hi, settings);
+        pieces.extend(piece);
+        voxels.push((index, part_voxels));
+    }
+    let voxels: HashMap<_, _> = voxels.into_iter().collect();
+
+    // Keep splitting the piece with the most empty space in its hull
+    let mut unsplittable = Vec::new();
+    while settings.limits.concave && pieces.len() < settings.limits.max_pieces {
+        let worst = pieces.iter().enumerate()
+            .filter(|&(_, p)| p.concavity(&voxels[&p.part]) > settings.max_concavity)
+            .max_by(|a, b| {
+                let empty = |p: &Piece| p.empty as f64 * voxels[&p.part].volume();
+                empty(a.1).total_cmp(&empty(b.1))
+            })
+            .map(|(i, _)| i);
+        let piece = match worst {
+            Some(i) => pieces.swap_remove(i),
+            None => break,
+        };
+
+        let part = &parts[piece.part];
+        match split(part, &voxels[&piece.part], &piece, settings) {
+            Some((a, b)) => {
+                pieces.push(a);
+                pieces.push(b);
+            },
+            None => unsplittable.push(piece),
+        }
+    }
+    pieces.extend(unsplittable);
+
+    // Turn the pieces back into triangles, moved apart slightly so they stay separate
+    let mut decomposed = Smd::new();
+    decomposed.bones = smd.bones.clone();
+    decomposed.animation_frames = smd.animation_frames.clone();
+    for piece in &pieces {
+        let hull = &piece.hull;
+        let center = hull.points.iter().fold(Point::new(0.0, 0.0, 0.0), |c, p| c + p) /
+            hull.points.len() as f64;
+        let points: Vec<_> = hull.points.iter()
+            .map(|p| {
+                let inward = center - p;
+                if inward.magnitude() > SEPARATION * 2.0 {
+                    p + inward.normalize() * SEPARATION
+                } else {
+                    *p
+                }
+            })
+            .collect();
+
+        for (index, triangle) in hull.triangles.iter().enumerate() {
+            let normal = hull.normal(index);
+            let mut vertices: [SmdVertex; 3] = Default::default();
+            for (vertex, point) in vertices.iter_mut().zip(triangle.iter()) {
+                *vertex = SmdVertex {
+                    parent_bone: parts[piece.part].bone,
+                    position: to_array(points[*point]),
+                    normal: to_array(normal),
+                    uv: [0.0, 0.0],
+                    links: Vec::new(),
+                };
+            }
+            decomposed.triangles.push(SmdTriangle {
+                material: MATERIAL.into(),
+                vertices,
+            });
+        }
+    }
+
+    Ok((decomposed, pieces.len()))
+}
+
+/// Checks if studiomdl will accept the collision mesh in an SMD. Every connected part of a
+/// concave collision mesh becomes a convex piece, a convex collision mesh is one piece.
+pub fn check_limits(smd: &Smd, limits: &Limits) -> Result<(), Error> {
+    check_positions(smd)?;
+    let parts = find_parts(smd);
+    let pieces = if limits.concave {
+        check_piece_count(parts.len(), limits.max_pieces)?;
+        parts.iter().map(|p| p.points()).collect::<Vec<Vec<_>>>()
+    } else {
+        vec!(parts.iter().flat_map(|p| p.points()).collect())
+    };
+
+    let max_hull_vertices = match limits.max_hull_vertices {
+        Some(max_hull_vertices) => max_hull_vertices,
+        None => return Ok(()),
+    };
+    for (index, points) in pieces.iter().enumerate() {
+        let vertices = hull::convex_hull(points, usize::MAX).map(|h| h.points.len()).unwrap_or(0);
+        if vertices > max_hull_vertices {
+            let location = match parts.get(index) {
+                Some(part) if pieces.len() > 1 => bone_location(smd, part.bone),
+                _ => String::new(),
+            };
+            return Err(invalid_mesh(format!(
+                "Collision piece{} has {} vertices, more than the limit of {}, simplify it or \
+                 enable decompose",
+                location, vertices, max_hull_vertices
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_piece_count(pieces: usize, max_pieces: usize) -> Result<(), Error> {
+    if pieces > max_pieces {
+        return Err(invalid_mesh(format!(
+            "Collision mesh has {} separate parts, more than the limit of {} convex pieces",
+            pieces, max_pieces
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that every vertex in the collision mesh has a position that can be worked with.
+fn check_positions(smd: &Smd) -> Result<(), Error> {
+    let vertices = smd.triangles.iter().flat_map(|t| t.vertices.iter());
+    for vertex in vertices {
+        if !vertex.position.iter().all(|c| c.is_finite()) {
+            return Err(invalid_mesh(format!(
+                "Collision mesh has a vertex at {:?}, which isn't a valid position",
+                vertex.position
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Describes which bone a part of the collision mesh is on, for use in error messages.
+fn bone_location(smd: &Smd, bone: BoneId) -> String {
+    match smd.bones.iter().find(|b| b.id == bone) {
+        Some(bone) => format!(" on bone \"{}\"", bone.name),
+        None => String::new(),
+    }
+}
+
+fn invalid_mesh(message: String) -> Error {
+    Error::Runner(TaskError::new(TaskErrorKind::Input, message))
+}
+
+/// A connected part of a collision mesh, along with the bone it moves with.
+struct Part {
+    bone: BoneId,
+    triangles: Vec<[Point; 3]>,
+}
+
+impl Part {
+    fn points(&self) -> Vec<Point> {
+        self.triangles.iter().flat_map(|t| t.iter().cloned()).collect()
+    }
+}
+
+/// Splits up the triangles in an SMD by bone, and then by which triangles share vertices.
+fn find_parts(smd: &Smd) -> Vec<Part> {
+    // Vertices are shared if they're in the exact same position
+    let key = |v: &SmdVertex| {
+        let bone = v.links.first().map(|l| l.bone).unwrap_or(v.parent_bone);
+        (bone, v.position[0].to_bits(), v.position[1].to_bits(), v.position[2].to_bits())
+    };
+
+    let mut groups: Vec<usize> = (0..smd.triangles.len()).collect();
+    let mut owners = HashMap::new();
+    for (index, triangle) in smd.triangles.iter().enumerate() {
+        for vertex in &triangle.vertices {
+            let owner = *owners.entry(key(vertex)).or_insert(index);
+            let (a, b) = (root(&mut groups, owner), root(&mut groups, index));
+            groups[a] = b;
+        }
+    }
+
+    let mut parts: Vec<Part> = Vec::new();
+    let mut part_of_group = HashMap::new();
+    for (index, triangle) in smd.triangles.iter().enumerate() {
+        let group = root(&mut groups, index);
+        let part = *part_of_group.entry(group).or_insert_with(|| {
+            parts.push(Part {
+                bone: key(&triangle.vertices[0]).0,
+                triangles: Vec::new(),
+            });
+            parts.len() - 1
+        });
+
+        let point = |i: usize| {
+            let p = triangle.vertices[i].position;
+            Point::new(p[0] as f64, p[1] as f64, p[2] as f64)
+        };
+        parts[part].triangles.push([point(0), point(1), point(2)]);
+    }
+
+    parts
+}
+
+fn root(groups: &mut [usize], mut index: usize) -> usize {
+    while groups[index] != index {
+        groups[index] = groups[groups[index]];
+        index = groups[index];
+    }
+    index
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Voxel {
+    /// Not yet known to be inside or outside.
+    Empty,
+    Outside,
+    Surface,
+}
+
+/// A part turned into voxels, with a layer of empty voxels around it.
+struct Voxels {
+    origin: Point,
+    size: f64,
+    dimensions: [usize; 3],
+    voxels: Vec<Voxel>,
+    /// How many voxels are inside the part or on its surface.
+    filled: usize,
+}
+
+impl Voxels {
+    fn new(triangles: &[[Point; 3]], resolution: usize) -> Option<Self> {
+        let points = triangles.iter().flat_map(|t| t.iter());
+        let (min, max) = points.fold(
+            (Point::new(f64::MAX, f64::MAX, f64::MAX), Point::new(f64::MIN, f64::MIN, f64::MIN)),
+            |(min, max), p| (
+                Point::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                Point::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        );
+        let extent = max - min;
+        let longest = extent.x.max(extent.y).max(extent.z);
+        if longest <= 0.0 {
+            return None;
+        }
+
+        // The grid is offset by half a voxel, so faces at round numbers go through the middle of
+        // voxels instead of along their sides
+        let size = longest / resolution as f64;
+        let padding = Point::new(size, size, size) * 1.5;
+        let count = |e: f64| (e / size).ceil().max(1.0) as usize + 3;
+        let mut voxels = Voxels {
+            origin: min - padding,
+            size,
+            dimensions: [count(extent.x), count(extent.y), count(extent.z)],
+            voxels: Vec::new(),
+            filled: 0,
+        };
+        let [x, y, z] = voxels.dimensions;
+        voxels.voxels = vec!(Voxel::Empty; x * y * z);
+
+        // Mark every voxel a triangle goes through, by sampling the triangle densely enough
+        for triangle in triangles {
+            let [a, b, c] = *triangle;
+            let longest_edge = (b - a).magnitude()
+                .max((c - a).magnitude())
+                .max((c - b).magnitude());
+            let steps = (longest_edge / (size * 0.5)).ceil().max(1.0) as usize;
+            for i in 0..steps + 1 {
+                for j in 0..steps + 1 - i {
+                    let (u, v) = (i as f64 / steps as f64, j as f64 / steps as f64);
+                    let position = voxels.position_of(a + (b - a) * u + (c - a) * v);
+                    let index = voxels.index(position);
+                    voxels.voxels[index] = Voxel::Surface;
+                }
+            }
+        }
+
+        // Everything that can be reached from the padding without crossing the surface is outside,
+        // what's left is inside
+        let mut remaining = vec!([0, 0, 0]);
+        voxels.voxels[0] = Voxel::Outside;
+        while let Some(position) = remaining.pop() {
+            for axis in 0..3 {
+                for &forward in &[false, true] {
+                    let mut neighbor = position;
+                    if forward && neighbor[axis] + 1 < voxels.dimensions[axis] {
+                        neighbor[axis] += 1;
+                    } else if !forward && neighbor[axis] > 0 {
+                        neighbor[axis] -= 1;
+                    } else {
+                        continue;
+                    }
+
+                    let index = voxels.index(neighbor);
+                    if voxels.voxels[index] == Voxel::Empty {
+                        voxels.voxels[index] = Voxel::Outside;
+                        remaining.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        voxels.filled = voxels.voxels.iter().filter(|v| **v != Voxel::Outside).count();
+        Some(voxels)
+    }
+
+    fn position_of(&self, point: Point) -> [usize; 3] {
+        let relative = (point - self.origin) / self.size;
+        let clamp = |v: f64, axis: usize| (v.max(0.0) as usize).min(self.dimensions[axis] - 1);
+        [clamp(relative.x, 0), clamp(relative.y, 1), clamp(relative.z, 2)]
+    }
+
+    fn index(&self, position: [usize; 3]) -> usize {
+        let [x, y, z] = position;
+        (z * self.dimensions[1] + y) * self.dimensions[0] + x
+    }
+
+    fn center(&self, position: [usize; 3]) -> Point {
+        let coordinate = |axis: usize| position[axis] as f64 + 0.5;
+        self.origin + Point::new(coordinate(0), coordinate(1), coordinate(2)) * self.size
+    }
+
+    fn corner(&self, position: [usize; 3]) -> Point {
+        let coordinate = |axis: usize| position[axis] as f64;
+        self.origin + Point::new(coordinate(0), coordinate(1), coordinate(2)) * self.size
+    }
+
+    fn volume(&self) -> f64 {
+        self.size * self.size * self.size
+    }
+
+    /// All voxel positions in a box, from `lo` up to but not including `hi`.
+    fn positions(&self, lo: [usize; 3], hi: [usize; 3]) -> Vec<[usize; 3]> {
+        let mut positions = Vec::new();
+        for z in lo[2]..hi[2] {
+            for y in lo[1]..hi[1] {
+                for x in lo[0]..hi[0] {
+                    positions.push([x, y, z]);
+                }
+            }
+        }
+        positions
+    }
+}
+
+/// A convex piece of a part, covering a box of the part's voxels.
+struct Piece {
+    part: usize,
+    lo: [usize; 3],
+    hi: [usize; 3],
+    /// The part's triangles that touch the box.
+    triangles: Vec<usize>,
+    hull: Hull,
+    filled: usize,
+    /// Voxels inside the hull that aren't inside the part.
+    empty: usize,
+}
+
+impl Piece {
+    /// How much of the hull is empty space, relative to the volume of the whole part.
+    fn concavity(&self, voxels: &Voxels) -> f64 {
+        self.empty as f64 / voxels.filled.max(1) as f64
+    }
+}
+
+/// Creates the piece for a box of a part's voxels, None if there's nothing in it.
+fn evaluate(
+    part: &Part, voxels: &Voxels, index: usize, candidates: &[usize], lo: [usize; 3],
+    hi: [usize; 3], settings: &Decomposition,
+) -> Option<Piece> {
+    let positions = voxels.positions(lo, hi);
+    let filled: Vec<_> = positions.iter()
+        .filter(|p| voxels.voxels[voxels.index(**p)] != Voxel::Outside)
+        .cloned()
+        .collect();
+    if filled.is_empty() {
+        return None;
+    }
+
+    // The part of the mesh inside the box is what the hull has to cover
+    let (min, max) = (voxels.corner(lo), voxels.corner(hi));
+    let mut triangles = Vec::new();
+    let mut points = Vec::new();
+    for triangle in candidates {
+        let clipped = clip(&part.triangles[*triangle], min, max);
+        if !clipped.is_empty() {
+            triangles.push(*triangle);
+            points.extend(clipped);
+        }
+    }
+
+    // Flat pieces don't have a hull, so use the voxels instead to give them some thickness
+    let max_vertices = settings.limits.max_hull_vertices.unwrap_or(usize::MAX);
+    let hull = hull::convex_hull(&points, max_vertices).or_else(|| {
+        let corners: Vec<_> = filled.iter()
+            .flat_map(|p| {
+                (0..8).map(move |i| [p[0] + (i & 1), p[1] + (i >> 1 & 1), p[2] + (i >> 2)])
+            })
+            .map(|p| voxels.corner(p))
+            .collect();
+        hull::convex_hull(&corners, max_vertices)
+    })?;
+
+    let tolerance = voxels.size * 1e-6;
+    let empty = positions.iter()
+        .filter(|p| voxels.voxels[voxels.index(**p)] == Voxel::Outside)
+        .filter(|p| hull.contains(voxels.center(**p), tolerance))
+        .count();
+
+    Some(Piece {
+        part: index,
+        lo,
+        hi,
+        triangles,
+        hull,
+        filled: filled.len(),
+        empty,
+    })
+}
+
+/// Finds the plane that splits a piece into the two most convex pieces. A few planes along every
+/// axis are tried first, then every plane close to the best of those.
+fn split(
+    part: &Part, voxels: &Voxels, piece: &Piece, settings: &Decomposition,
+) -> Option<(Piece, Piece)> {
+    let cut = |axis: usize, cut: usize| {
+        let (mut first_hi, mut second_lo) = (piece.hi, piece.lo);
+        first_hi[axis] = cut;
+        second_lo[axis] = cut;
+
+        let triangles = &piece.triangles;
+        let first = evaluate(part, voxels, piece.part, triangles, piece.lo, first_hi, settings);
+        let second = evaluate(part, voxels, piece.part, triangles, second_lo, piece.hi, settings);
+
+        // Cutting off empty space doesn't make anything more convex
+        let (first, second) = (first?, second?);
+
+        // Prefer cutting pieces in half when cuts are equally good, so pieces stay splittable
+        let balance = (first.filled as f64 - second.filled as f64).abs() * 0.01;
+        let cost = (first.empty + second.empty) as f64 + balance;
+        Some(Cut { axis, position: cut, cost, first, second })
+    };
+    let better = |best: Option<Cut>, cut: Option<Cut>| match (best, cut) {
+        (Some(best), Some(cut)) => Some(if cut.cost < best.cost { cut } else { best }),
+        (best, cut) => best.or(cut),
+    };
+
+    let mut best = None;
+    for axis in 0..3 {
+        let (lo, hi) = (piece.lo[axis], piece.hi[axis]);
+        let step = ((hi - lo) / CUT_CANDIDATES).max(1);
+        for position in (lo + step..hi).step_by(step) {
+            best = better(best, cut(axis, position));
+        }
+    }
+
+    let coarse = best.as_ref().map(|b| (b.axis, b.position));
+    if let Some((axis, position)) = coarse {
+        let (lo, hi) = (piece.lo[axis], piece.hi[axis]);
+        let step = ((hi - lo) / CUT_CANDIDATES).max(1);
+        let start = position.saturating_sub(step - 1).max(lo + 1);
+        for fine in (start..(position + step).min(hi)).filter(|p| *p != position) {
+            best = better(best, cut(axis, fine));
+        }
+    }
+
+    best.map(|b| (b.first, b.second))
+}
+
+struct Cut {
+    axis: usize,
+    position: usize,
+    cost: f64,
+    first: Piece,
+    second: Piece,
+}
+
+/// Clips a triangle to a box, returning the corners of what's left of it.
+fn clip(triangle: &[Point; 3], min: Point, max: Point) -> Vec<Point> {
+    let mut polygon = triangle.to_vec();
+    for axis in 0..3 {
+        for &(limit, is_max) in &[(min[axis], false), (max[axis], true)] {
+            // Positive distances are inside the box
+            let distance = |p: &Point| if is_max { limit - p[axis] } else { p[axis] - limit };
+
+            let mut clipped = Vec::new();
+            for i in 0..polygon.len() {
+                let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+                let (da, db) = (distance(&a), distance(&b));
+                if da >= 0.0 {
+                    clipped.push(a);
+                }
+                if (da >= 0.0) != (db >= 0.0) {
+                    clipped.push(a + (b - a) * (da / (da - db)));
+                }
+            }
+            polygon = clipped;
+
+            if polygon.is_empty() {
+                return polygon;
+            }
+        }
+    }
+    polygon
+}
+
+fn to_array(point: Vector3<f64>) -> [f32; 3] {
+    [point.x as f32, point.y as f32, point.z as f32]
+}
+
+#[cfg(test)]
+mod tests {
+    use soto::task::TaskErrorKind;
+    use soto::Error;
+    use sotolib_smd::{Smd, SmdTriangle, SmdVertex};
+    use super::{check_limits, decompose, find_parts, Decomposition, Limits};
+
+    /// Adds the triangles of a closed box to an SMD.
+    fn add_box(smd: &mut Smd, min: [f32; 3], max: [f32; 3]) {
+        let corner = |i: usize| [
+            if i & 1 != 0 { max[0] } else { min[0] },
+            if i & 2 != 0 { max[1] } else { min[1] },
+            if i & 4 != 0 { max[2] } else { min[2] },
+        ];
+        let faces = [
+            [0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5],
+        ];
+        for face in &faces {
+            for triangle in &[[face[0], face[1], face[2]], [face[0], face[2], face[3]]] {
+                let mut vertices: [SmdVertex; 3] = Default::default();
+                for (vertex, corner_index) in vertices.iter_mut().zip(triangle.iter()) {
+                    vertex.position = corner(*corner_index);
+                }
+                smd.triangles.push(SmdTriangle {
+                    material: "phys".into(),
+                    vertices,
+                });
+            }
+        }
+    }
+
+    /// An L shaped mesh, made of two boxes that share an edge so they're one part.
+    fn l_shape() -> Smd {
+        let mut smd = Smd::new();
+        add_box(&mut smd, [0.0, 0.0, 0.0], [2.0, 1.0, 1.0]);
+        add_box(&mut smd, [0.0, 1.0, 0.0], [1.0, 2.0, 1.0]);
+        smd
+    }
+
+    fn settings(max_pieces: usize) -> Decomposition {
+        Decomposition {
+            limits: Limits {
+                concave: true,
+                max_pieces,
+                max_hull_vertices: Some(32),
+            },
+            max_concavity: 0.01,
+            resolution: 8,
+        }
+    }
+
+    #[test]
+    fn it_decomposes_concave_meshes() {
+        let smd = l_shape();
+        assert!(find_parts(&smd).len() == 1);
+
+        let (decomposed, pieces) = decompose(&smd, &settings(8)).unwrap();
+
+        // The L should be cut into a bar and a cube, which don't share any vertices
+        assert!(pieces == 2);
+        assert!(find_parts(&decomposed).len() == 2);
+        check_limits(&decomposed, &settings(2).limits).unwrap();
+    }
+
+    #[test]
+    fn it_keeps_convex_meshes_whole() {
+        let mut smd = Smd::new();
+        add_box(&mut smd, [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+        add_box(&mut smd, [3.0, 0.0, 0.0], [4.0, 1.0, 1.0]);
+
+        let (decomposed, pieces) = decompose(&smd, &settings(8)).unwrap();
+
+        assert!(pieces == 2);
+        assert!(find_parts(&decomposed).len() == 2);
+    }
+
+    #[test]
+    fn it_checks_limits() {
+        let mut smd = Smd::new();
+        for i in 0..3 {
+            add_box(&mut smd, [i as f32 * 2.0, 0.0, 0.0], [i as f32 * 2.0 + 1.0, 1.0, 1.0]);
+        }
+
+        assert!(check_limits(&smd, &settings(3).limits).is_ok());
+        match check_limits(&smd, &settings(2).limits) {
+            Err(Error::Runner(ref error)) => assert!(error.kind == TaskErrorKind::Input),
+            _ => panic!("Expected an input error"),
+        }
+        assert!(decompose(&smd, &settings(2)).is_err());
+
+        // As a single convex piece the hull of all boxes only has the outer corners
+        let mut limits = Limits { concave: false, max_pieces: 1, max_hull_vertices: Some(8) };
+        assert!(check_limits(&smd, &limits).is_ok());
+        limits.max_hull_vertices = Some(7);
+        assert!(check_limits(&smd, &limits).is_err());
+
+        // Without a vertex limit only the amount of pieces matters
+        limits.max_hull_vertices = None;
+        assert!(check_limits(&smd, &limits).is_ok());
+    }
+
+    #[test]
+    fn it_refuses_invalid_positions() {
+        let mut smd = l_shape();
+        smd.triangles[0].vertices[0].position[1] = f32::NAN;
+
+        match decompose(&smd, &settings(8)) {
+            Err(Error::Runner(ref error)) => assert!(error.kind == TaskErrorKind::Input),
+            _ => panic!("Expected an input error"),
+        }
+        assert!(check_limits(&smd, &settings(8).limits).is_err());
+
+        // A part collapsed into a single point can't be turned into a piece
+        let mut smd = l_shape();
+        let mut vertices: [SmdVertex; 3] = Default::default();
+        for vertex in &mut vertices {
+            vertex.position = [5.0, 5.0, 5.0];
+        }
+        smd.triangles.push(SmdTriangle {
+            material: "phys".into(),
+            vertices,
+        });
+        assert!(decompose(&smd, &settings(8)).is_err());
+    }
+}
@@ -1,84 +1,396 @@
-use std::path::{PathBuf};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
 use std::fs::File;
 use std::io::{Write};
-use std::process::{Command};
 
-use soto::task::{task_log, TaskParameters};
 use soto::Error;
 
-use task::SotoFbxTask;
+use task::{KeyValue, SotoFbxTask};
 
-pub fn generate_qc(path: &PathBuf, toml: &SotoFbxTask, ref_mdl_name: &str) -> Result<(), Error> {
+/// The SMDs generated for a task, by the names the QC refers to them with.
+pub struct QcSmds {
+    pub reference: String,
+    pub collision: String,
+    /// The SMDs of the other meshes, by the FBX file they're generated from.
+    pub meshes: HashMap<PathBuf, String>,
+}
+
+pub fn generate_qc(
+    path: &PathBuf, toml: &SotoFbxTask, smds: &QcSmds, frame_rates: &HashMap<String, f32>,
+) -> Result<(), Error> {
     let mut file = File::create(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    write_qc(&mut file, directory, toml, smds, frame_rates)
+}
+
+/// Writes a QC that will be in `directory`, files the QC refers to are relative to it.
+fn write_qc<W: Write>(
+    file: &mut W, directory: &Path, toml: &SotoFbxTask, smds: &QcSmds,
+    frame_rates: &HashMap<String, f32>,
+) -> Result<(), Error> {
+    let ref_mdl_name = &smds.reference;
     writeln!(file, "// Generated by soto-fbx, do not edit manually")?;
 
     // Generic data
     writeln!(file, "$modelname \"{}.mdl\"", toml.prop.name)?;
-    writeln!(file, "$scale 16")?;
-    writeln!(file, "$upaxis Y")?;
     writeln!(file)?;
 
     // Prop information
     if toml.prop.dynamic == "static" {
         writeln!(file, "$staticprop")?;
     }
-    writeln!(file, "$surfaceprop \"default\"")?;
+    let surface_prop = toml.prop.surface_prop.as_deref().unwrap_or("default");
+    writeln!(file, "$surfaceprop \"{}\"", surface_prop)?;
     writeln!(file)?;
 
-    // Materials
-    writeln!(file, "$cdmaterials \"layl_test_texture/\"")?;
+    // Materials, if no directories are given use the directory the model itself is in
+    if let Some(ref cdmaterials) = toml.materials.cdmaterials {
+        for directory in cdmaterials {
+            writeln!(file, "$cdmaterials \"{}\"", directory)?;
+        }
+    } else {
+        let directory = toml.prop.name.rsplit_once('/').map(|(d, _)| d).unwrap_or("");
+        writeln!(file, "$cdmaterials \"models/{}\"", directory)?;
+    }
+    for group in &toml.texture_groups {
+        let name = group.name.as_deref().unwrap_or("skinfamilies");
+        writeln!(file, "$texturegroup \"{}\"", name)?;
+        writeln!(file, "{{")?;
+        for skin in &group.skins {
+            let materials: Vec<_> = skin.iter().map(|m| format!("\"{}\"", m)).collect();
+            writeln!(file, "    {{ {} }}", materials.join(" "))?;
+        }
+        writeln!(file, "}}")?;
+    }
     writeln!(file)?;
 
     // Reference model data
     writeln!(file, "$body shell \"{}\"", ref_mdl_name)?;
+    for bodygroup in &toml.bodygroups {
+        writeln!(file, "$bodygroup \"{}\"", bodygroup.name)?;
+        writeln!(file, "{{")?;
+        for option in &bodygroup.options {
+            match option.file {
+                Some(ref mesh) => writeln!(file, "    studio \"{}\"", smds.meshes[mesh])?,
+                None => writeln!(file, "    blank")?,
+            }
+        }
+        writeln!(file, "}}")?;
+    }
     writeln!(file)?;
 
+    // Levels of detail
+    for lod in &toml.lods {
+        writeln!(file, "$lod {}", lod.threshold)?;
+        writeln!(file, "{{")?;
+        if let Some(ref mesh) = lod.file {
+            writeln!(file, "    replacemodel \"{}\" \"{}\"", ref_mdl_name, smds.meshes[mesh])?;
+        }
+        for (from, to) in lod.replace.iter().flat_map(|r| r.iter()) {
+            writeln!(
+                file, "    replacemodel \"{}\" \"{}\"", smds.meshes[from], smds.meshes[to]
+            )?;
+        }
+        writeln!(file, "}}")?;
+    }
+    if !toml.lods.is_empty() {
+        writeln!(file)?;
+    }
+
     // Animation data
     writeln!(file, "$sequence idle \"{}\"", ref_mdl_name)?;
-    for (sequence, data) in toml.sequences.as_ref().unwrap_or(&::std::collections::HashMap::new()) {
-        writeln!(file, "$sequence {} \"animation_{}.smd\" {}", sequence, sequence, data.params)?;
+    for (sequence, data) in toml.sequences.as_ref().unwrap_or(&BTreeMap::new()) {
+        writeln!(
+            file, "$sequence {} \"animation_{}.smd\" fps {} {}",
+            sequence, sequence, frame_rates[sequence], data.params
+        )?;
     }
     writeln!(file)?;
 
+    // Attachments and hitboxes
+    for attachment in &toml.attachments {
+        let [x, y, z] = attachment.position;
+        write!(
+            file, "$attachment \"{}\" \"{}\" {} {} {}", attachment.name, attachment.bone, x, y, z
+        )?;
+        if let Some([pitch, yaw, roll]) = attachment.rotation {
+            write!(file, " rotate {} {} {}", pitch, yaw, roll)?;
+        }
+        if attachment.absolute == Some(true) {
+            write!(file, " absolute")?;
+        }
+        if attachment.rigid == Some(true) {
+            write!(file, " rigid")?;
+        }
+        writeln!(file)?;
+    }
+    for set in &toml.hitbox_sets {
+        writeln!(file, "$hboxset \"{}\"", set.name)?;
+        for hitbox in &set.boxes {
+            writeln!(
+                file, "$hbox {} \"{}\" {}",
+                hitbox.group, hitbox.bone, bounds(hitbox.min, hitbox.max)
+            )?;
+        }
+    }
+    if !toml.attachments.is_empty() || !toml.hitbox_sets.is_empty() {
+        writeln!(file)?;
+    }
+
+    // Lighting and bounds
+    if let Some(ref illum) = toml.illum_position {
+        let [x, y, z] = illum.position;
+        match illum.bone {
+            Some(ref bone) => writeln!(file, "$illumposition {} {} {} \"{}\"", x, y, z, bone)?,
+            None => writeln!(file, "$illumposition {} {} {}", x, y, z)?,
+        }
+    }
+    if let Some(cbox) = toml.clipping_box {
+        writeln!(file, "$cbox {}", bounds(cbox.min, cbox.max))?;
+    }
+    if let Some(bbox) = toml.bounding_box {
+        writeln!(file, "$bbox {}", bounds(bbox.min, bbox.max))?;
+    }
+    if toml.illum_position.is_some() || toml.clipping_box.is_some() ||
+        toml.bounding_box.is_some() {
+        writeln!(file)?;
+    }
+
+    // Key values for the game, like prop_data
+    if let Some(ref keyvalues) = toml.keyvalues {
+        writeln!(file, "$keyvalues")?;
+        writeln!(file, "{{")?;
+        write_keyvalues(file, keyvalues, 1)?;
+        writeln!(file, "}}")?;
+        writeln!(file)?;
+    }
+
     // Physics data
-    writeln!(file, "$collisionmodel \"{}\"", ref_mdl_name)?;
+    let collision = &toml.collision;
+    writeln!(file, "$collisionmodel \"{}\"", smds.collision)?;
     writeln!(file, "{{")?;
-    writeln!(file, "    $mass 1")?;
-    writeln!(file, "    $concave")?;
+    writeln!(file, "    $mass {}", collision.mass.unwrap_or(1.0))?;
+    if let Some(inertia) = collision.inertia {
+        writeln!(file, "    $inertia {}", inertia)?;
+    }
+    if let Some(damping) = collision.damping {
+        writeln!(file, "    $damping {}", damping)?;
+    }
+    if let Some(rot_damping) = collision.rot_damping {
+        writeln!(file, "    $rotdamping {}", rot_damping)?;
+    }
+    if collision.is_concave() {
+        writeln!(file, "    $concave")?;
+    }
+    if let Some(max_convex_pieces) = collision.max_convex_pieces {
+        writeln!(file, "    $maxconvexpieces {}", max_convex_pieces)?;
+    }
     writeln!(file, "}}")?;
     writeln!(file)?;
 
+    // Included files go last, so they can add to everything generated, they're relative to the
+    // QC so the path works the same for every compiler backend
+    for include in &toml.includes {
+        let path = relative_path(&include.file, directory);
+        writeln!(file, "$include \"{}\"", path.display())?;
+    }
+
     Ok(())
 }
 
-pub fn build_qc(qc_path: &PathBuf, params: &TaskParameters, game_dir: &PathBuf) -> Result<(), Error> {
-    // First, find studiomdl.exe, it should be in the game's bin
-    let mut studiomdl = params.local.game.bin.clone();
-    studiomdl.push("studiomdl.exe");
-    if studiomdl.exists() {
-        task_log(format!("Found studiomdl at \"{}\"", studiomdl.display()));
-    } else {
-        return Err(Error::Task(format!("Unable to find studiomdl at \"{}\"", studiomdl.display())));
+/// Finds the path that leads from `base` to `path`, keeps `path` as it is if they have nothing in
+/// common, for example when they're on different drives.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    let mut shared = 0;
+    while path_components.peek().is_some() && path_components.peek() == base_components.peek() {
+        path_components.next();
+        base_components.next();
+        shared += 1;
+    }
+    if shared == 0 {
+        return path.to_path_buf();
     }
 
-    // Now that we have studiomdl, run the compile
-    task_log(format!("Running studiomdl to compile \"{}\" with as game dir \"{}\"", qc_path.display(), game_dir.display()));
-    let output = Command::new(&studiomdl)
-        .arg("-game").arg(game_dir)
-        .arg(qc_path.to_str().unwrap())
-        .output()
-        .map_err(|e| Error::Io(e))?;
-
-    // Make sure it completed successfully
-    if !output.status.success() {
-        return Err(Error::Task(format!(
-            "Error during studiomdl compilation.\nStdout:\n{}\nStderr:\n{}",
-            ::std::str::from_utf8(&output.stdout).unwrap(),
-            ::std::str::from_utf8(&output.stderr).unwrap()
-        )));
+    let mut relative = PathBuf::new();
+    for component in base_components {
+        if component != Component::CurDir {
+            relative.push("..");
+        }
     }
+    relative.extend(path_components);
+    relative
+}
+
+fn bounds(min: [f32; 3], max: [f32; 3]) -> String {
+    format!("{} {} {} {} {} {}", min[0], min[1], min[2], max[0], max[1], max[2])
+}
 
-    // TODO: Copy files back to dest, studiomdl drops them in the game
+fn write_keyvalues<W: Write>(
+    file: &mut W, section: &BTreeMap<String, KeyValue>, depth: usize
+) -> Result<(), Error> {
+    let indent = "    ".repeat(depth);
+    for (key, value) in section {
+        match *value {
+            KeyValue::Section(ref section) => {
+                writeln!(file, "{}\"{}\"", indent, key)?;
+                writeln!(file, "{}{{", indent)?;
+                write_keyvalues(file, section, depth + 1)?;
+                writeln!(file, "{}}}", indent)?;
+            },
+            KeyValue::String(ref value) => writeln!(file, "{}\"{}\" \"{}\"", indent, key, value)?,
+            KeyValue::Integer(value) => writeln!(file, "{}\"{}\" \"{}\"", indent, key, value)?,
+            KeyValue::Float(value) => writeln!(file, "{}\"{}\" \"{}\"", indent, key, value)?,
+            KeyValue::Boolean(value) =>
+                writeln!(file, "{}\"{}\" \"{}\"", indent, key, if value { 1 } else { 0 })?,
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use task::tests::task;
+    use super::{relative_path, write_qc, QcSmds};
+
+    #[test]
+    fn it_generates_qc_sections() {
+        let task = task(r#"
+            [[bodygroups]]
+            name = "lid"
+            options = [{ file = "lid.fbx" }, {}]
+
+            [[texture_groups]]
+            skins = [["crate", "lid"], ["crate_dirty", "lid_dirty"]]
+
+            [[hitbox_sets]]
+            name = "default"
+            boxes = [{ group = 2, bone = "root", min = [-8.0, -8.0, 0.0], max = [8.0, 8.0, 16.5] }]
+
+            [[lods]]
+            threshold = 10.0
+            file = "crate_lod1.fbx"
+            replace = { "lid.fbx" = "lid_lod1.fbx" }
+
+            [keyvalues.prop_data]
+            base = "Wooden.Small"
+            health = 20
+        "#);
+        let mut meshes = HashMap::new();
+        meshes.insert(PathBuf::from("lid.fbx"), "mesh_0.smd".to_string());
+        meshes.insert(PathBuf::from("crate_lod1.fbx"), "mesh_1.smd".to_string());
+        meshes.insert(PathBuf::from("lid_lod1.fbx"), "mesh_2.smd".to_string());
+        let smds = QcSmds {
+            reference: "reference.smd".into(),
+            collision: "phys.smd".into(),
+            meshes,
+        };
+
+        let mut qc = Vec::new();
+        write_qc(&mut qc, Path::new("/project/target/working"), &task, &smds, &HashMap::new())
+            .unwrap();
+        let qc = String::from_utf8(qc).unwrap();
+
+        let expected = [
+            "$bodygroup \"lid\"\n{\n    studio \"mesh_0.smd\"\n    blank\n}\n",
+            "$texturegroup \"skinfamilies\"\n{\n    { \"crate\" \"lid\" }\n    \
+             { \"crate_dirty\" \"lid_dirty\" }\n}\n",
+            "$lod 10\n{\n    replacemodel \"reference.smd\" \"mesh_1.smd\"\n    \
+             replacemodel \"mesh_0.smd\" \"mesh_2.smd\"\n}\n",
+            "$keyvalues\n{\n    \"prop_data\"\n    {\n        \"base\" \"Wooden.Small\"\n        \
+             \"health\" \"20\"\n    }\n}\n",
+            "$hboxset \"default\"\n$hbox 2 \"root\" -8 -8 0 8 8 16.5\n",
+        ];
+        for section in &expected {
+            assert!(qc.contains(section), "Missing:\n{}\nIn:\n{}", section, qc);
+        }
+    }
+
+    #[test]
+    fn it_generates_collision_settings() {
+        let task = task(r#"
+            [collision]
+            mass = 20.0
+            inertia = 1.5
+            damping = 0.25
+            rot_damping = 0.5
+            max_convex_pieces = 40
+        "#);
+        let smds = QcSmds {
+            reference: "reference.smd".into(),
+            collision: "phys.smd".into(),
+            meshes: HashMap::new(),
+        };
+
+        let mut qc = Vec::new();
+        write_qc(&mut qc, Path::new("/project/target/working"), &task, &smds, &HashMap::new())
+            .unwrap();
+        let qc = String::from_utf8(qc).unwrap();
+
+        let expected = "$collisionmodel \"phys.smd\"\n{\n    $mass 20\n    $inertia 1.5\n    \
+                        $damping 0.25\n    $rotdamping 0.5\n    $concave\n    \
+                        $maxconvexpieces 40\n}\n";
+        assert!(qc.contains(expected), "Missing:\n{}\nIn:\n{}", expected, qc);
+    }
+
+    #[test]
+    fn it_writes_sequences_and_includes_in_a_stable_order() {
+        let mut task = task(r#"
+            [sequences.walk]
+            file = "walk.fbx"
+            params = "loop"
+
+            [sequences.idle_open]
+            file = "open.fbx"
+            params = ""
+
+            [sequences.run]
+            file = "run.fbx"
+            params = "loop"
+
+            [[includes]]
+            file = "shared/crate.qci"
+        "#);
+        task.resolve_paths(Path::new("/project"));
+        let smds = QcSmds {
+            reference: "reference.smd".into(),
+            collision: "phys.smd".into(),
+            meshes: HashMap::new(),
+        };
+        let frame_rates = ["walk", "idle_open", "run"].iter()
+            .map(|s| (s.to_string(), 30.0))
+            .collect();
+
+        let mut qc = Vec::new();
+        write_qc(&mut qc, Path::new("/project/target/working"), &task, &smds, &frame_rates)
+            .unwrap();
+        let qc = String::from_utf8(qc).unwrap();
+
+        let expected = [
+            "$sequence idle_open \"animation_idle_open.smd\" fps 30 \n\
+             $sequence run \"animation_run.smd\" fps 30 loop\n\
+             $sequence walk \"animation_walk.smd\" fps 30 loop\n",
+            "$include \"../../shared/crate.qci\"\n",
+        ];
+        for section in &expected {
+            assert!(qc.contains(section), "Missing:\n{}\nIn:\n{}", section, qc);
+        }
+    }
+
+    #[test]
+    fn it_finds_relative_paths() {
+        let path = Path::new("/project/shared/crate.qci");
+        let relative = |base: &str| relative_path(path, Path::new(base));
+        assert!(relative("/project") == Path::new("shared/crate.qci"));
+        assert!(relative("/project/shared") == Path::new("crate.qci"));
+        assert!(relative("/other") == Path::new("../project/shared/crate.qci"));
+
+        // Without anything in common there's no relative path
+        let file = Path::new("crate.qci");
+        assert!(relative_path(file, Path::new("/project")) == file);
+    }
+}
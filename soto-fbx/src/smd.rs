@@ -1,47 +1,98 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader};
-use std::path::PathBuf;
+use std::path::Path;
 
-use cgmath::{Matrix4, Deg, Vector4, SquareMatrix, Vector3, Euler, Quaternion, Rotation};
-use soto::task::{task_log};
+use cgmath::{Matrix3, Matrix4, Vector3, Vector4, InnerSpace, SquareMatrix, Zero};
+use slog::Logger;
+use soto::task::{TaskError, TaskErrorKind};
 use soto::Error;
 use sotolib_fbx::{RawFbx, id_name, friendly_name, ObjectTreeNode};
 use sotolib_fbx::animation::{Animation};
-use sotolib_fbx::simple::{Object, SimpleFbx, ObjectType, ModelProperties, Geometry};
-use sotolib_smd::{Smd, SmdVertex, SmdTriangle, SmdAnimationFrameBone, SmdBone};
+use sotolib_fbx::simple::{ObjectId, SimpleFbx, ObjectType, ModelProperties};
+use sotolib_smd::{Smd, SmdVertex, SmdTriangle, SmdAnimationFrameBone, SmdBone, SmdLink, BoneId};
 
-pub fn create_reference_smd(fbx: &PathBuf, flip_fix_list: &Vec<String>) -> Result<Smd, Error> {
+use conversion::Conversion;
+use task::{Model, Materials, MeshFilter, Sequence};
+use transform;
+
+/// The material name used for triangles that don't have a material assigned.
+const DEFAULT_MATERIAL: &str = "default";
+
+/// The most bones Source lets influence a single vertex.
+const MAX_LINKS: usize = 3;
+
+/// Geometry found while walking the FBX tree, processed after all bones have been added so skin
+/// clusters can refer to bones anywhere in the hierarchy.
+struct PendingGeometry<'a> {
+    node: &'a ObjectTreeNode,
+    matrix: Matrix4<f32>,
+    bone: BoneId,
+}
+
+pub fn create_reference_smd(
+    log: &Logger, model: &Model, materials: &Materials, filter: MeshFilter,
+) -> Result<Smd, Error> {
     // Read in the fbx we got told to convert
-    let file = BufReader::new(File::open(&fbx).unwrap());
-    let fbx = SimpleFbx::from_raw(&RawFbx::parse(file).unwrap()).unwrap();
+    let fbx = read_fbx(&model.reference)?;
     let fbx_tree = ObjectTreeNode::from_simple(&fbx);
+    let conversion = Conversion::new(&fbx.global_settings, model)?;
 
     // Go over all FBX root nodes and turn them into SMD data
     let mut smd = Smd::new();
+    let mut geometries = Vec::new();
     process_fbx_node(
-        &fbx,
+        log, &fbx,
         &fbx_tree, &mut smd,
-        &Matrix4::identity(),
         None,
-        flip_fix_list
+        &conversion,
+        &mut geometries,
     )?;
 
+    // Now that we have all bones, add the geometry, geometry belongs to the model it's parented to
+    for geometry in geometries {
+        let model = smd.bones.iter().find(|b| b.id == geometry.bone).unwrap().name.clone();
+        if filter.includes(&model) {
+            process_geometry(log, &fbx, &mut smd, &geometry, materials, &conversion)?;
+        }
+    }
+
     Ok(smd)
 }
 
+/// Creates the SMD for an animation sequence, returns the SMD and the frame rate it's sampled at.
 pub fn create_animation_smd(
-    ref_smd: &Smd, fbx: &PathBuf, flip_fix_list: &Vec<String>,
-) -> Result<Smd, Error> {
+    log: &Logger, ref_smd: &Smd, sequence: &Sequence, model: &Model,
+) -> Result<(Smd, f32), Error> {
     // Read in the fbx we got told to convert
-    let file = BufReader::new(File::open(&fbx).unwrap());
-    let mut fbx = SimpleFbx::from_raw(&RawFbx::parse(file).unwrap()).unwrap();
-
-    // Read in the animation data itself
-    let animation = Animation::from_simple(&fbx).unwrap();
+    let fbx_path = &sequence.file;
+    let mut fbx = read_fbx(fbx_path)?;
+    let conversion = Conversion::new(&fbx.global_settings, model)?;
+    let frame_rate = sequence.fps
+        .or_else(|| fbx.global_settings.frame_rate())
+        .unwrap_or(30.0);
+
+    // Read in the animation data itself, from the take we got told to use if any
+    let animation = if let Some(ref take) = sequence.take {
+        Animation::by_name(&fbx, take).ok_or_else(|| {
+            let takes: Vec<_> = Animation::all_from_simple(&fbx).into_iter()
+                .map(|a| format!("\"{}\"", a.name))
+                .collect();
+            Error::Task(format!(
+                "Take \"{}\" not found in \"{}\", available takes: {}",
+                take, fbx_path.display(), takes.join(", ")
+            ))
+        })?
+    } else {
+        Animation::from_simple(&fbx).ok_or_else(|| Error::Task(format!(
+            "No animation found in \"{}\"", fbx_path.display()
+        )))?
+    };
+    debug!(log, "Using take \"{}\"", animation.name);
 
     // Count and log frames
-    let frame_count = animation.frame_count(&fbx);
-    task_log(format!("Animation has {} frames", frame_count));
+    let frame_count = animation.frame_count(frame_rate);
+    debug!(log, "Animation has {} frames at {} fps", frame_count, frame_rate);
 
     // Copy over every bone to the new animation SMD
     let mut smd = Smd::new();
@@ -52,15 +103,17 @@ pub fn create_animation_smd(
     // Finally, turn the animation data into bone positions in the SMD
     for frame in 0..frame_count {
         // First transform the FBX for this frame
-        animation.transform_fbx_to_frame(&mut fbx, frame);
+        animation.transform_fbx_to_frame(&mut fbx, frame, frame_rate);
 
         // Now go over all models
-        for (_, model) in fbx.objects.iter().filter(|&(_, o)| o.class.type_name() == "Model") {
+        for (_, fbx_model) in fbx.objects.iter().filter(|&(_, o)| o.class.type_name() == "Model") {
             // For this model, look up the matching BoneId in the reference SMD
-            if let Some(bone_id) = ref_smd.id_of_bone(&id_name(&model.name).unwrap()) {
+            if let Some(bone_id) = ref_smd.id_of_bone(&id_name(&fbx_model.name).unwrap()) {
                 // Now that we have a model and a bone, we need the current translation and rotation
                 // for the model
-                let (translation, rotation) = calculate_animation_transforms_for(&fbx, model, flip_fix_list);
+                let (translation, rotation) = transform::bone_transform(
+                    &fbx, fbx_model.id, &conversion
+                );
 
                 // And now that we have those, finally add the bone data to the animation SMD
                 smd.set_animation(frame, bone_id, SmdAnimationFrameBone {
@@ -71,26 +124,43 @@ pub fn create_animation_smd(
         }
     }
 
-    Ok(smd)
+    Ok((smd, frame_rate))
 }
 
-fn process_fbx_node(
-    fbx: &SimpleFbx,
-    fbx_node: &ObjectTreeNode, mut smd: &mut Smd,
-    matrix: &Matrix4<f32>,
+fn read_fbx(path: &Path) -> Result<SimpleFbx, Error> {
+    let input_error = |message: String| {
+        Error::Runner(TaskError::new(TaskErrorKind::Input, message).with_file(path))
+    };
+
+    let file = File::open(path)
+        .map_err(|e| input_error(format!("Unable to open FBX: {}", e)))?;
+    let raw = RawFbx::parse(BufReader::new(file))
+        .map_err(|e| input_error(format!("Unable to parse FBX: {}", e)))?;
+    SimpleFbx::from_raw(&raw)
+        .map_err(|e| input_error(format!("Unsupported FBX: {}", e)))
+}
+
+fn process_fbx_node<'a>(
+    log: &Logger, fbx: &SimpleFbx,
+    fbx_node: &'a ObjectTreeNode, smd: &mut Smd,
     current_bone: Option<&SmdBone>,
-    flip_fix_list: &Vec<String>,
+    conversion: &Conversion,
+    geometries: &mut Vec<PendingGeometry<'a>>,
 ) -> Result<(), Error> {
     // Perform node type specific information
     match fbx_node.object.class {
-        ObjectType::Geometry(ref geometry) =>
-            process_geometry(smd, geometry, matrix, current_bone.unwrap()),
+        ObjectType::Geometry(_) =>
+            geometries.push(PendingGeometry {
+                node: fbx_node,
+                matrix: geometry_matrix(fbx, fbx_node.object.id, conversion),
+                bone: current_bone.unwrap().id,
+            }),
         ObjectType::Model(ref _model) =>
-            process_model(fbx, fbx_node, smd, matrix, current_bone, flip_fix_list)?,
+            process_model(log, fbx, fbx_node, smd, current_bone, conversion, geometries)?,
         _ => {
             // Just go straight to the children
             for node in &fbx_node.nodes {
-                process_fbx_node(fbx, node, smd, matrix, current_bone, flip_fix_list)?;
+                process_fbx_node(log, fbx, node, smd, current_bone, conversion, geometries)?;
             }
         }
     }
@@ -98,52 +168,209 @@ fn process_fbx_node(
     Ok(())
 }
 
-fn process_geometry(smd: &mut Smd, geometry: &Geometry, matrix: &Matrix4<f32>, current_bone: &SmdBone) {
+fn process_geometry(
+    log: &Logger, fbx: &SimpleFbx, smd: &mut Smd, pending: &PendingGeometry, materials: &Materials,
+    conversion: &Conversion,
+) -> Result<(), Error> {
+    let geometry = match pending.node.object.class {
+        ObjectType::Geometry(ref geometry) => geometry,
+        _ => unreachable!(),
+    };
+    let degenerate = geometry.degenerate_polygons().len();
+    if degenerate != 0 {
+        warn!(
+            log, "Skipping {} polygons without any area in \"{}\"",
+            degenerate, friendly_name(&pending.node.object.name)
+        );
+    }
+    let normal_matrix = transform::normal_matrix(&pending.matrix);
+
+    // Find the bone weights for the control points, if this geometry is skinned
+    let skins = control_point_skins(fbx, smd, pending.node, conversion)?;
+
+    // Find the names of the materials the geometry's material indices refer to
+    let material_names = model_material_names(fbx, pending.node.object.id, materials);
+
     // Add triangles to parent node
-    let tris = geometry.triangles();
+    let tris = geometry.triangles()
+        .map_err(|e| Error::Task(format!(
+            "Invalid geometry \"{}\": {}", friendly_name(&pending.node.object.name), e
+        )))?;
     for tri in tris {
         // Turn the vertices in this triangle to SMD vertices
         let mut smd_verts: [SmdVertex; 3] = Default::default();
-        for (i, vert) in tri.iter().enumerate() {
+        for (i, vert) in tri.vertices.iter().enumerate() {
+            // Skinned control points move along with their bones, others with their model
+            let skin = skins.get(&vert.index);
+            let matrix = skin.map(|s| &s.matrix).unwrap_or(&pending.matrix);
+            let normal_matrix = skin.map(|s| &s.normal_matrix).unwrap_or(&normal_matrix);
+
             // Multiply the vectors that need to be multiplied
-            let pos = matrix * Vector4::new(vert.0[0], vert.0[1], vert.0[2], 1.0);
-            let norm = matrix * Vector4::new(vert.1[0], vert.1[1], vert.1[2], 0.0);
+            let pos = matrix * Vector4::new(vert.position[0], vert.position[1], vert.position[2], 1.0);
+            let mut norm = normal_matrix * Vector3::from(vert.normal);
+
+            // The matrix includes scale, so make sure the normal is still a unit vector
+            if norm.magnitude2() > 0.0 {
+                norm = norm.normalize();
+            }
 
             smd_verts[i] = SmdVertex {
-                parent_bone: current_bone.id, // This is overwritten by links
+                parent_bone: pending.bone, // This is overwritten by links
                 position: pos.truncate().into(),
-                normal: norm.truncate().into(),
-                uv: vert.2,
-                links: vec!(
-                    /*Not needed, we aren't using weights anyways so this done by parent_bone
-                    SmdLink {
-                        bone: bone_id,
-                        weight: 1.0,
-                    }*/
-                )
+                normal: norm.into(),
+                uv: vert.uv,
+                // Control points without skin weights are rigidly parented through parent_bone
+                links: skin.map(|s| s.links.clone()).unwrap_or_default(),
             };
         }
 
         // Add the actual SMD triangle
+        let material = tri.material
+            .and_then(|m| material_names.get(m as usize))
+            .map(|m| m.as_str())
+            .unwrap_or(DEFAULT_MATERIAL);
         smd.triangles.push(SmdTriangle {
-            material: "layl_test_texture".into(),
+            material: material.into(),
             vertices: smd_verts,
         });
     }
+
+    Ok(())
+}
+
+/// Finds the SMD material names for the materials of the model a geometry belongs to, in the
+/// order the geometry's material indices refer to them.
+fn model_material_names(fbx: &SimpleFbx, geometry: ObjectId, materials: &Materials) -> Vec<String> {
+    let model = match fbx.parent_of(geometry) {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+
+    fbx.children_of(model).into_iter()
+        .filter(|o| o.class.as_material().is_some())
+        .map(|material| {
+            // Find the name we want to give this material
+            let mut name = id_name(&material.name).unwrap();
+            if materials.use_texture_names.unwrap_or(false) {
+                let texture_name = fbx.driven_properties_of(material.id).iter()
+                    .filter(|p| p.name == "DiffuseColor")
+                    .filter_map(|p| fbx.objects.get(&p.driver))
+                    .filter_map(|o| o.class.as_texture())
+                    .filter_map(|t| t.file_stem())
+                    .next();
+                if let Some(texture_name) = texture_name {
+                    name = texture_name;
+                }
+            }
+
+            // If it's been remapped, use that instead
+            materials.remap.as_ref()
+                .and_then(|r| r.get(&name))
+                .cloned()
+                .unwrap_or(name)
+        })
+        .collect()
+}
+
+/// How a skinned control point is deformed.
+struct ControlPointSkin {
+    /// The bones influencing the control point, strongest first, with weights adding up to 1.
+    links: Vec<SmdLink>,
+    /// Moves the control point from its bind pose into SMD space, blended from its bones.
+    matrix: Matrix4<f32>,
+    normal_matrix: Matrix3<f32>,
+}
+
+/// Collects how every skinned control point of a geometry node is deformed. Skinned geometry is
+/// stored in the pose it was bound to its bones in, which the skeleton doesn't have to be in
+/// anymore, so control points are moved along with their bones from the bind pose.
+fn control_point_skins(
+    fbx: &SimpleFbx, smd: &Smd, geometry_node: &ObjectTreeNode, conversion: &Conversion,
+) -> Result<HashMap<u32, ControlPointSkin>, Error> {
+    let geometric = geometric_matrix(fbx, geometry_node.object.id);
+    let mut influences: HashMap<u32, Vec<(SmdLink, Matrix4<f32>)>> = HashMap::new();
+
+    // Skins are attached to the geometry, clusters to the skins
+    let clusters = geometry_node.nodes.iter()
+        .filter(|n| matches!(n.object.class, ObjectType::Skin(_)))
+        .flat_map(|skin| skin.nodes.iter());
+    for cluster_node in clusters {
+        let cluster = match cluster_node.object.class.as_cluster() {
+            Some(v) => v,
+            None => continue,
+        };
+
+        // The bone this cluster binds to is the model attached to it
+        let bone = cluster_node.nodes.iter()
+            .find(|n| n.object.class.type_name() == "Model")
+            .ok_or_else(|| Error::Task(format!(
+                "Skin cluster \"{}\" is not linked to a bone", friendly_name(&cluster_node.object.name)
+            )))?;
+        let bone_name = id_name(&bone.object.name).unwrap();
+        let bone_id = smd.id_of_bone(&bone_name)
+            .ok_or_else(|| Error::Task(format!(
+                "Bone \"{}\" used by skin is not in the SMD", bone_name
+            )))?;
+
+        // The cluster has the global transforms of the geometry and the bone when they were
+        // bound, going from the first to the second gets the geometry relative to the bone
+        let bone_bind = transform::fbx_matrix(&cluster.transform_link).invert()
+            .ok_or_else(|| Error::Task(format!(
+                "Bind pose of bone \"{}\" can't be inverted", bone_name
+            )))?;
+        let matrix = conversion.matrix() *
+            transform::global_matrix(fbx, bone.object.id) *
+            bone_bind *
+            transform::fbx_matrix(&cluster.transform) *
+            geometric;
+
+        for (index, weight) in cluster.indexes.iter().zip(cluster.weights.iter()) {
+            // Broken exporters can write NaN or infinite weights, those can't be normalized
+            if !weight.is_finite() || *weight <= 0.0 {
+                continue;
+            }
+
+            let link = SmdLink {
+                bone: bone_id,
+                weight: *weight,
+            };
+            influences.entry(*index).or_default().push((link, matrix));
+        }
+    }
+
+    Ok(influences.into_iter().map(|(index, links)| (index, blend_influences(links))).collect())
 }
 
-fn process_model(
-    fbx: &SimpleFbx,
-    fbx_node: &ObjectTreeNode, smd: &mut Smd,
-    matrix: &Matrix4<f32>,
+/// Keeps the strongest influences on a control point Source supports, with their weights adding
+/// up to 1, and blends their matrices.
+fn blend_influences(mut influences: Vec<(SmdLink, Matrix4<f32>)>) -> ControlPointSkin {
+    influences.sort_by(|a, b| b.0.weight.total_cmp(&a.0.weight));
+    influences.truncate(MAX_LINKS);
+
+    let total: f32 = influences.iter().map(|i| i.0.weight).sum();
+    let mut links = Vec::new();
+    let mut matrix = Matrix4::zero();
+    for (mut link, link_matrix) in influences {
+        link.weight /= total;
+        matrix += link_matrix * link.weight;
+        links.push(link);
+    }
+
+    ControlPointSkin {
+        links,
+        normal_matrix: transform::normal_matrix(&matrix),
+        matrix,
+    }
+}
+
+fn process_model<'a>(
+    log: &Logger, fbx: &SimpleFbx,
+    fbx_node: &'a ObjectTreeNode, smd: &mut Smd,
     current_bone: Option<&SmdBone>,
-    flip_fix_list: &Vec<String>,
+    conversion: &Conversion,
+    geometries: &mut Vec<PendingGeometry<'a>>,
 ) -> Result<(), Error> {
-    task_log(format!("Adding model \"{}\" to SMD data", friendly_name(&fbx_node.object.name)));
-    let properties = ModelProperties::from_generic(&fbx_node.object.properties);
-
-    // Create a new transformation matrix
-    let local_matrix = local_matrices(&properties);
+    debug!(log, "Adding model \"{}\" to SMD data", friendly_name(&fbx_node.object.name));
 
     // Create a new bone
     let new_bone = smd.new_bone(
@@ -157,134 +384,118 @@ fn process_model(
         .clone(); // Clone needed to avoid a borrow since we need to mut borrow SMD later
 
     // Set the transformations on this bone
-    let (translation, rotation) = calculate_animation_transforms_for(&fbx, &fbx_node.object, flip_fix_list);
+    let (translation, rotation) = transform::bone_transform(fbx, fbx_node.object.id, conversion);
     let first_frame = SmdAnimationFrameBone {
-        // This needs to be derived from the matrix to get the right location
         translation: translation.into(),
-        // This can just be directly copied over
         rotation: rotation.into(),
     };
     smd.set_animation(0, new_bone.id, first_frame);
 
-    // Make new matrices for children
-    let matrix = matrix * local_matrix;
-
     // Make sure the child nodes will receive this new bone
     for node in &fbx_node.nodes {
-        process_fbx_node(fbx, node, smd, &matrix, Some(&new_bone), flip_fix_list)?;
+        process_fbx_node(log, fbx, node, smd, Some(&new_bone), conversion, geometries)?;
     }
 
     Ok(())
 }
 
-/// Returns (Translation, Rotation)
-fn calculate_animation_transforms_for(
-    fbx: &SimpleFbx, obj: &Object, flip_fix_list: &Vec<String>,
-) -> (Vector3<f32>, Vector3<f32>) {
-    let properties = ModelProperties::from_generic(&obj.properties);
-
-    // Get the bone's translation
-    let parent_after_rot_translation = calculate_parent_after_rot_translation(fbx, obj);
-    let prop_translation: Vector3<_> = properties.translation.into();
-    let prop_rot_offset: Vector3<_> = properties.rotation_offset.into();
-    let prop_rot_pivot: Vector3<_> = properties.rotation_pivot.into();
-    let translation = parent_after_rot_translation + prop_translation + prop_rot_offset + prop_rot_pivot;
-
-    // Check if this bone's in the flip fix list
-    // TODO: Get an actual fix instead of this dirty manual hack
-    let flip = flip_fix_list.iter().any(|n| n == &id_name(&obj.name).unwrap());
-
-    // We want the rotation, but we've got multiple rotations, so combine them
-    let pre_rotation = Quaternion::from(Euler::new(
-        Deg(properties.pre_rotation[0]), Deg(properties.pre_rotation[1]), Deg(properties.pre_rotation[2])
-    ));
-    let rotation = Quaternion::from(Euler::new(
-        Deg(properties.rotation[0]), Deg(properties.rotation[1]), Deg(properties.rotation[2])
-    ));
-    let post_rotation = Quaternion::from(Euler::new(
-        Deg(properties.post_rotation[0]), Deg(properties.post_rotation[1]), Deg(properties.post_rotation[2])
-    ));
-
-    let total_rotation = if !flip {
-        Euler::from(post_rotation.invert() * rotation * pre_rotation)
-    } else {
-        Euler::from(post_rotation.invert() * rotation.invert() * pre_rotation)
-    };
-    let rotation = Vector3::new(
-        total_rotation.x.0,
-        total_rotation.y.0,
-        total_rotation.z.0,
-    );
-
-    (translation, rotation)
+/// Gets the matrix that moves a geometry's vertices into SMD space.
+fn geometry_matrix(fbx: &SimpleFbx, geometry: ObjectId, conversion: &Conversion) -> Matrix4<f32> {
+    match geometry_model(fbx, geometry) {
+        Some(model) => conversion.matrix() *
+            transform::global_matrix(fbx, model) *
+            geometric_matrix(fbx, geometry),
+        None => conversion.matrix(),
+    }
 }
 
-fn calculate_parent_after_rot_translation(fbx: &SimpleFbx, obj: &Object) -> Vector3<f32> {
-    // First actually get the parent's model data
-    let parent_obj = if let Some(v) = fbx.parent_of(obj.id) {
-        if v == 0 {
-            // At root, no extra translation
-            return Vector3::new(0.0, 0.0, 0.0)
-        }
-        v
-    } else {
-        // No parent, no extra translation
-        return Vector3::new(0.0, 0.0, 0.0)
-    };
-    let props = ModelProperties::from_generic(&fbx.objects[&parent_obj].properties);
-
-    // Now add up all the translations applied after rotation
-    let rotation_pivot: Vector3<_> = props.rotation_pivot.into();
-    let scale_offset: Vector3<_> = props.scale_offset.into();
-    let translation = -rotation_pivot + scale_offset;
-
-    translation
+/// Gets the transformation of a geometry relative to the model it's attached to.
+fn geometric_matrix(fbx: &SimpleFbx, geometry: ObjectId) -> Matrix4<f32> {
+    match geometry_model(fbx, geometry) {
+        Some(model) => transform::geometric_matrix(
+            &ModelProperties::from_generic(&fbx.objects[&model].properties)
+        ),
+        None => Matrix4::identity(),
+    }
 }
 
-fn local_matrices(properties: &ModelProperties) -> Matrix4<f32> {
-    // Create various matrices
-    let rotation_offset = properties.rotation_offset.into();
-    let rotation_offset_mat = Matrix4::from_translation(rotation_offset);
-    let rotation_pivot: Vector3<_> = properties.rotation_pivot.into();
-    let rotation_pivot_mat = Matrix4::from_translation(rotation_pivot);
-
-    let pre_rotation = euler_rotation_to_matrix(properties.pre_rotation);
-    let rotation = euler_rotation_to_matrix(properties.rotation);
-    let post_rotation = euler_rotation_to_matrix(properties.post_rotation);
-
-    let scale_offset = properties.scale_offset.into();
-    let scale_offset_mat = Matrix4::from_translation(scale_offset);
-    let scale_pivot: Vector3<_> = properties.scale_pivot.into();
-    let scale_pivot_mat = Matrix4::from_translation(scale_pivot);
-
-    let scale = Matrix4::from_nonuniform_scale(
-        properties.scale[0],
-        properties.scale[1],
-        properties.scale[2]
-    );
-
-    let local_matrix_for_vertices =
-        Matrix4::from_translation(properties.translation.into()) *
-
-        // Rotation
-        rotation_offset_mat *
-        rotation_pivot_mat *
-        pre_rotation *
-        rotation *
-        post_rotation.invert().unwrap() *
-        rotation_pivot_mat.invert().unwrap() *
-
-        // Scale
-        scale_offset_mat *
-        scale_pivot_mat *
-        scale *
-        scale_pivot_mat.invert().unwrap();
-
-    local_matrix_for_vertices
+fn geometry_model(fbx: &SimpleFbx, geometry: ObjectId) -> Option<ObjectId> {
+    fbx.parent_of(geometry).filter(|model| *model != 0)
 }
 
-fn euler_rotation_to_matrix(rot_degs: [f32; 3]) -> Matrix4<f32> {
-    Matrix4::from_angle_z(Deg(rot_degs[2])) *
-    Matrix4::from_angle_y(Deg(rot_degs[1])) *
-    Matrix4::from_angle_x(Deg(rot_degs[0]))
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+    use sotolib_fbx::ObjectTreeNode;
+    use sotolib_fbx::simple::{Cluster, Geometry, Model, ObjectType, Skin, SimpleFbx};
+    use sotolib_smd::{Smd, SmdLink};
+    use conversion::Conversion;
+    use task;
+    use transform::tests::vectors;
+    use super::{blend_influences, control_point_skins};
+
+    #[test]
+    fn it_keeps_the_strongest_links() {
+        let link = |bone, weight| (
+            SmdLink { bone, weight },
+            Matrix4::from_translation(Vector3::new(bone as f32, 0.0, 0.0)),
+        );
+
+        let skin = blend_influences(vec!(link(0, 0.1), link(1, 0.4), link(2, 0.2), link(3, 0.3)));
+
+        let bones: Vec<_> = skin.links.iter().map(|l| l.bone).collect();
+        let total: f32 = skin.links.iter().map(|l| l.weight).sum();
+        assert!(bones == [1, 3, 2]);
+        assert!((total - 1.0).abs() < 1e-6);
+        assert!((skin.matrix.w.x - 17.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn it_moves_skinned_control_points_with_their_bones() {
+        let mut fbx = SimpleFbx::new();
+        let mesh = fbx.new_object(ObjectType::Model(Model::default()));
+        let geometry = fbx.new_object(ObjectType::Geometry(Geometry::default()));
+        let skin = fbx.new_object(ObjectType::Skin(Skin::default()));
+        let bone = fbx.new_object(ObjectType::Model(Model::default()));
+
+        // The bone was bound 5 up, and has been moved 10 to the side since
+        let mut cluster = Cluster {
+            indexes: vec!(0),
+            weights: vec!(1.0),
+            ..Cluster::default()
+        };
+        cluster.transform_link[13] = 5.0;
+        let cluster = fbx.new_object(ObjectType::Cluster(cluster));
+        fbx.objects.get_mut(&bone).unwrap().name = "bone".into();
+        fbx.objects.get_mut(&bone).unwrap().properties =
+            vectors(&[("Lcl Translation", [10.0, 5.0, 0.0])]);
+
+        fbx.connect_parent_child(0, mesh);
+        fbx.connect_parent_child(0, bone);
+        fbx.connect_parent_child(mesh, geometry);
+        fbx.connect_parent_child(geometry, skin);
+        fbx.connect_parent_child(skin, cluster);
+        fbx.connect_parent_child(cluster, bone);
+
+        let tree = ObjectTreeNode::from_simple(&fbx);
+        let geometry_node = tree.nodes.iter()
+            .flat_map(|n| n.nodes.iter())
+            .find(|n| n.object.id == geometry)
+            .unwrap();
+        let mut smd = Smd::new();
+        smd.new_bone("bone", None);
+        let conversion = Conversion::new(&fbx.global_settings, &task::Model {
+            reference: PathBuf::new(),
+            scale: Some(1.0),
+            up_axis: None,
+            front_axis: None,
+        }).unwrap();
+
+        let skins = control_point_skins(&fbx, &smd, geometry_node, &conversion).unwrap();
+
+        let point = skins[&0].matrix * Vector4::new(1.0, 5.0, 0.0, 1.0);
+        let expected = conversion.matrix() * Vector4::new(11.0, 5.0, 0.0, 1.0);
+        assert!((point - expected).magnitude2() < 1e-8);
+    }
 }
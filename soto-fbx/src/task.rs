@@ -1,27 +1,737 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use soto::task::{TaskError, TaskErrorKind};
+use soto::Error;
+
+use physics::{self, Decomposition, Limits};
 
 #[derive(Deserialize)]
 pub struct Prop {
     pub name: String,
     pub dynamic: String,
+    /// The surface property of the model, defaults to "default".
+    pub surface_prop: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Model {
     pub reference: PathBuf,
-    pub flip_fix_list: Vec<String>,
+    /// Scale from FBX units to inches, overrides the unit scale in the FBX files.
+    pub scale: Option<f32>,
+    /// Up axis of the FBX files, like "Y" or "-Z", overrides the axis system in the files.
+    pub up_axis: Option<String>,
+    /// Front axis of the FBX files, like "Z" or "-Y", overrides the axis system in the files.
+    pub front_axis: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Materials {
+    /// Directories $cdmaterials should point to, relative to the game's materials directory.
+    pub cdmaterials: Option<Vec<String>>,
+    /// Name materials after their diffuse texture's file name instead of the FBX material name.
+    pub use_texture_names: Option<bool>,
+    /// Renames materials, from the name in the FBX to the name used in the SMD.
+    pub remap: Option<HashMap<String, String>>,
 }
 
 #[derive(Deserialize)]
 pub struct Sequence {
     pub file: PathBuf,
+    /// Name of the animation stack (take) in the file to use, defaults to the first one.
+    pub take: Option<String>,
+    /// Frame rate to sample the animation at, defaults to the frame rate of the FBX or 30 if it
+    /// doesn't have one.
+    pub fps: Option<f32>,
     pub params: String,
 }
 
+/// A $bodygroup, of which one option is shown at a time.
+#[derive(Deserialize)]
+pub struct BodyGroup {
+    pub name: String,
+    pub options: Vec<BodyGroupOption>,
+}
+
+#[derive(Deserialize)]
+pub struct BodyGroupOption {
+    /// FBX file with the mesh to show, the option is blank if not given.
+    pub file: Option<PathBuf>,
+}
+
+/// A $texturegroup, every skin replaces the materials of the first skin with its own.
+#[derive(Deserialize)]
+pub struct TextureGroup {
+    /// Name of the texture group, defaults to "skinfamilies".
+    pub name: Option<String>,
+    pub skins: Vec<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct Attachment {
+    pub name: String,
+    pub bone: String,
+    #[serde(default)]
+    pub position: [f32; 3],
+    /// Rotation in pitch, yaw and roll.
+    pub rotation: Option<[f32; 3]>,
+    /// Position the attachment relative to the model instead of the bone.
+    pub absolute: Option<bool>,
+    /// Keep the attachment attached to the bone, without being affected by other bones.
+    pub rigid: Option<bool>,
+}
+
+/// A $hboxset, the first set is the one the model uses by default.
+#[derive(Deserialize)]
+pub struct HitboxSet {
+    pub name: String,
+    pub boxes: Vec<Hitbox>,
+}
+
+#[derive(Deserialize)]
+pub struct Hitbox {
+    /// The hit group, telling the game which part of the model got hit.
+    #[serde(default)]
+    pub group: u32,
+    pub bone: String,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// A $lod, replacing meshes with simpler ones when the model is further away.
+#[derive(Deserialize)]
+pub struct Lod {
+    /// The screen-space threshold at which this LOD is used.
+    pub threshold: f32,
+    /// FBX file replacing the reference model.
+    pub file: Option<PathBuf>,
+    /// Replaces the meshes of body group options, from the option's FBX file to the LOD's.
+    pub replace: Option<HashMap<PathBuf, PathBuf>>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+#[derive(Deserialize)]
+pub struct IllumPosition {
+    pub position: [f32; 3],
+    /// The bone the position is relative to.
+    pub bone: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Include {
+    /// The QC file to include, relative to the project.
+    pub file: PathBuf,
+}
+
+/// How the $collisionmodel is built.
+#[derive(Deserialize, Default)]
+pub struct Collision {
+    /// FBX file with the collision mesh, defaults to the reference model's file.
+    pub file: Option<PathBuf>,
+    /// Only use the meshes of models with a name starting with one of these, like "UCX_". These
+    /// meshes are left out of the model's other meshes.
+    pub prefixes: Option<Vec<String>>,
+    /// Mass in kilograms, defaults to 1.
+    pub mass: Option<f32>,
+    pub inertia: Option<f32>,
+    pub damping: Option<f32>,
+    pub rot_damping: Option<f32>,
+    /// Allow the collision mesh to be made of multiple convex pieces, defaults to true. Otherwise
+    /// the collision mesh is a single convex hull.
+    pub concave: Option<bool>,
+    /// The maximum amount of convex pieces studiomdl accepts for a concave collision mesh.
+    pub max_convex_pieces: Option<u32>,
+    /// Split the collision mesh into convex pieces before handing it to studiomdl, instead of
+    /// leaving it to studiomdl to turn every connected part into a convex piece.
+    pub decompose: Option<bool>,
+    /// How much of a convex piece can be empty space before it gets split, as a fraction of the
+    /// volume of the part of the mesh it's in.
+    pub max_concavity: Option<f32>,
+    /// Amount of voxels along the longest side of the mesh used to find pieces.
+    pub resolution: Option<u32>,
+    /// The maximum amount of vertices in a convex piece, only checked if given or when
+    /// decomposing.
+    pub max_hull_vertices: Option<u32>,
+}
+
+impl Collision {
+    pub fn is_concave(&self) -> bool {
+        self.concave.unwrap_or(true)
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            concave: self.is_concave(),
+            max_pieces: self.max_convex_pieces
+                .unwrap_or(physics::DEFAULT_MAX_CONVEX_PIECES) as usize,
+            max_hull_vertices: self.max_hull_vertices.map(|v| v as usize),
+        }
+    }
+
+    /// The decomposition settings, if the collision mesh should be decomposed.
+    pub fn decomposition(&self) -> Option<Decomposition> {
+        if !self.decompose.unwrap_or(false) {
+            return None;
+        }
+
+        // Decomposed pieces always get simplified, so they need a vertex limit
+        let mut limits = self.limits();
+        let max_hull_vertices = self.max_hull_vertices
+            .unwrap_or(physics::DEFAULT_MAX_HULL_VERTICES);
+        limits.max_hull_vertices = Some(max_hull_vertices as usize);
+
+        Some(Decomposition {
+            limits,
+            max_concavity: self.max_concavity.unwrap_or(physics::DEFAULT_MAX_CONCAVITY) as f64,
+            resolution: self.resolution.unwrap_or(physics::DEFAULT_RESOLUTION) as usize,
+        })
+    }
+
+    /// The filter for the collision mesh.
+    pub fn filter(&self) -> MeshFilter<'_> {
+        match self.prefixes {
+            Some(ref prefixes) => MeshFilter::Only(prefixes),
+            None => MeshFilter::All,
+        }
+    }
+
+    /// The filter for the other meshes, which shouldn't include collision meshes.
+    pub fn visual_filter(&self) -> MeshFilter<'_> {
+        match self.prefixes {
+            Some(ref prefixes) => MeshFilter::Except(prefixes),
+            None => MeshFilter::All,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(ref prefixes) = self.prefixes {
+            if prefixes.is_empty() || prefixes.iter().any(|p| p.is_empty()) {
+                return Err(invalid("Collision prefixes can't be empty".into()));
+            }
+        }
+
+        let positive = [("mass", self.mass), ("inertia", self.inertia)];
+        for &(name, value) in &positive {
+            if value.map(|v| v <= 0.0).unwrap_or(false) {
+                return Err(invalid(format!("Collision {} has to be above 0", name)));
+            }
+        }
+        let not_negative = [("damping", self.damping), ("rot_damping", self.rot_damping)];
+        for &(name, value) in &not_negative {
+            if value.map(|v| v < 0.0).unwrap_or(false) {
+                return Err(invalid(format!("Collision {} can't be below 0", name)));
+            }
+        }
+
+        if self.max_concavity.map(|v| v < 0.0).unwrap_or(false) {
+            return Err(invalid("Collision max_concavity can't be below 0".into()));
+        }
+        if self.resolution.map(|v| !(4..=128).contains(&v)).unwrap_or(false) {
+            return Err(invalid("Collision resolution has to be between 4 and 128".into()));
+        }
+        if self.max_hull_vertices.map(|v| v < 4).unwrap_or(false) {
+            return Err(invalid("Collision max_hull_vertices has to be at least 4".into()));
+        }
+
+        match self.max_convex_pieces {
+            Some(0) => Err(invalid("Collision max_convex_pieces has to be above 0".into())),
+            Some(_) if !self.is_concave() => Err(invalid(
+                "Collision max_convex_pieces can only be used with a concave collision mesh".into()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which meshes in an FBX end up in an SMD, by the name of the model they belong to.
+#[derive(Clone, Copy)]
+pub enum MeshFilter<'a> {
+    All,
+    /// Only meshes of models with a name starting with one of the prefixes.
+    Only(&'a [String]),
+    /// All meshes except the ones of models with a name starting with one of the prefixes.
+    Except(&'a [String]),
+}
+
+impl<'a> MeshFilter<'a> {
+    pub fn includes(&self, model: &str) -> bool {
+        let matches = |prefixes: &[String]| prefixes.iter().any(|p| model.starts_with(p.as_str()));
+        match *self {
+            MeshFilter::All => true,
+            MeshFilter::Only(prefixes) => matches(prefixes),
+            MeshFilter::Except(prefixes) => !matches(prefixes),
+        }
+    }
+}
+
+/// A value in $keyvalues, tables become nested sections.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum KeyValue {
+    Section(BTreeMap<String, KeyValue>),
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
 #[derive(Deserialize)]
 pub struct SotoFbxTask {
     pub prop: Prop,
     pub model: Model,
-    pub sequences: Option<HashMap<String, Sequence>>,
+    #[serde(default)]
+    pub materials: Materials,
+    pub sequences: Option<BTreeMap<String, Sequence>>,
+    #[serde(default)]
+    pub bodygroups: Vec<BodyGroup>,
+    #[serde(default)]
+    pub texture_groups: Vec<TextureGroup>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub hitbox_sets: Vec<HitboxSet>,
+    #[serde(default)]
+    pub lods: Vec<Lod>,
+    pub keyvalues: Option<BTreeMap<String, KeyValue>>,
+    pub illum_position: Option<IllumPosition>,
+    /// The $cbox, the box used for collisions with the world.
+    pub clipping_box: Option<Bounds>,
+    /// The $bbox, the box used for visibility.
+    pub bounding_box: Option<Bounds>,
+    #[serde(default)]
+    pub includes: Vec<Include>,
+    #[serde(default)]
+    pub collision: Collision,
+}
+
+impl SotoFbxTask {
+    /// Makes all file paths in the task relative to a directory.
+    pub fn resolve_paths(&mut self, directory: &Path) {
+        self.model.reference = directory.join(&self.model.reference);
+        for sequence in self.sequences.iter_mut().flat_map(|s| s.values_mut()) {
+            sequence.file = directory.join(&sequence.file);
+        }
+        for option in self.bodygroups.iter_mut().flat_map(|b| b.options.iter_mut()) {
+            option.file = option.file.as_ref().map(|f| directory.join(f));
+        }
+        for lod in &mut self.lods {
+            lod.file = lod.file.as_ref().map(|f| directory.join(f));
+            lod.replace = lod.replace.as_ref().map(|r| r.iter()
+                .map(|(from, to)| (directory.join(from), directory.join(to)))
+                .collect());
+        }
+        for include in &mut self.includes {
+            include.file = directory.join(&include.file);
+        }
+        self.collision.file = self.collision.file.as_ref().map(|f| directory.join(f));
+    }
+
+    /// The FBX files besides the reference and sequences that need to be turned into SMDs, in
+    /// the order they're first used.
+    pub fn meshes(&self) -> Vec<&PathBuf> {
+        let bodygroups = self.bodygroups.iter()
+            .flat_map(|b| b.options.iter())
+            .filter_map(|o| o.file.as_ref());
+        let lods = self.lods.iter().flat_map(|l| {
+            let replaced = l.replace.iter().flat_map(|r| r.values());
+            l.file.iter().chain(replaced)
+        });
+
+        let mut meshes = Vec::new();
+        for mesh in bodygroups.chain(lods) {
+            if !meshes.contains(&mesh) {
+                meshes.push(mesh);
+            }
+        }
+        meshes
+    }
+
+    /// Checks if the QC sections make sense, before any work is done.
+    pub fn validate(&self) -> Result<(), Error> {
+        // The model name decides where studiomdl puts the model, it has to stay in models
+        check_name("Model", &self.prop.name)?;
+        let model_path = self.prop.name.replace('\\', "/");
+        let is_relative = Path::new(&model_path).components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !is_relative {
+            return Err(invalid(format!(
+                "Model name \"{}\" has to be a relative path without \"..\"", self.prop.name
+            )));
+        }
+
+        if let Some(ref surface_prop) = self.prop.surface_prop {
+            check_name("Surface prop", surface_prop)?;
+        }
+
+        // Sequence names are written into the QC unquoted, and are part of their SMD's file name
+        for (name, sequence) in self.sequences.iter().flat_map(|s| s.iter()) {
+            check_name("Sequence", name)?;
+            if name.contains(|c: char| c.is_whitespace() || c == '/' || c == '\\') {
+                return Err(invalid(format!(
+                    "Sequence \"{}\" can't contain whitespace or slashes", name
+                )));
+            }
+
+            // The fps is always written, it has to match the rate the animation is sampled at
+            if sequence.params.split_whitespace().any(|p| p.eq_ignore_ascii_case("fps")) {
+                return Err(invalid(format!(
+                    "Sequence \"{}\" sets fps in its params, use its fps key instead", name
+                )));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for bodygroup in &self.bodygroups {
+            check_name("Body group", &bodygroup.name)?;
+            check_unique("body group", &bodygroup.name, &mut names)?;
+            if bodygroup.options.is_empty() {
+                return Err(invalid(format!("Body group \"{}\" has no options", bodygroup.name)));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for group in &self.texture_groups {
+            let name = group.name.as_deref().unwrap_or("skinfamilies");
+            check_name("Texture group", name)?;
+            check_unique("texture group", name, &mut names)?;
+
+            let materials = group.skins.first().map(|s| s.len()).unwrap_or(0);
+            if materials == 0 {
+                return Err(invalid(format!("Texture group \"{}\" has no materials", name)));
+            }
+            if group.skins.iter().any(|s| s.len() != materials) {
+                return Err(invalid(format!(
+                    "Skins in texture group \"{}\" have to replace the same amount of materials",
+                    name
+                )));
+            }
+            for material in group.skins.iter().flat_map(|s| s.iter()) {
+                check_name("Material", material)?;
+            }
+        }
+
+        let mut names = HashSet::new();
+        for attachment in &self.attachments {
+            check_name("Attachment", &attachment.name)?;
+            check_unique("attachment", &attachment.name, &mut names)?;
+            check_name("Bone", &attachment.bone)?;
+        }
+
+        let mut names = HashSet::new();
+        for set in &self.hitbox_sets {
+            check_name("Hitbox set", &set.name)?;
+            check_unique("hitbox set", &set.name, &mut names)?;
+            for hitbox in &set.boxes {
+                check_name("Bone", &hitbox.bone)?;
+                check_bounds(&format!("Hitbox on \"{}\"", hitbox.bone), hitbox.min, hitbox.max)?;
+            }
+        }
+
+        let options: HashSet<_> = self.bodygroups.iter()
+            .flat_map(|b| b.options.iter())
+            .filter_map(|o| o.file.as_ref())
+            .collect();
+        let mut thresholds = Vec::new();
+        for lod in &self.lods {
+            if lod.threshold <= 0.0 {
+                return Err(invalid(format!("LOD threshold {} isn't above 0", lod.threshold)));
+            }
+            if thresholds.contains(&lod.threshold) {
+                return Err(invalid(format!("Multiple LODs use threshold {}", lod.threshold)));
+            }
+            thresholds.push(lod.threshold);
+
+            for from in lod.replace.iter().flat_map(|r| r.keys()) {
+                if !options.contains(from) {
+                    return Err(invalid(format!(
+                        "LOD {} replaces \"{}\", which isn't a body group option",
+                        lod.threshold, from.display()
+                    )));
+                }
+            }
+        }
+
+        if let Some(ref keyvalues) = self.keyvalues {
+            check_keyvalues(keyvalues)?;
+        }
+        if let Some(bone) = self.illum_position.as_ref().and_then(|i| i.bone.as_ref()) {
+            check_name("Bone", bone)?;
+        }
+        if let Some(bounds) = self.clipping_box {
+            check_bounds("Clipping box", bounds.min, bounds.max)?;
+        }
+        if let Some(bounds) = self.bounding_box {
+            check_bounds("Bounding box", bounds.min, bounds.max)?;
+        }
+        self.collision.validate()?;
+
+        Ok(())
+    }
+
+    /// The bones the QC refers to.
+    fn bones(&self) -> Vec<&str> {
+        let attachments = self.attachments.iter().map(|a| a.bone.as_str());
+        let hitboxes = self.hitbox_sets.iter()
+            .flat_map(|s| s.boxes.iter())
+            .map(|h| h.bone.as_str());
+        let illum = self.illum_position.iter().filter_map(|i| i.bone.as_deref());
+        attachments.chain(hitboxes).chain(illum).collect()
+    }
+
+    /// Checks if the bones the QC refers to exist in the model.
+    pub fn check_bones(&self, bones: &HashSet<&str>) -> Result<(), Error> {
+        match self.bones().into_iter().find(|b| !bones.contains(b)) {
+            Some(bone) => Err(invalid(format!("Bone \"{}\" doesn't exist in the model", bone))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn check_keyvalues(section: &BTreeMap<String, KeyValue>) -> Result<(), Error> {
+    for (key, value) in section {
+        check_name("Key", key)?;
+        match *value {
+            KeyValue::Section(ref section) => check_keyvalues(section)?,
+            KeyValue::String(ref value) => check_value("Value", value)?,
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+/// Names are written quoted into the QC, so they can't contain quotes or be empty.
+fn check_name(what: &str, name: &str) -> Result<(), Error> {
+    if name.is_empty() {
+        return Err(invalid(format!("{} name can't be empty", what)));
+    }
+    check_value(what, name)
+}
+
+fn check_value(what: &str, value: &str) -> Result<(), Error> {
+    if value.contains(&['"', '\n', '\r'][..]) {
+        return Err(invalid(format!(
+            "{} \"{}\" can't contain quotes or line breaks", what, value.escape_default()
+        )));
+    }
+    Ok(())
+}
+
+fn check_unique<'a>(what: &str, name: &'a str, names: &mut HashSet<&'a str>) -> Result<(), Error> {
+    if !names.insert(name) {
+        return Err(invalid(format!("There are multiple {}s named \"{}\"", what, name)));
+    }
+    Ok(())
+}
+
+fn check_bounds(what: &str, min: [f32; 3], max: [f32; 3]) -> Result<(), Error> {
+    if (0..3).any(|i| min[i] > max[i]) {
+        return Err(invalid(format!(
+            "{} has a minimum {:?} that's larger than its maximum {:?}", what, min, max
+        )));
+    }
+    Ok(())
+}
+
+fn invalid(message: String) -> Error {
+    Error::Runner(TaskError::new(TaskErrorKind::InvalidTask, message))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashSet;
+    use soto::task::TaskErrorKind;
+    use soto::Error;
+    use toml;
+    use super::{MeshFilter, SotoFbxTask};
+
+    /// Parses a task, adding the sections every task needs in front of the given ones.
+    pub fn task(sections: &str) -> SotoFbxTask {
+        let toml = format!(
+            "[prop]\nname = \"props/crate\"\ndynamic = \"dynamic\"\n\
+             [model]\nreference = \"crate.fbx\"\n{}",
+            sections
+        );
+        toml::from_str(&toml).unwrap()
+    }
+
+    pub fn assert_invalid(sections: &str) {
+        match task(sections).validate() {
+            Err(Error::Runner(ref error)) if error.kind == TaskErrorKind::InvalidTask => {},
+            Err(error) => panic!("Expected an invalid task error, got \"{}\"", error),
+            Ok(()) => panic!("Expected the task to be invalid:\n{}", sections),
+        }
+    }
+
+    #[test]
+    fn it_accepts_valid_tasks() {
+        let task = task(r#"
+            [sequences.run]
+            file = "run.fbx"
+            params = "loop"
+
+            [[bodygroups]]
+            name = "lid"
+            options = [{ file = "lid.fbx" }, {}]
+
+            [[texture_groups]]
+            skins = [["crate", "lid"], ["crate_dirty", "lid_dirty"]]
+
+            [[hitbox_sets]]
+            name = "default"
+            boxes = [{ bone = "root", min = [-8.0, -8.0, 0.0], max = [8.0, 8.0, 16.0] }]
+
+            [[lods]]
+            threshold = 10.0
+            replace = { "lid.fbx" = "lid_lod1.fbx" }
+        "#);
+
+        task.validate().unwrap();
+    }
+
+    #[test]
+    fn it_rejects_mismatched_skins() {
+        assert_invalid(r#"
+            [[texture_groups]]
+            skins = [["crate", "lid"], ["crate_dirty"]]
+        "#);
+    }
+
+    #[test]
+    fn it_rejects_duplicate_names() {
+        assert_invalid(r#"
+            [[bodygroups]]
+            name = "lid"
+            options = [{}]
+            [[bodygroups]]
+            name = "lid"
+            options = [{}]
+        "#);
+        assert_invalid(r#"
+            [[attachments]]
+            name = "top"
+            bone = "root"
+            [[attachments]]
+            name = "top"
+            bone = "lid"
+        "#);
+        assert_invalid(r#"
+            [[hitbox_sets]]
+            name = "default"
+            boxes = []
+            [[hitbox_sets]]
+            name = "default"
+            boxes = []
+        "#);
+    }
+
+    #[test]
+    fn it_rejects_lods_replacing_unknown_options() {
+        assert_invalid(r#"
+            [[bodygroups]]
+            name = "lid"
+            options = [{ file = "lid.fbx" }]
+
+            [[lods]]
+            threshold = 10.0
+            replace = { "handle.fbx" = "handle_lod1.fbx" }
+        "#);
+    }
+
+    #[test]
+    fn it_rejects_quotes_and_line_breaks_in_names() {
+        assert_invalid(r#"
+            [[attachments]]
+            name = "the \"top\""
+            bone = "root"
+        "#);
+        assert_invalid(r#"
+            [[bodygroups]]
+            name = "lid\n$include \"evil.qci\""
+            options = [{}]
+        "#);
+        assert_invalid(r#"
+            [keyvalues.prop_data]
+            base = "Wooden\"Small"
+        "#);
+    }
+
+    #[test]
+    fn it_rejects_inverted_bounds() {
+        assert_invalid(r#"
+            [[hitbox_sets]]
+            name = "default"
+            boxes = [{ bone = "root", min = [8.0, -8.0, 0.0], max = [-8.0, 8.0, 16.0] }]
+        "#);
+        assert_invalid(r#"
+            [clipping_box]
+            min = [0.0, 0.0, 16.0]
+            max = [8.0, 8.0, 0.0]
+        "#);
+    }
+
+    #[test]
+    fn it_rejects_invalid_sequence_names() {
+        for name in &["run fast", "run\\\"fast", "run/fast"] {
+            assert_invalid(&format!(
+                "[sequences.\"{}\"]\nfile = \"run.fbx\"\nparams = \"loop\"", name
+            ));
+        }
+    }
+
+    #[test]
+    fn it_rejects_frame_rates_in_sequence_params() {
+        assert_invalid(r#"
+            [sequences.run]
+            file = "run.fbx"
+            params = "loop FPS 30"
+        "#);
+        task(r#"
+            [sequences.run]
+            file = "run.fbx"
+            fps = 30.0
+            params = "loop"
+        "#).validate().unwrap();
+    }
+
+    #[test]
+    fn it_checks_bones_exist() {
+        let task = task(r#"
+            [[attachments]]
+            name = "top"
+            bone = "lid"
+        "#);
+
+        let mut bones = HashSet::new();
+        bones.insert("root");
+        assert!(task.check_bones(&bones).is_err());
+        bones.insert("lid");
+        assert!(task.check_bones(&bones).is_ok());
+    }
+
+    #[test]
+    fn it_filters_meshes_by_prefix() {
+        let prefixes = vec!["UCX_".to_string(), "COL_".to_string()];
+
+        assert!(MeshFilter::All.includes("UCX_Crate"));
+        assert!(MeshFilter::Only(&prefixes).includes("UCX_Crate"));
+        assert!(MeshFilter::Only(&prefixes).includes("COL_Lid"));
+        assert!(!MeshFilter::Only(&prefixes).includes("Crate"));
+        assert!(!MeshFilter::Only(&prefixes).includes("Crate_UCX_"));
+        assert!(!MeshFilter::Except(&prefixes).includes("UCX_Crate"));
+        assert!(MeshFilter::Except(&prefixes).includes("Crate"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_collision_settings() {
+        assert_invalid("[collision]\nmass = 0.0");
+        assert_invalid("[collision]\nmass = -1.0");
+        assert_invalid("[collision]\nconcave = false\nmax_convex_pieces = 4");
+        task("[collision]\nconcave = true\nmax_convex_pieces = 4").validate().unwrap();
+    }
 }
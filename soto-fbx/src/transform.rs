@@ -0,0 +1,304 @@
+use cgmath::{Matrix, Matrix3, Matrix4, Deg, Vector3, Vector4, SquareMatrix, InnerSpace};
+use sotolib_fbx::simple::{SimpleFbx, ObjectId, ModelProperties, RotationOrder, InheritType};
+
+use conversion::Conversion;
+
+/// Gets a model's transformation relative to its parent, using the full FBX transform chain:
+/// T * Roff * Rp * Rpre * R * Rpost^-1 * Rp^-1 * Soff * Sp * S * Sp^-1
+pub fn local_matrix(properties: &ModelProperties) -> Matrix4<f32> {
+    // Without an active rotation, the rotation order and extra rotations aren't used
+    let (rotation_order, pre_rotation, post_rotation) = if properties.rotation_active {
+        (properties.rotation_order, properties.pre_rotation, properties.post_rotation)
+    } else {
+        (RotationOrder::XYZ, [0.0; 3], [0.0; 3])
+    };
+
+    let rotation_offset = Matrix4::from_translation(properties.rotation_offset.into());
+    let rotation_pivot = Matrix4::from_translation(properties.rotation_pivot.into());
+    let scale_offset = Matrix4::from_translation(properties.scale_offset.into());
+    let scale_pivot = Matrix4::from_translation(properties.scale_pivot.into());
+
+    // Pre- and post-rotation always use XYZ, only the animated rotation uses the rotation order
+    let pre_rotation = euler_matrix(pre_rotation, RotationOrder::XYZ);
+    let rotation = euler_matrix(properties.rotation, rotation_order);
+    let post_rotation = euler_matrix(post_rotation, RotationOrder::XYZ);
+
+    Matrix4::from_translation(properties.translation.into()) *
+
+        // Rotation
+        rotation_offset *
+        rotation_pivot *
+        pre_rotation *
+        rotation *
+        post_rotation.invert().unwrap() *
+        rotation_pivot.invert().unwrap() *
+
+        // Scale
+        scale_offset *
+        scale_pivot *
+        scale_matrix(properties.scale) *
+        scale_pivot.invert().unwrap()
+}
+
+/// Gets the transformation of geometry attached to a model, relative to the model.
+pub fn geometric_matrix(properties: &ModelProperties) -> Matrix4<f32> {
+    Matrix4::from_translation(properties.geometric_translation.into()) *
+        euler_matrix(properties.geometric_rotation, RotationOrder::XYZ) *
+        scale_matrix(properties.geometric_scale)
+}
+
+/// Gets a model's transformation in the FBX file's space, inheriting from its parents the way
+/// their inherit types say it should.
+pub fn global_matrix(fbx: &SimpleFbx, id: ObjectId) -> Matrix4<f32> {
+    let properties = ModelProperties::from_generic(&fbx.objects[&id].properties);
+    let local = local_matrix(&properties);
+
+    let parent = match parent_model(fbx, id) {
+        Some(v) => v,
+        None => return local,
+    };
+    let parent_global = global_matrix(fbx, parent);
+
+    // The translation is always affected by the parent's full transformation, but depending on
+    // the inherit type the parent's scale is applied at different points
+    let parent_scale = match properties.inherit_type {
+        InheritType::RSrs => return parent_global * local,
+        InheritType::RrSs => decompose(&parent_global).2,
+        InheritType::Rrs => {
+            // Remove the parent's own scale, but keep the scale it inherited. A parent scaled to
+            // zero flattens everything inside it, so there's nothing left to inherit
+            let parent_properties = ModelProperties::from_generic(&fbx.objects[&parent].properties);
+            let own_scale: Vector3<f32> = parent_properties.scale.into();
+            let scale = decompose(&parent_global).2;
+            let unscale = |s: f32, own: f32| if own != 0.0 { s / own } else { 0.0 };
+            Vector3::new(
+                unscale(scale.x, own_scale.x),
+                unscale(scale.y, own_scale.y),
+                unscale(scale.z, own_scale.z),
+            )
+        },
+    };
+    let (_, parent_rotation, _) = decompose(&parent_global);
+    let (_, local_rotation, local_scale) = decompose(&local);
+    let translation = parent_global * local.w;
+
+    let rotation_scale = parent_rotation * local_rotation *
+        Matrix3::from_diagonal(parent_scale) * Matrix3::from_diagonal(local_scale);
+    let mut matrix = Matrix4::from(rotation_scale);
+    matrix.w = translation;
+    matrix
+}
+
+/// Gets a bone's translation and rotation relative to its parent bone in SMD space. SMD bones
+/// can't be scaled, so scale only affects the positions of the bones.
+/// Returns (Translation, Rotation), the rotation being Euler angles in radians.
+pub fn bone_transform(
+    fbx: &SimpleFbx, id: ObjectId, conversion: &Conversion
+) -> (Vector3<f32>, Vector3<f32>) {
+    let (translation, rotation, _) = decompose(&(conversion.matrix() * global_matrix(fbx, id)));
+
+    // Bones are relative to their parent bone, but bones at the root are in the SMD's space
+    let (translation, rotation) = match parent_model(fbx, id) {
+        Some(parent) => {
+            let parent_global = conversion.matrix() * global_matrix(fbx, parent);
+            let (parent_translation, parent_rotation, _) = decompose(&parent_global);
+
+            // The parent's rotation is orthonormal, so the transpose is the inverse
+            let inverse = parent_rotation.transpose();
+            (inverse * (translation - parent_translation), inverse * rotation)
+        },
+        None => (translation, rotation),
+    };
+
+    (translation, smd_euler(&rotation))
+}
+
+/// Turns a matrix stored in an FBX, which is column-major, into a matrix.
+pub fn fbx_matrix(values: &[f32; 16]) -> Matrix4<f32> {
+    let column = |i: usize| Vector4::new(values[i], values[i + 1], values[i + 2], values[i + 3]);
+    Matrix4::from_cols(column(0), column(4), column(8), column(12))
+}
+
+/// Gets the matrix to transform normals with for a matrix that transforms positions.
+pub fn normal_matrix(matrix: &Matrix4<f32>) -> Matrix3<f32> {
+    let matrix = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+    matrix.invert().map(|m| m.transpose()).unwrap_or(matrix)
+}
+
+/// Finds the model this model is attached to, if it's not at the root. Skinned bones are also
+/// children of their skin clusters, so only parents that are models count.
+fn parent_model(fbx: &SimpleFbx, id: ObjectId) -> Option<ObjectId> {
+    fbx.parents_of(id).into_iter()
+        .find(|p| fbx.objects.get(p).map(|o| o.class.type_name() == "Model").unwrap_or(false))
+}
+
+fn euler_matrix(rot_degs: [f32; 3], order: RotationOrder) -> Matrix4<f32> {
+    let axis_matrix = |axis: usize| match axis {
+        0 => Matrix4::from_angle_x(Deg(rot_degs[0])),
+        1 => Matrix4::from_angle_y(Deg(rot_degs[1])),
+        _ => Matrix4::from_angle_z(Deg(rot_degs[2])),
+    };
+
+    // The first axis in the order is applied first, so it ends up on the right
+    let axes = order.axes();
+    axis_matrix(axes[2]) * axis_matrix(axes[1]) * axis_matrix(axes[0])
+}
+
+fn scale_matrix(scale: [f32; 3]) -> Matrix4<f32> {
+    Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2])
+}
+
+/// Splits a matrix into a translation, a rotation and a scale, ignoring any shearing.
+fn decompose(matrix: &Matrix4<f32>) -> (Vector3<f32>, Matrix3<f32>, Vector3<f32>) {
+    let translation = matrix.w.truncate();
+    let mut x = matrix.x.truncate();
+    let y = matrix.y.truncate();
+    let z = matrix.z.truncate();
+    let mut scale = Vector3::new(x.magnitude(), y.magnitude(), z.magnitude());
+
+    // A mirrored matrix can't be a rotation, so move the mirroring into the scale
+    if x.cross(y).dot(z) < 0.0 {
+        x = -x;
+        scale.x = -scale.x;
+    }
+
+    let rotation = Matrix3::from_cols(
+        safe_normalize(x), safe_normalize(y), safe_normalize(z)
+    );
+    (translation, rotation, scale)
+}
+
+fn safe_normalize(vector: Vector3<f32>) -> Vector3<f32> {
+    if vector.magnitude2() > 0.0 {
+        vector.normalize()
+    } else {
+        vector
+    }
+}
+
+/// Gets the Euler angles studiomdl expects for a rotation, which are applied X first, then Y,
+/// then Z.
+fn smd_euler(rotation: &Matrix3<f32>) -> Vector3<f32> {
+    // Columns are the first index, rows the second
+    let sin_y = -rotation.x.z;
+    if sin_y.abs() < 0.9999 {
+        Vector3::new(
+            rotation.y.z.atan2(rotation.z.z),
+            sin_y.asin(),
+            rotation.x.y.atan2(rotation.x.x),
+        )
+    } else {
+        // Gimbal lock, X and Z rotate around the same axis so put it all in X
+        let half_pi = ::std::f32::consts::FRAC_PI_2;
+        if sin_y > 0.0 {
+            Vector3::new(rotation.y.x.atan2(rotation.y.y), half_pi, 0.0)
+        } else {
+            Vector3::new((-rotation.y.x).atan2(rotation.y.y), -half_pi, 0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use cgmath::{InnerSpace, Matrix3, Rad, Vector3, Vector4};
+    use sotolib_fbx::OwnedProperty;
+    use sotolib_fbx::simple::{
+        Cluster, Model, ModelProperties, ObjectType, Properties, Property, SimpleFbx,
+    };
+    use super::{global_matrix, local_matrix, smd_euler};
+
+    pub fn vectors(values: &[(&str, [f32; 3])]) -> Properties {
+        let mut properties = Properties::new();
+        for &(name, value) in values {
+            properties.insert(name.into(), Property {
+                name: name.into(),
+                values: value.iter().map(|v| OwnedProperty::F64(*v as f64)).collect(),
+            });
+        }
+        properties
+    }
+
+    fn is_close(a: Vector4<f32>, b: Vector4<f32>) -> bool {
+        (a - b).truncate().magnitude2() < 1e-8
+    }
+
+    #[test]
+    fn it_builds_local_matrices() {
+        let properties = ModelProperties::from_generic(&vectors(&[
+            ("Lcl Translation", [1.0, 2.0, 3.0]),
+            ("Lcl Rotation", [0.0, 0.0, 90.0]),
+            ("Lcl Scaling", [2.0, 2.0, 2.0]),
+        ]));
+
+        // Scaled first, then rotated, then translated
+        let point = local_matrix(&properties) * Vector4::new(1.0, 0.0, 0.0, 1.0);
+        assert!(is_close(point, Vector4::new(1.0, 4.0, 3.0, 1.0)));
+
+        // Rotation happens around the pivot
+        let properties = ModelProperties::from_generic(&vectors(&[
+            ("Lcl Rotation", [0.0, 0.0, 90.0]),
+            ("RotationPivot", [1.0, 0.0, 0.0]),
+        ]));
+        let point = local_matrix(&properties) * Vector4::new(2.0, 0.0, 0.0, 1.0);
+        assert!(is_close(point, Vector4::new(1.0, 1.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn it_inherits_transforms_from_parent_models() {
+        let mut fbx = SimpleFbx::new();
+        let parent = fbx.new_object(ObjectType::Model(Model::default()));
+        let child = fbx.new_object(ObjectType::Model(Model::default()));
+        let cluster = fbx.new_object(ObjectType::Cluster(Cluster::default()));
+        fbx.objects.get_mut(&parent).unwrap().properties =
+            vectors(&[("Lcl Translation", [10.0, 0.0, 0.0])]);
+        fbx.objects.get_mut(&child).unwrap().properties =
+            vectors(&[("Lcl Translation", [0.0, 5.0, 0.0])]);
+
+        // Skinned bones are children of their cluster as well, which shouldn't count as a parent
+        fbx.connect_parent_child(cluster, child);
+        fbx.connect_parent_child(parent, child);
+
+        let point = global_matrix(&fbx, child) * Vector4::new(0.0, 0.0, 0.0, 1.0);
+        assert!(is_close(point, Vector4::new(10.0, 5.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn it_inherits_from_parent_models_scaled_to_zero() {
+        let mut fbx = SimpleFbx::new();
+        let parent = fbx.new_object(ObjectType::Model(Model::default()));
+        let child = fbx.new_object(ObjectType::Model(Model::default()));
+        fbx.objects.get_mut(&parent).unwrap().properties =
+            vectors(&[("Lcl Scaling", [0.0, 1.0, 1.0])]);
+        let mut properties = vectors(&[("Lcl Translation", [1.0, 2.0, 3.0])]);
+        properties.insert("InheritType".into(), Property {
+            name: "InheritType".into(),
+            values: vec!(OwnedProperty::I32(2)),
+        });
+        fbx.objects.get_mut(&child).unwrap().properties = properties;
+        fbx.connect_parent_child(parent, child);
+
+        let point = global_matrix(&fbx, child) * Vector4::new(1.0, 1.0, 1.0, 1.0);
+        assert!(is_close(point, Vector4::new(0.0, 3.0, 4.0, 1.0)));
+    }
+
+    #[test]
+    fn it_converts_rotations_to_smd_euler_angles() {
+        // SMD angles are applied X first, then Y, then Z
+        let rotation = |angles: Vector3<f32>| {
+            Matrix3::from_angle_z(Rad(angles.z)) *
+                Matrix3::from_angle_y(Rad(angles.y)) *
+                Matrix3::from_angle_x(Rad(angles.x))
+        };
+
+        let angles = Vector3::new(0.3, -0.5, 1.2);
+        let result = smd_euler(&rotation(angles));
+        assert!((result - angles).magnitude2() < 1e-8);
+
+        // In gimbal lock the angles are different, but have to give the same rotation
+        let locked = rotation(Vector3::new(0.4, ::std::f32::consts::FRAC_PI_2, 0.2));
+        let result = rotation(smd_euler(&locked));
+        for column in 0..3 {
+            assert!((result[column] - locked[column]).magnitude2() < 1e-8);
+        }
+    }
+}
@@ -1,21 +1,223 @@
+extern crate clap;
+extern crate glob;
 extern crate soto;
 #[macro_use] extern crate slog;
 extern crate slog_term;
 
-use slog::DrainExt;
+use std::path::Path;
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use glob::Pattern;
+use slog::{DrainExt, Level, Logger};
+use soto::{ReportFormat, RunnerSource};
+
+/// Exit code used when something went wrong, like a task failing to build.
+const EXIT_FAILURE: i32 = 1;
 
 fn main() {
+    let path_arg = || Arg::with_name("path")
+        .help("The project directory")
+        .default_value(".");
+    let only_arg = || Arg::with_name("only")
+        .long("only").takes_value(true).value_name("GLOB")
+        .help("Only builds tasks with a path relative to the project matching the pattern");
+    let jobs_arg = || Arg::with_name("jobs")
+        .short("j").long("jobs").takes_value(true).value_name("N")
+        .help("The maximum amount of tasks to run at the same time");
+    let matches = App::new("soto")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Source Engine asset project manager and build tool")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("verbose")
+            .short("v").long("verbose").multiple(true).global(true)
+            .help("Shows more output, use twice to show everything"))
+        .arg(Arg::with_name("quiet")
+            .short("q").long("quiet").multiple(true).global(true)
+            .conflicts_with("verbose")
+            .help("Shows less output, use twice to only show errors"))
+        .subcommand(SubCommand::with_name("build")
+            .about("Builds all tasks in a project")
+            .arg(path_arg())
+            .arg(only_arg())
+            .arg(Arg::with_name("force")
+                .long("force")
+                .help("Builds tasks even if they're up to date"))
+            .arg(jobs_arg())
+            .arg(Arg::with_name("report")
+                .long("report").number_of_values(2).value_names(&["FORMAT", "FILE"])
+                .help("Writes a report of the build to a file, FORMAT is \"json\" or \"junit\"")))
+        .subcommand(SubCommand::with_name("watch")
+            .about("Builds a project, then rebuilds tasks whenever their files change")
+            .arg(path_arg())
+            .arg(only_arg())
+            .arg(jobs_arg()))
+        .subcommand(SubCommand::with_name("clean")
+            .about("Removes all intermediate and output files")
+            .arg(path_arg()))
+        .subcommand(SubCommand::with_name("list")
+            .about("Lists all tasks in a project and their runners")
+            .arg(path_arg()))
+        .subcommand(SubCommand::with_name("check")
+            .about("Validates the project and all tasks without running them")
+            .arg(path_arg()))
+        .subcommand(SubCommand::with_name("runners")
+            .about("Lists the runners a project uses and the versions they report")
+            .arg(path_arg()))
+        .subcommand(SubCommand::with_name("init")
+            .about("Creates the project files for a new project")
+            .arg(path_arg()))
+        .get_matches();
+
     // Initialize logging
+    let log = create_logger(&matches);
+
+    // Run the command we got told to run
+    let (name, sub_matches) = matches.subcommand();
+    let sub_matches = sub_matches.unwrap();
+    let directory = Path::new(sub_matches.value_of("path").unwrap());
+    let result = match name {
+        "build" => build(&log, directory, sub_matches),
+        "watch" => watch(&log, directory, sub_matches),
+        "clean" => soto::clean(&log, directory).map(|_| true),
+        "list" => list(&log, directory),
+        "check" => check(&log, directory),
+        "runners" => runners(&log, directory),
+        "init" => soto::init(&log, directory).map(|_| true),
+        _ => unreachable!(),
+    };
+
+    // Make sure the exit code reflects if we were successful
+    match result {
+        Ok(true) => {},
+        Ok(false) => process::exit(EXIT_FAILURE),
+        Err(e) => {
+            crit!(log, "Soto error: {}", e);
+            process::exit(EXIT_FAILURE);
+        },
+    }
+}
+
+fn create_logger(matches: &ArgMatches) -> Logger {
+    let level = match (matches.occurrences_of("verbose"), matches.occurrences_of("quiet")) {
+        (0, 0) => Level::Info,
+        (1, _) => Level::Debug,
+        (_, 0) => Level::Trace,
+        (_, 1) => Level::Warning,
+        (_, _) => Level::Error,
+    };
+
     let drain = slog_term::streamer()
         .use_custom_timestamp(|_| Ok(()))
         .compact()
-        .build().fuse();
-    let log = slog::Logger::root(drain, o!());
+        .build();
+    slog::Logger::root(slog::level_filter(level, drain).fuse(), o!())
+}
+
+/// Returns if all tasks built successfully.
+fn build(log: &Logger, directory: &Path, matches: &ArgMatches) -> Result<bool, soto::Error> {
     info!(log, "Running build using soto {}", env!("CARGO_PKG_VERSION"));
 
-    // Run actual build
-    match soto::build(&log, "./") {
-        Ok(_) => {},
-        Err(e) => println!("Soto error: {}", e),
+    // Check the report arguments before building, so a typo doesn't waste a build
+    let report_file = match matches.values_of("report") {
+        Some(mut values) => {
+            let format: ReportFormat = values.next().unwrap().parse()?;
+            Some((format, Path::new(values.next().unwrap())))
+        },
+        None => None,
+    };
+
+    let options = build_options(matches)?;
+    let report = soto::build(log, directory, &options)?;
+
+    if let Some((format, path)) = report_file {
+        report.write(path, format)?;
+        info!(log, "Wrote build report to \"{}\"", path.display());
     }
+
+    Ok(report.is_success())
+}
+
+/// Only returns if watching can't continue.
+fn watch(log: &Logger, directory: &Path, matches: &ArgMatches) -> Result<bool, soto::Error> {
+    info!(log, "Running watch using soto {}", env!("CARGO_PKG_VERSION"));
+
+    let options = build_options(matches)?;
+    soto::watch(log, directory, &options)?;
+    Ok(true)
+}
+
+fn build_options(matches: &ArgMatches) -> Result<soto::BuildOptions, soto::Error> {
+    let mut options = soto::BuildOptions::default();
+    options.force = matches.is_present("force");
+    if let Some(jobs) = matches.value_of("jobs") {
+        options.jobs = jobs.parse().map_err(|_| soto::Error::Task(format!(
+            "Invalid amount of jobs \"{}\"", jobs
+        )))?;
+    }
+    if let Some(only) = matches.value_of("only") {
+        options.only = Some(Pattern::new(only).map_err(|e| soto::Error::Task(format!(
+            "Invalid pattern \"{}\": {}", only, e
+        )))?);
+    }
+
+    Ok(options)
+}
+
+fn list(log: &Logger, directory: &Path) -> Result<bool, soto::Error> {
+    let mut success = true;
+    for task in soto::find_tasks(log, directory) {
+        match task {
+            Ok(task) => info!(
+                log, "{} (runner \"{}\")",
+                soto::relative_path(directory, &task.path).display(), task.soto.runner
+            ),
+            Err((path, e)) => {
+                error!(log, "{}: {}", soto::relative_path(directory, &path).display(), e);
+                success = false;
+            },
+        }
+    }
+
+    Ok(success)
+}
+
+fn check(log: &Logger, directory: &Path) -> Result<bool, soto::Error> {
+    let problems = soto::check(log, directory)?;
+    for &(ref path, ref problem) in &problems {
+        error!(log, "{}: {}", soto::relative_path(directory, path).display(), problem);
+    }
+
+    if problems.is_empty() {
+        info!(log, "No problems found");
+    }
+    Ok(problems.is_empty())
+}
+
+fn runners(log: &Logger, directory: &Path) -> Result<bool, soto::Error> {
+    let mut success = true;
+    for (name, runner) in soto::runners(log, directory)? {
+        match runner {
+            Ok(runner) => {
+                let source = match runner.source {
+                    RunnerSource::Search => "found in PATH".to_string(),
+                    RunnerSource::Path(_) => "from path".to_string(),
+                    RunnerSource::Binary(_) => "from project".to_string(),
+                    RunnerSource::Cargo(ref package) =>
+                        format!("built from \"{}\"", package.display()),
+                };
+                info!(
+                    log, "{}: {} {} at \"{}\" ({})",
+                    name, runner.hello.name, runner.hello.version, runner.executable.display(),
+                    source
+                );
+            },
+            Err(e) => {
+                error!(log, "{}: {}", name, e);
+                success = false;
+            },
+        }
+    }
+
+    Ok(success)
 }
@@ -1,76 +1,450 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
 use std::path::{Path, PathBuf};
+use std::mem;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Instant;
 
-use serde::Deserialize;
-use walkdir::WalkDir;
+use glob::Pattern;
+use serde_json;
+use toml::Value;
 use slog::Logger;
 
-use files::{SotoProjectFile, SotoLocalFile, SotoTaskFile};
-use task::{Task};
-use {read_toml, Error};
+use cache::{self, BuildCache, FingerprintInputs};
+use files::{SotoProjectFile, SotoLocalFile};
+use graph::{self, DependencyGraph};
+use project::{self, TaskFile, read_required};
+use report::{BuildReport, CaptureDrain, LogLine, TaskReport, TaskStatus};
+use runner::Runners;
+use task::{Task, TaskRunners, RunnerHandle};
+use Error;
 
-/// Build a project in a directory.
-pub fn build<P: Into<PathBuf>>(log: &Logger, directory: P) -> Result<(), Error> {
+/// Options that change how a project gets built.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Run every task, even if its inputs haven't changed since it last ran.
+    pub force: bool,
+    /// The maximum amount of tasks to run at the same time.
+    pub jobs: usize,
+    /// Only build tasks with a path relative to the project directory matching this pattern.
+    pub only: Option<Pattern>,
+    /// Only build these tasks, by path relative to the project directory.
+    pub tasks: Option<BTreeSet<PathBuf>>,
+    /// In-process runners, used instead of runner executables with the same name.
+    pub runners: TaskRunners,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            force: false,
+            jobs: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            only: None,
+            tasks: None,
+            runners: TaskRunners::default(),
+        }
+    }
+}
+
+/// State shared between all tasks in a build.
+struct BuildContext<'a> {
+    directory: PathBuf,
+    project: SotoProjectFile,
+    local: SotoLocalFile,
+    options: &'a BuildOptions,
+    cache: BuildCache,
+    cache_path: PathBuf,
+    runners: Runners,
+    /// Runner versions by runner, so every runner only has to be looked up once.
+    runner_versions: HashMap<String, Option<String>>,
+}
+
+/// A task that needs to be run.
+struct PendingTask {
+    /// Index of the task in the dependency graph.
+    index: usize,
+    /// Path of the task file, as found in the project.
+    path: PathBuf,
+    task: Task,
+    log: Logger,
+    /// What got logged for the task so far, for the report.
+    lines: Arc<Mutex<Vec<LogLine>>>,
+    fingerprint: Option<String>,
+}
+
+/// What needs to happen with a task.
+enum TaskCheck {
+    Pending(Box<PendingTask>),
+    /// Nothing changed since the task last completed, with the task's fingerprint.
+    UpToDate(String),
+}
+
+/// Build a project in a directory. Errors in tasks don't stop the build, they're collected in
+/// the report instead.
+pub fn build<P: Into<PathBuf>>(
+    log: &Logger, directory: P, options: &BuildOptions
+) -> Result<BuildReport, Error> {
     let directory = directory.into();
 
     // Open up the project files
     let project: SotoProjectFile = read_required(&directory, "SoTo.toml")?;
     let local: SotoLocalFile = read_required(&directory, "SoTo.Local.toml")?;
 
-    // Walk the directory looking for files we need to do stuff to
-    for entry in WalkDir::new(directory) {
-        let entry = entry.unwrap();
+    // Load up what we know about previous builds
+    let cache_path = directory.join("target").join("cache.json");
+    let mut context = BuildContext {
+        directory: directory.clone(),
+        runners: Runners::new(&directory, &project),
+        project,
+        local,
+        options,
+        cache: BuildCache::load(&cache_path),
+        cache_path,
+        runner_versions: HashMap::new(),
+    };
+    let mut report = BuildReport::default();
+
+    // Find all the tasks in the project
+    let mut tasks = Vec::new();
+    for task in project::find_tasks(log, &directory) {
+        match task {
+            Ok(v) => tasks.push(v),
+            Err((path, e)) => {
+                let log = log.new(o!("file" => format!("{}", path.display())));
+                error!(log, "Error while reading task: {}", e);
+                let path = project::relative_path(&directory, &path).to_path_buf();
+                report.tasks.push(TaskReport::failed(path, None, format!("{}", e)));
+            },
+        }
+    }
+
+    // Find out which order the tasks have to be built in
+    let (graph, dependency_problems) = graph::resolve_dependencies(
+        &directory, &tasks, &context.cache
+    );
+    let order = graph.topological_order()
+        .map_err(|cycle| graph::cycle_error(&directory, &tasks, &cycle))?;
+    let mut problems = HashMap::new();
+    for (index, problem) in dependency_problems {
+        problems.entry(index).or_insert(problem);
+    }
 
-        // If it's not a file, skip it
-        if !entry.file_type().is_file() { continue; }
+    // Only build the tasks we got asked to build, along with what they need
+    let mut selected: BTreeSet<usize> = (0..tasks.len())
+        .filter(|index| {
+            let path = project::relative_path(&directory, &tasks[*index].path);
+            let matches_only = options.only.as_ref().map(|o| o.matches_path(path));
+            let is_listed = options.tasks.as_ref().map(|t| t.contains(&graph::normalize(path)));
+            matches_only.unwrap_or(true) && is_listed.unwrap_or(true)
+        })
+        .collect();
+    graph.with_dependencies(&mut selected);
 
-        // Handle any extensions we want to look at
-        match entry.path().extension().map(|v| v.to_str().unwrap()) {
-            Some("toml") => handle_toml(log, entry.path(), &project, &local)?,
-            _ => ()
+    // Check the tasks after their dependencies, so we know if the dependencies changed
+    let mut fingerprints = vec!(None; tasks.len());
+    let mut failed = vec!(false; tasks.len());
+    let mut pending = Vec::new();
+    for index in order.into_iter().filter(|i| selected.contains(i)) {
+        let task = tasks[index].clone();
+        let path = project::relative_path(&directory, &task.path).to_path_buf();
+        let runner = Some(task.soto.runner.clone());
+        let (log, lines) = CaptureDrain::logger(
+            log.new(o!("file" => format!("{}", task.path.display())))
+        );
+
+        let result = match problems.remove(&index) {
+            Some(problem) => Err(Error::Task(problem)),
+            None => dependency_fingerprints(
+                &directory, &graph, index, &tasks, &fingerprints, &failed
+            ).and_then(|dependencies| {
+                handle_task(&log, &lines, index, task, dependencies, &mut context)
+            }),
+        };
+        match result {
+            Ok(TaskCheck::Pending(task)) => {
+                fingerprints[index] = task.fingerprint.clone();
+                pending.push(*task);
+            },
+            Ok(TaskCheck::UpToDate(fingerprint)) => {
+                fingerprints[index] = Some(fingerprint);
+                let mut task_report = TaskReport::new(path, runner, TaskStatus::UpToDate);
+                task_report.log = take_lines(&lines);
+                report.tasks.push(task_report);
+            },
+            Err(e) => {
+                error!(log, "Error while checking task: {}", e);
+                failed[index] = true;
+                let mut task_report = TaskReport::failed(path, runner, format!("{}", e));
+                task_report.log = take_lines(&lines);
+                report.tasks.push(task_report);
+            },
         }
     }
 
-    Ok(())
+    run_tasks(pending, &graph, &mut context, &mut report)?;
+
+    info!(
+        log, "Build finished: {} succeeded, {} up to date, {} failed",
+        report.with_status(TaskStatus::Succeeded).len(),
+        report.with_status(TaskStatus::UpToDate).len(),
+        report.with_status(TaskStatus::Failed).len()
+    );
+    for task in report.with_status(TaskStatus::Failed) {
+        let error = task.error.as_deref().unwrap_or("");
+        error!(log, "Failed \"{}\": {}", task.path.display(), error);
+    }
+
+    Ok(report)
 }
 
-fn handle_toml(
-    log: &Logger, path: &Path, project: &SotoProjectFile, local: &SotoLocalFile
-) -> Result<(), Error> {
-    // Set up the logger for this file
-    let log_path = format!("{}", path.display());
-    let log = log.new(o!("file" => log_path));
-
-    // Check the file for a soto tag so we know if we should process it
-    let data: SotoTaskFile = read_toml(path)?;
-    let data = match data.soto {
-        Some(v) => v,
-        None => { debug!(log, "No soto tag, skipping"); return Ok(()); }
+/// Takes the lines logged for a task so far.
+fn take_lines(lines: &Mutex<Vec<LogLine>>) -> Vec<LogLine> {
+    mem::take(&mut *lines.lock().unwrap())
+}
+
+/// Gets the fingerprints of a task's dependencies, or None if any of them can't be fingerprinted.
+/// Tasks can't be built if any of their dependencies failed.
+fn dependency_fingerprints(
+    directory: &Path, graph: &DependencyGraph, index: usize, tasks: &[TaskFile],
+    fingerprints: &[Option<String>], failed: &[bool],
+) -> Result<Option<Vec<String>>, Error> {
+    let dependencies = graph.dependencies(index);
+    if let Some(dependency) = dependencies.iter().find(|d| failed[**d]) {
+        return Err(dependency_failed(directory, &tasks[*dependency].path));
+    }
+
+    Ok(dependencies.iter().map(|d| fingerprints[*d].clone()).collect())
+}
+
+fn dependency_failed(directory: &Path, path: &Path) -> Error {
+    Error::Task(format!(
+        "Dependency \"{}\" failed", project::relative_path(directory, path).display()
+    ))
+}
+
+/// Finds out if a task needs to run.
+fn handle_task(
+    log: &Logger, lines: &Arc<Mutex<Vec<LogLine>>>, index: usize, task: TaskFile, dependencies: Option<Vec<String>>,
+    context: &mut BuildContext,
+) -> Result<TaskCheck, Error> {
+    // The runner has to be usable before we can do anything with the task
+    let handle = match context.options.runners.get(&task.soto.runner) {
+        Some(runner) => RunnerHandle::InProcess(runner),
+        None => RunnerHandle::Executable(
+            context.runners.resolve(log, &task.soto.runner)?.executable.clone()
+        ),
     };
 
-    // We've got a file we want to process, now go process it
-    info!(log, "Processing with runner \"{}\"", data.runner);
-    let result = Task {
-        runner: data.runner,
-        task_file: path.to_path_buf(),
-    }.run(&log, project.clone(), local.clone());
-
-    // Log the actual result
-    match result {
-        Ok(_) => info!(log, "Completed successfully"),
-        Err(Error::Task(e)) => error!(log, "Error while running task: {}", e),
-        Err(e) => return Err(e),
+    // Skip the task if nothing changed since it last completed, the cache uses paths relative to
+    // the project so it doesn't matter where soto is run from
+    let relative_path = project::relative_path(&context.directory, &task.path).to_path_buf();
+    let fingerprint = match dependencies {
+        Some(ref dependencies) => fingerprint(
+            &task.path, &task.soto.runner, &handle, &task.toml, dependencies, context
+        )?,
+        None => None,
+    };
+    if let Some(ref fingerprint) = fingerprint {
+        if !context.options.force && context.cache.is_fresh(&relative_path, fingerprint) {
+            info!(log, "Up to date, skipping");
+            return Ok(TaskCheck::UpToDate(fingerprint.clone()));
+        }
+    }
+
+    Ok(TaskCheck::Pending(Box::new(PendingTask {
+        index,
+        path: relative_path,
+        task: Task::new(task.soto.runner, handle, &context.directory, &task.path)?,
+        log: log.clone(),
+        lines: lines.clone(),
+        fingerprint,
+    })))
+}
+
+/// Runs tasks on a pool of worker threads, recording the results as they come in. Tasks are
+/// started once the tasks they depend on are done.
+fn run_tasks(
+    pending: Vec<PendingTask>, graph: &DependencyGraph, context: &mut BuildContext,
+    report: &mut BuildReport,
+) -> Result<(), Error> {
+    let workers = context.options.jobs.max(1).min(pending.len());
+
+    // Tasks have to wait for their dependencies that are also being built
+    let pending_indices: HashSet<_> = pending.iter().map(|p| p.index).collect();
+    let mut ready = Vec::new();
+    let mut waiting = HashMap::new();
+    for task in pending {
+        let count = graph.dependencies(task.index).iter()
+            .filter(|d| pending_indices.contains(d))
+            .count();
+        if count == 0 {
+            ready.push(task);
+        } else {
+            waiting.insert(task.index, (task, count));
+        }
     }
 
-    Ok(())
+    let (job_sender, job_receiver) = mpsc::channel::<PendingTask>();
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    let BuildContext {
+        ref directory, ref project, ref local, ref mut cache, ref cache_path, ..
+    } = *context;
+    thread::scope(|scope| -> Result<(), Error> {
+        // Workers stop once the job sender is dropped, moving it in here makes sure that happens
+        // when we leave this closure, even when returning early
+        let job_sender = job_sender;
+
+        for _ in 0..workers {
+            let result_sender = result_sender.clone();
+            let job_receiver = &job_receiver;
+            scope.spawn(move || loop {
+                // Wait for the next task, once there's none left this worker's done
+                let next = job_receiver.lock().unwrap().recv();
+                let pending = match next {
+                    Ok(v) => v,
+                    Err(_) => break,
+                };
+
+                // We've got a task we want to process, now go process it
+                info!(pending.log, "Processing with runner \"{}\"", pending.task.runner);
+                let start = Instant::now();
+                let result = pending.task.run(&pending.log, project.clone(), local.clone())
+                    .map_err(|e| format!("{}", e));
+                let duration = start.elapsed();
+                if result_sender.send((pending, result, duration)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut running = 0;
+        for task in ready {
+            let _ = job_sender.send(task);
+            running += 1;
+        }
+
+        // Log the actual results, and remember them for the next build
+        while running > 0 {
+            let (pending, result, duration) = match result_receiver.recv() {
+                Ok(v) => v,
+                Err(_) => break,
+            };
+            running -= 1;
+
+            let relative_path = &pending.path;
+            let mut task_report = TaskReport::new(
+                relative_path.clone(), Some(pending.task.runner.clone()), TaskStatus::Succeeded
+            );
+            task_report.duration = duration.as_secs() as f64 +
+                f64::from(duration.subsec_nanos()) / 1_000_000_000.0;
+            match result {
+                Ok(output) => {
+                    info!(pending.log, "Completed successfully");
+                    match pending.fingerprint {
+                        Some(fingerprint) => cache.insert(relative_path, fingerprint),
+                        None => cache.remove(relative_path),
+                    }
+                    let project_dir = &pending.task.project_dir;
+                    let artifacts = output.artifacts.iter()
+                        .map(|a| a.path.strip_prefix(project_dir).unwrap_or(&a.path))
+                        .map(graph::normalize)
+                        .collect();
+                    cache.set_artifacts(relative_path, artifacts);
+                    task_report.runner_version = Some(output.runner.version);
+                    task_report.artifacts = output.artifacts;
+                    task_report.log = take_lines(&pending.lines);
+                    report.tasks.push(task_report);
+
+                    // Start the tasks that were only still waiting on this one
+                    for dependent in graph.dependents(pending.index) {
+                        let is_ready = match waiting.get_mut(dependent) {
+                            Some(&mut (_, ref mut count)) => {
+                                *count -= 1;
+                                *count == 0
+                            },
+                            None => false,
+                        };
+                        if is_ready {
+                            let (task, _) = waiting.remove(dependent).unwrap();
+                            let _ = job_sender.send(task);
+                            running += 1;
+                        }
+                    }
+                },
+                Err(e) => {
+                    error!(pending.log, "Error while running task: {}", e);
+                    cache.remove(relative_path);
+                    task_report.status = TaskStatus::Failed;
+                    task_report.error = Some(e);
+                    task_report.log = take_lines(&pending.lines);
+                    report.tasks.push(task_report);
+                    fail_dependents(
+                        directory, graph, pending.index, relative_path, &mut waiting, report
+                    );
+                },
+            }
+            cache.save(cache_path)?;
+        }
+
+        Ok(())
+    })
 }
 
-fn read_required<P: Deserialize>(directory: &PathBuf, file_name: &str) -> Result<P, Error> {
-    read_toml(&file_in(directory, file_name))
-        .map_err(|e| Error::RequiredFileRead(file_name.into(), Box::new(e)))
+/// Fails the tasks waiting on a task that failed, and the tasks waiting on those.
+fn fail_dependents(
+    directory: &Path, graph: &DependencyGraph, index: usize, path: &Path,
+    waiting: &mut HashMap<usize, (PendingTask, usize)>, report: &mut BuildReport,
+) {
+    for dependent in graph.dependents(index) {
+        if let Some((task, _)) = waiting.remove(dependent) {
+            let error = dependency_failed(directory, path);
+            error!(task.log, "{}", error);
+            let mut task_report = TaskReport::failed(
+                task.path.clone(), Some(task.task.runner.clone()), format!("{}", error)
+            );
+            task_report.log = take_lines(&task.lines);
+            report.tasks.push(task_report);
+            fail_dependents(directory, graph, *dependent, &task.path, waiting, report);
+        }
+    }
 }
 
-fn file_in(path: &PathBuf, file: &str) -> PathBuf {
-    let mut path = path.clone();
-    path.push(file);
-    path
+/// Gets the fingerprint of a task, or None if the task can't be fingerprinted.
+fn fingerprint(
+    path: &Path, runner: &str, handle: &RunnerHandle, toml: &Value, dependencies: &[String],
+    context: &mut BuildContext,
+) -> Result<Option<String>, Error> {
+    let runner_version = context.runner_versions.entry(runner.to_string())
+        .or_insert_with(|| match *handle {
+            RunnerHandle::Executable(ref executable) => cache::runner_version(executable),
+            // In-process runners are part of the program running the build
+            RunnerHandle::InProcess(ref runner) => {
+                let info = runner.info();
+                env::current_exe().ok()
+                    .and_then(|exe| cache::runner_version(&exe))
+                    .map(|v| format!("{} {} {}", info.name, info.version, v))
+            },
+        });
+    let runner_version = match *runner_version {
+        Some(ref v) => v,
+        None => return Ok(None),
+    };
+
+    let files = cache::find_inputs(&context.directory, path, toml);
+    let settings = format!(
+        "{}\n{}",
+        serde_json::to_string(&context.project).unwrap(),
+        serde_json::to_string(&context.local).unwrap()
+    );
+
+    cache::fingerprint(&context.directory, path, &FingerprintInputs {
+        files: &files,
+        runner_version,
+        settings: &settings,
+        dependencies,
+    }).map(Some)
 }
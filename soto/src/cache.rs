@@ -0,0 +1,236 @@
+use std::collections::{HashMap, BTreeSet};
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use toml::Value;
+
+use Error;
+
+/// Fingerprints of tasks that completed successfully, stored between builds so tasks whose
+/// inputs haven't changed can be skipped.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct BuildCache {
+    /// Fingerprints by task TOML path.
+    tasks: HashMap<String, String>,
+    /// Artifacts tasks reported the last time they completed, relative to the project directory.
+    #[serde(default)]
+    artifacts: HashMap<String, Vec<PathBuf>>,
+}
+
+impl BuildCache {
+    /// Loads the cache, an unreadable or missing cache is treated as empty.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path).ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self).unwrap())?;
+        Ok(())
+    }
+
+    /// Checks if the task last completed with the same fingerprint.
+    pub fn is_fresh(&self, task_file: &Path, fingerprint: &str) -> bool {
+        self.tasks.get(&key(task_file)).map(|f| f == fingerprint).unwrap_or(false)
+    }
+
+    pub fn insert(&mut self, task_file: &Path, fingerprint: String) {
+        self.tasks.insert(key(task_file), fingerprint);
+    }
+
+    pub fn remove(&mut self, task_file: &Path) {
+        self.tasks.remove(&key(task_file));
+    }
+
+    pub fn artifacts(&self, task_file: &Path) -> &[PathBuf] {
+        self.artifacts.get(&key(task_file)).map(|a| a.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn set_artifacts(&mut self, task_file: &Path, artifacts: Vec<PathBuf>) {
+        self.artifacts.insert(key(task_file), artifacts);
+    }
+}
+
+fn key(task_file: &Path) -> String {
+    format!("{}", task_file.display())
+}
+
+/// Everything that goes into a task's fingerprint, besides the task TOML itself.
+pub struct FingerprintInputs<'a> {
+    /// Input files of the task, relative paths are relative to the project directory.
+    pub files: &'a BTreeSet<PathBuf>,
+    /// Identifies the version of the runner.
+    pub runner_version: &'a str,
+    /// The project and local settings the task receives, serialized.
+    pub settings: &'a str,
+    /// Fingerprints of the tasks this task depends on, so it's run again if they change.
+    pub dependencies: &'a [String],
+}
+
+/// Hashes a task's TOML and everything it depends on into a fingerprint.
+pub fn fingerprint(
+    directory: &Path, task_file: &Path, inputs: &FingerprintInputs
+) -> Result<String, Error> {
+    let mut hasher = FnvHasher::new();
+    hasher.write_str(env!("CARGO_PKG_VERSION"));
+    hasher.write_str(inputs.runner_version);
+    hasher.write_str(inputs.settings);
+    for dependency in inputs.dependencies {
+        hasher.write_str(dependency);
+    }
+    hash_file(&mut hasher, task_file)?;
+
+    // The set is sorted, so the order of the inputs is always the same
+    for file in inputs.files {
+        hasher.write_str(&format!("{}", file.display()));
+        hash_file(&mut hasher, &directory.join(file))
+            .map_err(|e| Error::Task(format!(
+                "Unable to read input file \"{}\": {}", file.display(), e
+            )))?;
+    }
+
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Identifies a runner by the contents of its executable, so rebuilding or updating a runner
+/// invalidates the tasks it ran. Returns None if the executable can't be read.
+pub fn runner_version(executable: &Path) -> Option<String> {
+    let mut hasher = FnvHasher::new();
+    hash_file(&mut hasher, executable).ok()?;
+    Some(format!("{:016x}", hasher.finish()))
+}
+
+/// Finds the input files a task TOML refers to. These are the files listed in the soto section's
+/// inputs, and any string in the TOML that names an existing file in the project directory.
+pub fn find_inputs(directory: &Path, task_file: &Path, toml: &Value) -> BTreeSet<PathBuf> {
+    let mut strings = Vec::new();
+    collect_strings(toml, &mut strings);
+
+    let declared: Vec<_> = toml.get("soto")
+        .and_then(|s| s.get("inputs"))
+        .and_then(|i| i.as_array())
+        .map(|i| i.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut inputs = BTreeSet::new();
+    for value in strings {
+        let path = Path::new(value);
+        let is_declared = declared.contains(&value);
+        if value.is_empty() || (!is_declared && !directory.join(path).is_file()) {
+            continue;
+        }
+
+        // The TOML itself is always hashed already
+        if directory.join(path) != task_file {
+            inputs.insert(path.to_path_buf());
+        }
+    }
+
+    inputs
+}
+
+fn collect_strings<'a>(value: &'a Value, strings: &mut Vec<&'a str>) {
+    match *value {
+        Value::String(ref v) => strings.push(v),
+        Value::Array(ref values) => for v in values {
+            collect_strings(v, strings);
+        },
+        Value::Table(ref table) => for v in table.values() {
+            collect_strings(v, strings);
+        },
+        _ => (),
+    }
+}
+
+fn hash_file(hasher: &mut FnvHasher, path: &Path) -> Result<(), Error> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buffer[..read]);
+    }
+
+    // Separate this file from whatever is hashed next
+    hasher.write_u8(0xff);
+    Ok(())
+}
+
+/// 64-bit FNV-1a, unlike the standard library's hasher its output is guaranteed to stay the
+/// same between Rust versions, which matters for a cache that's stored on disk.
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    fn new() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write(value.as_bytes());
+        self.write_u8(0xff);
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use toml::{self, Value};
+    use super::*;
+
+    #[test]
+    fn it_finds_declared_and_referenced_inputs() {
+        let toml: Value = toml::from_str(r#"
+            [soto]
+            runner = "soto-fbx"
+            inputs = ["textures/missing.tga"]
+
+            [model]
+            reference = "test_cubes.fbx"
+
+            [sequences.move]
+            file = "test_cubes_move.fbx"
+            params = "loop"
+        "#).unwrap();
+        let directory = Path::new("../example");
+
+        let inputs = find_inputs(directory, &directory.join("test_cubes.toml"), &toml);
+
+        let expected: Vec<PathBuf> = vec!(
+            "test_cubes.fbx".into(), "test_cubes_move.fbx".into(), "textures/missing.tga".into()
+        );
+        assert!(inputs.into_iter().collect::<Vec<_>>() == expected);
+    }
+
+    #[test]
+    fn it_detects_changed_fingerprints() {
+        let mut cache = BuildCache::default();
+        let task = Path::new("./props/crate.toml");
+        cache.insert(task, "0123".into());
+
+        assert!(cache.is_fresh(task, "0123"));
+        assert!(!cache.is_fresh(task, "4567"));
+        assert!(!cache.is_fresh(Path::new("./props/barrel.toml"), "0123"));
+    }
+}
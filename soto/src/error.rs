@@ -3,12 +3,16 @@ use std::fmt::{self, Display, Formatter};
 use std::io;
 use toml::de;
 
+use task::TaskError;
+
 #[derive(Debug)]
 pub enum Error {
     RequiredFileRead(String, Box<error::Error>),
     Io(io::Error),
     TomlParse(de::Error),
     Task(String),
+    /// A task runner reported an error.
+    Runner(TaskError),
 }
 
 impl error::Error for Error {
@@ -18,6 +22,7 @@ impl error::Error for Error {
             Error::Io(_) => "IO Error",
             Error::TomlParse(_) => "Toml Parse Error",
             Error::Task(_) => "Task Running Error",
+            Error::Runner(_) => "Task Runner Error",
         }
     }
 }
@@ -27,7 +32,10 @@ impl Display for Error {
         match *self {
             Error::RequiredFileRead(ref file, ref e) =>
                 write!(f, "Error while reading required file \"{}\": {}", file, e),
-            _ => write!(f, "{:?}", self),
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::TomlParse(ref e) => write!(f, "Invalid TOML: {}", e),
+            Error::Task(ref e) => write!(f, "{}", e),
+            Error::Runner(ref e) => write!(f, "{}", e),
         }
     }
 }
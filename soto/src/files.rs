@@ -1,8 +1,12 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SotoProjectFile {
-    pub project: SotoProjectFileProject
+    pub project: SotoProjectFileProject,
+    /// Runners used by the project's tasks by name, runners that aren't declared are looked up
+    /// in PATH.
+    pub runners: Option<BTreeMap<String, SotoProjectFileRunner>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -10,15 +14,45 @@ pub struct SotoProjectFileProject {
     pub prefix: String,
 }
 
+/// Where to find a runner, only one of `path`, `binary` and `cargo` can be used.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SotoProjectFileRunner {
+    /// An explicit path to the runner's executable.
+    pub path: Option<PathBuf>,
+    /// Path to the runner's executable, relative to the project directory.
+    pub binary: Option<PathBuf>,
+    /// Path to a cargo package to build the runner from, relative to the project directory.
+    pub cargo: Option<PathBuf>,
+    /// The oldest version of the runner the project works with.
+    pub min_version: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SotoLocalFile {
     pub game: SotoLocalFileGame,
+    /// How models get compiled, defaults to running studiomdl from the game's bin directory.
+    pub compiler: Option<SotoLocalFileCompiler>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SotoLocalFileGame {
     pub bin: PathBuf,
     pub content: PathBuf,
+    /// If runners should leave the files they build installed in the game's content directory,
+    /// besides putting them in the project's target directory.
+    pub install: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SotoLocalFileCompiler {
+    /// One of "studiomdl", "wine", "qc-only" or "mock", defaults to "studiomdl".
+    pub backend: Option<String>,
+    /// Path to studiomdl, defaults to studiomdl in the game's bin directory.
+    pub studiomdl: Option<PathBuf>,
+    /// The Wine executable used by the "wine" backend, defaults to "wine" in PATH.
+    pub wine: Option<PathBuf>,
+    /// The Wine prefix used by the "wine" backend, defaults to Wine's default prefix.
+    pub wine_prefix: Option<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -28,5 +62,14 @@ pub struct SotoTaskFile {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SotoTaskFileSoto {
-    pub runner: String
+    pub runner: String,
+    /// Extra input files the task depends on that aren't referenced in the TOML, relative to the
+    /// project directory. Used to check if the task needs to be run again.
+    pub inputs: Option<Vec<PathBuf>>,
+    /// Tasks or outputs of tasks that have to be built before this task, relative to the project
+    /// directory.
+    pub depends: Option<Vec<PathBuf>>,
+    /// Files this task builds, relative to the project directory. Other tasks can depend on
+    /// these, artifacts the runner reports can also be depended on after the task ran once.
+    pub outputs: Option<Vec<PathBuf>>,
 }
@@ -0,0 +1,219 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+use cache::BuildCache;
+use project::{self, TaskFile};
+use Error;
+
+/// Which tasks depend on which other tasks, tasks are referred to by index.
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    dependencies: Vec<BTreeSet<usize>>,
+    dependents: Vec<BTreeSet<usize>>,
+}
+
+impl DependencyGraph {
+    pub fn new(task_count: usize) -> Self {
+        DependencyGraph {
+            dependencies: vec!(BTreeSet::new(); task_count),
+            dependents: vec!(BTreeSet::new(); task_count),
+        }
+    }
+
+    pub fn add_dependency(&mut self, task: usize, dependency: usize) {
+        self.dependencies[task].insert(dependency);
+        self.dependents[dependency].insert(task);
+    }
+
+    /// The tasks a task needs to be built before it.
+    pub fn dependencies(&self, task: usize) -> &BTreeSet<usize> {
+        &self.dependencies[task]
+    }
+
+    /// The tasks that need a task to be built before them.
+    pub fn dependents(&self, task: usize) -> &BTreeSet<usize> {
+        &self.dependents[task]
+    }
+
+    /// Orders the tasks so every task comes after its dependencies, otherwise keeping tasks in
+    /// the order they were added. If the tasks depend on each other in a cycle, returns the tasks
+    /// in the cycle instead, starting and ending with the same task.
+    pub fn topological_order(&self) -> Result<Vec<usize>, Vec<usize>> {
+        let mut marks = vec!(Mark::Unvisited; self.dependencies.len());
+        let mut order = Vec::with_capacity(self.dependencies.len());
+        let mut stack = Vec::new();
+
+        for task in 0..self.dependencies.len() {
+            self.visit(task, &mut marks, &mut order, &mut stack)?;
+        }
+
+        Ok(order)
+    }
+
+    fn visit(
+        &self, task: usize, marks: &mut [Mark], order: &mut Vec<usize>, stack: &mut Vec<usize>
+    ) -> Result<(), Vec<usize>> {
+        match marks[task] {
+            Mark::Done => return Ok(()),
+            // We're already visiting this task further up, so we went in a circle
+            Mark::Visiting => {
+                let start = stack.iter().position(|t| *t == task).unwrap();
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(task);
+                return Err(cycle);
+            },
+            Mark::Unvisited => {},
+        }
+
+        marks[task] = Mark::Visiting;
+        stack.push(task);
+        for dependency in &self.dependencies[task] {
+            self.visit(*dependency, marks, order, stack)?;
+        }
+        stack.pop();
+
+        marks[task] = Mark::Done;
+        order.push(task);
+        Ok(())
+    }
+
+    /// Adds all tasks the given tasks depend on, directly or through other tasks, to the set.
+    pub fn with_dependencies(&self, tasks: &mut BTreeSet<usize>) {
+        extend(tasks, &self.dependencies);
+    }
+
+    /// Adds all tasks that depend on the given tasks, directly or through other tasks, to the set.
+    pub fn with_dependents(&self, tasks: &mut BTreeSet<usize>) {
+        extend(tasks, &self.dependents);
+    }
+}
+
+fn extend(tasks: &mut BTreeSet<usize>, edges: &[BTreeSet<usize>]) {
+    let mut remaining: Vec<_> = tasks.iter().cloned().collect();
+    while let Some(task) = remaining.pop() {
+        for other in &edges[task] {
+            if tasks.insert(*other) {
+                remaining.push(*other);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    Visiting,
+    Done,
+}
+
+/// Builds the dependency graph of a project's tasks. A dependency can be another task's TOML,
+/// an output a task declares, or an artifact a task reported the last time it ran. Dependencies
+/// that can't be found are returned as problems, along with the index of the task.
+pub fn resolve_dependencies(
+    directory: &Path, tasks: &[TaskFile], cache: &BuildCache
+) -> (DependencyGraph, Vec<(usize, String)>) {
+    // Find out what paths belong to which task, the task files themselves go first so they
+    // can't be claimed by another task's outputs
+    let mut owners = HashMap::new();
+    for (index, task) in tasks.iter().enumerate() {
+        owners.insert(normalize(project::relative_path(directory, &task.path)), index);
+    }
+    for (index, task) in tasks.iter().enumerate() {
+        let relative_path = project::relative_path(directory, &task.path);
+        let declared = task.soto.outputs.iter().flat_map(|o| o.iter());
+        for output in declared.chain(cache.artifacts(relative_path).iter()) {
+            owners.entry(normalize(output)).or_insert(index);
+        }
+    }
+
+    let mut graph = DependencyGraph::new(tasks.len());
+    let mut problems = Vec::new();
+    for (index, task) in tasks.iter().enumerate() {
+        for dependency in task.soto.depends.iter().flat_map(|d| d.iter()) {
+            match owners.get(&normalize(dependency)) {
+                Some(owner) if *owner == index => problems.push((index, format!(
+                    "Task depends on itself through \"{}\"", dependency.display()
+                ))),
+                Some(owner) => graph.add_dependency(index, *owner),
+                None => problems.push((index, format!(
+                    "Dependency \"{}\" is not a task or an output of a task", dependency.display()
+                ))),
+            }
+        }
+    }
+
+    (graph, problems)
+}
+
+/// Creates the error for a dependency cycle, listing the tasks in it.
+pub fn cycle_error(directory: &Path, tasks: &[TaskFile], cycle: &[usize]) -> Error {
+    let names: Vec<_> = cycle.iter()
+        .map(|t| format!("\"{}\"", project::relative_path(directory, &tasks[*t].path).display()))
+        .collect();
+    Error::Task(format!("Tasks depend on each other in a cycle: {}", names.join(" -> ")))
+}
+
+/// Makes paths that point to the same file in the project compare equal, as long as they don't
+/// go through symbolic links.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if normalized.file_name().is_some() => { normalized.pop(); },
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::path::Path;
+    use super::{DependencyGraph, normalize};
+
+    #[test]
+    fn it_orders_dependencies_first() {
+        let mut graph = DependencyGraph::new(4);
+        graph.add_dependency(0, 2);
+        graph.add_dependency(2, 3);
+        graph.add_dependency(1, 3);
+
+        assert!(graph.topological_order() == Ok(vec!(3, 2, 0, 1)));
+    }
+
+    #[test]
+    fn it_detects_cycles() {
+        let mut graph = DependencyGraph::new(4);
+        graph.add_dependency(0, 1);
+        graph.add_dependency(1, 2);
+        graph.add_dependency(2, 3);
+        graph.add_dependency(3, 1);
+
+        assert!(graph.topological_order() == Err(vec!(1, 2, 3, 1)));
+    }
+
+    #[test]
+    fn it_includes_indirect_dependencies_and_dependents() {
+        let mut graph = DependencyGraph::new(5);
+        graph.add_dependency(0, 1);
+        graph.add_dependency(1, 2);
+        graph.add_dependency(3, 4);
+
+        let mut tasks: BTreeSet<usize> = vec!(0).into_iter().collect();
+        graph.with_dependencies(&mut tasks);
+        assert!(tasks.into_iter().collect::<Vec<_>>() == vec!(0, 1, 2));
+
+        let mut tasks: BTreeSet<usize> = vec!(2).into_iter().collect();
+        graph.with_dependents(&mut tasks);
+        assert!(tasks.into_iter().collect::<Vec<_>>() == vec!(0, 1, 2));
+    }
+
+    #[test]
+    fn it_normalizes_paths() {
+        assert!(normalize(Path::new("./props/../materials/./shared.toml")) ==
+            Path::new("materials/shared.toml"));
+        assert!(normalize(Path::new("../shared.toml")) == Path::new("../shared.toml"));
+    }
+}
@@ -1,3 +1,5 @@
+extern crate glob;
+extern crate notify;
 extern crate serde;
 #[macro_use] extern crate serde_derive;
 extern crate serde_json;
@@ -6,12 +8,23 @@ extern crate walkdir;
 #[macro_use] extern crate slog;
 
 mod build;
+mod cache;
 mod error;
 mod files;
+mod graph;
+mod project;
+mod report;
+mod runner;
 pub mod task;
+mod watch;
 
-pub use build::build;
+pub use build::{build, BuildOptions};
 pub use error::Error;
+pub use files::{SotoLocalFile, SotoLocalFileGame, SotoLocalFileCompiler};
+pub use project::{find_tasks, relative_path, clean, init, check, runners, TaskFile};
+pub use report::{BuildReport, TaskReport, TaskStatus, LogLine, ReportFormat};
+pub use runner::{Runner, RunnerSource};
+pub use watch::watch;
 
 use std::path::Path;
 use std::fs::File;
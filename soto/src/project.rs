@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use slog::Logger;
+use toml::Value;
+use walkdir::{WalkDir, WalkDirIterator, DirEntry};
+
+use cache::BuildCache;
+use files::{SotoProjectFile, SotoLocalFile, SotoTaskFile, SotoTaskFileSoto};
+use graph;
+use runner::{Runner, Runners};
+use {read_toml, Error};
+
+/// A TOML file in a project with a soto section, which makes it a task.
+#[derive(Debug, Clone)]
+pub struct TaskFile {
+    pub path: PathBuf,
+    pub soto: SotoTaskFileSoto,
+    /// The full contents of the TOML, including the runner specific data.
+    pub toml: Value,
+}
+
+/// Finds all tasks in a project directory, sorted by path. TOML files that can't be read are
+/// returned as errors along with their path.
+pub fn find_tasks(log: &Logger, directory: &Path) -> Vec<Result<TaskFile, (PathBuf, Error)>> {
+    let target = directory.join("target");
+    let entries = WalkDir::new(directory)
+        .sort_by(|a, b| a.cmp(b))
+        .into_iter()
+        // Everything in target is generated, so there won't be any tasks in there
+        .filter_entry(|e| e.path() != target);
+
+    let mut tasks = Vec::new();
+    for entry in entries {
+        let entry: DirEntry = entry.unwrap();
+
+        // If it's not a TOML file, skip it
+        if !entry.file_type().is_file() { continue; }
+        if entry.path().extension().and_then(|v| v.to_str()) != Some("toml") { continue; }
+
+        let path = entry.path().to_path_buf();
+        match read_task(&path) {
+            Ok(Some(task)) => tasks.push(Ok(task)),
+            Ok(None) => debug!(log, "No soto tag, skipping"; "file" => format!("{}", path.display())),
+            Err(e) => tasks.push(Err((path, e))),
+        }
+    }
+
+    tasks
+}
+
+fn read_task(path: &Path) -> Result<Option<TaskFile>, Error> {
+    // Check the file for a soto tag so we know if it's a task
+    let toml: Value = read_toml(path)?;
+    let data: SotoTaskFile = toml.clone().try_into()?;
+
+    Ok(data.soto.map(|soto| TaskFile {
+        path: path.to_path_buf(),
+        soto,
+        toml,
+    }))
+}
+
+/// Gets the path of a file relative to the project directory.
+pub fn relative_path<'a>(directory: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(directory).unwrap_or(path)
+}
+
+/// Removes all intermediate and output files of a project.
+pub fn clean(log: &Logger, directory: &Path) -> Result<(), Error> {
+    let target = directory.join("target");
+    for dir in &["working", "dist"] {
+        let path = target.join(dir);
+        if path.is_dir() {
+            info!(log, "Removing \"{}\"", path.display());
+            fs::remove_dir_all(path)?;
+        }
+    }
+
+    // Without the outputs, the tasks need to run again
+    let cache_path = target.join("cache.json");
+    if cache_path.is_file() {
+        fs::remove_file(cache_path)?;
+    }
+
+    Ok(())
+}
+
+/// Creates the project files in a directory, files that already exist are left alone.
+pub fn init(log: &Logger, directory: &Path) -> Result<(), Error> {
+    fs::create_dir_all(directory)?;
+
+    // Base the prefix on the directory name
+    let name = directory.canonicalize()?.file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "project".into());
+    let prefix: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let files = [
+        ("SoTo.toml", format!("[project]\nprefix = \"{}\"\n", prefix)),
+        ("SoTo.Local.toml", LOCAL_TEMPLATE.to_string()),
+    ];
+    for &(file_name, ref contents) in &files {
+        let path = directory.join(file_name);
+        if path.exists() {
+            warn!(log, "\"{}\" already exists, skipping", path.display());
+            continue;
+        }
+
+        fs::write(&path, contents)?;
+        info!(log, "Created \"{}\"", path.display());
+    }
+
+    Ok(())
+}
+
+const LOCAL_TEMPLATE: &str = r#"# Settings specific to this computer, this file shouldn't be added to version control
+
+[game]
+# The game's bin directory, which contains studiomdl
+bin = "C:\\Program Files (x86)\\Steam\\steamapps\\common\\Team Fortress 2\\bin"
+# The game's content directory
+content = "C:\\Program Files (x86)\\Steam\\steamapps\\common\\Team Fortress 2\\tf"
+# Keep built models in the game's content directory as well, to try them out in the game
+install = false
+
+# How models get compiled: "studiomdl" runs it directly, "wine" runs it through Wine,
+# "qc-only" stops after generating the QC and SMDs, and "mock" creates placeholder models
+#[compiler]
+#backend = "wine"
+#wine_prefix = "/home/me/.wine-source"
+"#;
+
+/// Validates the project files and all tasks without running anything. Returns the problems
+/// found with tasks, along with the task's path.
+pub fn check(log: &Logger, directory: &Path) -> Result<Vec<(PathBuf, String)>, Error> {
+    // The project files have to be valid for anything to build
+    let project: SotoProjectFile = read_required(directory, "SoTo.toml")?;
+    let _: SotoLocalFile = read_required(directory, "SoTo.Local.toml")?;
+
+    let mut runners = Runners::new(directory, &project);
+    let mut problems = Vec::new();
+    let mut tasks = Vec::new();
+    for task in find_tasks(log, directory) {
+        let task = match task {
+            Ok(v) => v,
+            Err((path, e)) => {
+                problems.push((path, format!("{}", e)));
+                continue;
+            },
+        };
+
+        if let Err(e) = runners.resolve(log, &task.soto.runner) {
+            problems.push((task.path.clone(), format!("{}", e)));
+        }
+
+        // All inputs need to exist
+        for input in task.soto.inputs.iter().flat_map(|i| i.iter()) {
+            if !directory.join(input).is_file() {
+                problems.push((task.path.clone(), format!(
+                    "Input file \"{}\" does not exist", input.display()
+                )));
+            }
+        }
+
+        tasks.push(task);
+    }
+
+    // All dependencies need to be known, and tasks can't depend on each other in a cycle
+    let cache = BuildCache::load(&directory.join("target").join("cache.json"));
+    let (graph, dependency_problems) = graph::resolve_dependencies(directory, &tasks, &cache);
+    for (index, problem) in dependency_problems {
+        problems.push((tasks[index].path.clone(), problem));
+    }
+    if let Err(cycle) = graph.topological_order() {
+        let error = graph::cycle_error(directory, &tasks, &cycle);
+        problems.push((tasks[cycle[0]].path.clone(), format!("{}", error)));
+    }
+
+    Ok(problems)
+}
+
+type RunnerResult = Result<Runner, Error>;
+
+/// Looks up all runners declared in the project's SoTo.toml, and the runners used by tasks that
+/// aren't declared. Returns every runner by name, along with if it can be used.
+pub fn runners(log: &Logger, directory: &Path) -> Result<Vec<(String, RunnerResult)>, Error> {
+    let project: SotoProjectFile = read_required(directory, "SoTo.toml")?;
+    let mut runners = Runners::new(directory, &project);
+
+    let mut names = runners.declared();
+    for task in find_tasks(log, directory).into_iter().filter_map(|t| t.ok()) {
+        if !names.contains(&task.soto.runner) {
+            names.push(task.soto.runner);
+        }
+    }
+
+    Ok(names.into_iter()
+        .map(|name| {
+            let runner = runners.resolve(log, &name).cloned();
+            (name, runner)
+        })
+        .collect())
+}
+
+pub fn read_required<P: ::serde::Deserialize>(
+    directory: &Path, file_name: &str
+) -> Result<P, Error> {
+    read_toml(&directory.join(file_name))
+        .map_err(|e| Error::RequiredFileRead(file_name.into(), Box::new(e)))
+}
@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use serde_json;
+use slog::{Drain, Level, Logger, Never, OwnedKeyValueList, Record};
+
+use task::{Artifact, LogLevel};
+use Error;
+
+/// What happened to every task in a build.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BuildReport {
+    pub tasks: Vec<TaskReport>,
+}
+
+impl BuildReport {
+    pub fn is_success(&self) -> bool {
+        self.tasks.iter().all(|t| t.status != TaskStatus::Failed)
+    }
+
+    /// The tasks with a status.
+    pub fn with_status(&self, status: TaskStatus) -> Vec<&TaskReport> {
+        self.tasks.iter().filter(|t| t.status == status).collect()
+    }
+
+    /// Writes the report to a file in a format.
+    pub fn write(&self, path: &Path, format: ReportFormat) -> Result<(), Error> {
+        let data = match format {
+            ReportFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+            ReportFormat::Junit => self.to_junit(),
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Creates a JUnit XML report, with every task as a test case. Tasks that were up to date are
+    /// reported as skipped.
+    pub fn to_junit(&self) -> String {
+        let failures = self.with_status(TaskStatus::Failed).len();
+        let skipped = self.with_status(TaskStatus::UpToDate).len();
+        let time: f64 = self.tasks.iter().map(|t| t.duration).sum();
+
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"soto\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            self.tasks.len(), failures, time
+        ));
+        xml.push_str(&format!(
+            "  <testsuite name=\"build\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" \
+             time=\"{:.3}\">\n",
+            self.tasks.len(), failures, skipped, time
+        ));
+
+        for task in &self.tasks {
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">\n",
+                escape_xml(&format!("{}", task.path.display())),
+                escape_xml(task.runner.as_deref().unwrap_or("soto")),
+                task.duration
+            ));
+
+            match task.status {
+                TaskStatus::Failed => {
+                    let error = task.error.as_deref().unwrap_or("");
+                    let message = error.lines().next().unwrap_or("");
+                    xml.push_str(&format!(
+                        "      <failure message=\"{}\">{}</failure>\n",
+                        escape_xml(message), escape_xml(error)
+                    ));
+                },
+                TaskStatus::UpToDate =>
+                    xml.push_str("      <skipped message=\"Up to date\"/>\n"),
+                TaskStatus::Succeeded => {},
+            }
+
+            if !task.log.is_empty() {
+                let lines: Vec<_> = task.log.iter()
+                    .map(|l| format!("[{:?}] {}", l.level, l.text))
+                    .collect();
+                xml.push_str(&format!(
+                    "      <system-out>{}</system-out>\n", escape_xml(&lines.join("\n"))
+                ));
+            }
+
+            xml.push_str("    </testcase>\n");
+        }
+
+        xml.push_str("  </testsuite>\n");
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+}
+
+/// What happened to a task in a build.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskReport {
+    /// Path of the task file, as found in the project.
+    pub path: PathBuf,
+    /// The runner the task uses, None if the task file couldn't be read.
+    pub runner: Option<String>,
+    /// The version the runner reported, if the task ran.
+    pub runner_version: Option<String>,
+    pub status: TaskStatus,
+    /// How long the task ran for, in seconds.
+    pub duration: f64,
+    pub error: Option<String>,
+    /// Everything logged for the task while it was being built.
+    pub log: Vec<LogLine>,
+    /// The files the task produced.
+    pub artifacts: Vec<Artifact>,
+}
+
+impl TaskReport {
+    pub fn new(path: PathBuf, runner: Option<String>, status: TaskStatus) -> Self {
+        TaskReport {
+            path,
+            runner,
+            runner_version: None,
+            status,
+            duration: 0.0,
+            error: None,
+            log: Vec::new(),
+            artifacts: Vec::new(),
+        }
+    }
+
+    pub fn failed(path: PathBuf, runner: Option<String>, error: String) -> Self {
+        let mut report = TaskReport::new(path, runner, TaskStatus::Failed);
+        report.error = Some(error);
+        report
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TaskStatus {
+    Succeeded,
+    /// The task didn't need to run because nothing changed since it last completed.
+    UpToDate,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogLine {
+    pub level: LogLevel,
+    pub text: String,
+}
+
+/// File formats a build report can be written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Json,
+    Junit,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match value {
+            "json" => Ok(ReportFormat::Json),
+            "junit" => Ok(ReportFormat::Junit),
+            _ => Err(Error::Task(format!(
+                "Unknown report format \"{}\", expected \"json\" or \"junit\"", value
+            ))),
+        }
+    }
+}
+
+/// Collects the lines logged for a task, while still passing them on to the build's logger.
+pub struct CaptureDrain {
+    logger: Logger,
+    lines: Arc<Mutex<Vec<LogLine>>>,
+}
+
+impl CaptureDrain {
+    /// Creates a logger that captures everything logged to it, along with the captured lines.
+    pub fn logger(logger: Logger) -> (Logger, Arc<Mutex<Vec<LogLine>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let drain = CaptureDrain {
+            logger,
+            lines: lines.clone(),
+        };
+        (Logger::root(drain, o!()), lines)
+    }
+}
+
+impl Drain for CaptureDrain {
+    type Error = Never;
+
+    fn log(&self, record: &Record, _: &OwnedKeyValueList) -> Result<(), Never> {
+        let level = match record.level() {
+            Level::Critical | Level::Error => LogLevel::Error,
+            Level::Warning => LogLevel::Warning,
+            Level::Info => LogLevel::Info,
+            Level::Debug | Level::Trace => LogLevel::Debug,
+        };
+        self.lines.lock().unwrap().push(LogLine {
+            level,
+            text: format!("{}", record.msg()),
+        });
+
+        self.logger.log(record);
+        Ok(())
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters aren't allowed in XML, even escaped
+            c if (c as u32) < 0x20 && c != '\n' && c != '\t' && c != '\r' => {},
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildReport, TaskReport, TaskStatus};
+
+    #[test]
+    fn it_writes_junit_reports() {
+        let mut succeeded = TaskReport::new(
+            "props/crate.toml".into(), Some("soto-fbx".into()), TaskStatus::Succeeded
+        );
+        succeeded.duration = 1.5;
+        let report = BuildReport {
+            tasks: vec!(
+                succeeded,
+                TaskReport::new("props/barrel.toml".into(), None, TaskStatus::UpToDate),
+                TaskReport::failed(
+                    "props/door.toml".into(), Some("soto-fbx".into()),
+                    "Bone \"<root>\" exists multiple times\nin the FBX".into()
+                ),
+            ),
+        };
+
+        let xml = report.to_junit();
+
+        assert!(xml.contains(
+            "<testsuite name=\"build\" tests=\"3\" failures=\"1\" skipped=\"1\" time=\"1.500\">"
+        ));
+        assert!(xml.contains(
+            "<testcase name=\"props/crate.toml\" classname=\"soto-fbx\" time=\"1.500\">"
+        ));
+        assert!(xml.contains("<skipped message=\"Up to date\"/>"));
+        assert!(xml.contains(
+            "<failure message=\"Bone &quot;&lt;root&gt;&quot; exists multiple times\">"
+        ));
+    }
+}
@@ -0,0 +1,280 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use slog::Logger;
+
+use files::{SotoProjectFile, SotoProjectFileRunner};
+use task::{RunnerProcess, RunnerHello};
+use Error;
+
+/// Where to find a runner's executable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunnerSource {
+    /// Looked up by name in PATH, used for runners that aren't declared in SoTo.toml.
+    Search,
+    /// An explicit path to the executable.
+    Path(PathBuf),
+    /// An executable in the project, relative to the project directory.
+    Binary(PathBuf),
+    /// A cargo package directory, relative to the project directory. The binary with the same
+    /// name as the runner is built before it's used.
+    Cargo(PathBuf),
+}
+
+impl RunnerSource {
+    fn from_declaration(name: &str, runner: &SotoProjectFileRunner) -> Result<Self, Error> {
+        let sources = [
+            runner.path.clone().map(RunnerSource::Path),
+            runner.binary.clone().map(RunnerSource::Binary),
+            runner.cargo.clone().map(RunnerSource::Cargo),
+        ];
+        let mut sources = sources.iter().filter_map(|s| s.clone());
+
+        let source = sources.next().unwrap_or(RunnerSource::Search);
+        if sources.next().is_some() {
+            return Err(Error::Task(format!(
+                "Runner \"{}\" can only have one of \"path\", \"binary\" or \"cargo\"", name
+            )));
+        }
+
+        Ok(source)
+    }
+}
+
+/// A runner that's been found and has told us its version.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    /// The name tasks use to refer to the runner.
+    pub name: String,
+    pub source: RunnerSource,
+    /// Full path to the executable.
+    pub executable: PathBuf,
+    /// What the runner reported about itself.
+    pub hello: RunnerHello,
+}
+
+/// The runners a project uses. Runners are looked up the first time they're needed, after that
+/// the result is reused.
+pub struct Runners {
+    directory: PathBuf,
+    declared: BTreeMap<String, SotoProjectFileRunner>,
+    resolved: HashMap<String, Result<Runner, String>>,
+}
+
+impl Runners {
+    pub fn new(directory: &Path, project: &SotoProjectFile) -> Self {
+        Runners {
+            directory: directory.to_path_buf(),
+            declared: project.runners.clone().unwrap_or_default(),
+            resolved: HashMap::new(),
+        }
+    }
+
+    /// The names of the runners declared in SoTo.toml, sorted.
+    pub fn declared(&self) -> Vec<String> {
+        self.declared.keys().cloned().collect()
+    }
+
+    /// Finds a runner by name, making sure it's usable by this project.
+    pub fn resolve(&mut self, log: &Logger, name: &str) -> Result<&Runner, Error> {
+        if !self.resolved.contains_key(name) {
+            let result = self.find(log, name).map_err(|e| format!("{}", e));
+            self.resolved.insert(name.to_string(), result);
+        }
+
+        self.resolved[name].as_ref().map_err(|e| Error::Task(e.clone()))
+    }
+
+    fn find(&self, log: &Logger, name: &str) -> Result<Runner, Error> {
+        let declaration = self.declared.get(name);
+        let source = match declaration {
+            Some(runner) => RunnerSource::from_declaration(name, runner)?,
+            None => RunnerSource::Search,
+        };
+
+        // Find out where the runner is, and make sure it can be run from the project directory
+        let executable = match source {
+            RunnerSource::Search => find_executable(name).ok_or_else(|| Error::Task(format!(
+                "Can't find runner \"{}\", you may have to install it or declare where to find \
+                 it in SoTo.toml", name
+            )))?,
+            RunnerSource::Path(ref path) => existing_executable(name, path)?,
+            RunnerSource::Binary(ref path) =>
+                existing_executable(name, &self.directory.join(path))?,
+            RunnerSource::Cargo(ref package) =>
+                build_cargo_runner(log, &self.directory, name, package)?,
+        };
+        let executable: PathBuf = env::current_dir()?.join(executable).components().collect();
+
+        // Ask the runner who it is, if the hello is all it gets it'll exit without doing anything
+        let hello = {
+            let mut process = RunnerProcess::start(name, &executable, &self.directory)?;
+            process.hello(log)?
+        };
+
+        // Older runners may be missing features or fixes tasks in the project rely on
+        if let Some(min_version) = declaration.and_then(|d| d.min_version.as_ref()) {
+            let min = Version::parse(min_version).ok_or_else(|| Error::Task(format!(
+                "Minimum version \"{}\" of runner \"{}\" is not a valid version", min_version, name
+            )))?;
+            let version = Version::parse(&hello.version).ok_or_else(|| Error::Task(format!(
+                "Runner \"{}\" reports version \"{}\", which is not a valid version",
+                name, hello.version
+            )))?;
+
+            if version < min {
+                return Err(Error::Task(format!(
+                    "Runner \"{}\" is version {}, but the project needs at least version {}",
+                    name, hello.version, min_version
+                )));
+            }
+        }
+
+        Ok(Runner {
+            name: name.to_string(),
+            source,
+            executable,
+            hello,
+        })
+    }
+}
+
+fn existing_executable(name: &str, path: &Path) -> Result<PathBuf, Error> {
+    if path.is_file() {
+        Ok(path.to_path_buf())
+    } else {
+        Err(Error::Task(format!(
+            "Runner \"{}\" does not exist at \"{}\"", name, path.display()
+        )))
+    }
+}
+
+/// Builds a runner from a cargo package, returns the path of the built executable. Runners are
+/// built in the project's target directory so they don't interfere with the package's own builds.
+fn build_cargo_runner(
+    log: &Logger, directory: &Path, name: &str, package: &Path
+) -> Result<PathBuf, Error> {
+    let manifest = directory.join(package).join("Cargo.toml");
+    if !manifest.is_file() {
+        return Err(Error::Task(format!(
+            "Runner \"{}\" has no cargo package at \"{}\"", name, package.display()
+        )));
+    }
+
+    info!(log, "Building runner \"{}\" with cargo", name);
+    let target_dir = directory.join("target").join("runners");
+    let status = Command::new("cargo")
+        .arg("build").arg("--release")
+        .arg("--manifest-path").arg(&manifest)
+        .arg("--bin").arg(name)
+        .arg("--target-dir").arg(&target_dir)
+        .status()
+        .map_err(|e| Error::Task(format!("Unable to run cargo: {}", e)))?;
+    if !status.success() {
+        return Err(Error::Task(format!(
+            "Building runner \"{}\" with cargo failed", name
+        )));
+    }
+
+    let file_name = if cfg!(windows) { format!("{}.exe", name) } else { name.to_string() };
+    existing_executable(name, &target_dir.join("release").join(file_name))
+}
+
+/// Finds a runner's executable, either from its path or by looking it up in PATH.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    // Runners given as a path don't need to be looked up
+    let path = Path::new(name);
+    if path.components().count() > 1 {
+        return if path.is_file() { Some(path.to_path_buf()) } else { None };
+    }
+
+    let file_names = if cfg!(windows) {
+        vec!(format!("{}.exe", name), name.to_string())
+    } else {
+        vec!(name.to_string())
+    };
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .flat_map(|dir| file_names.iter().map(move |n| dir.join(n)))
+        .find(|p| p.is_file())
+}
+
+/// A dotted version number like "1.2.3", anything after a '-' or '+' is ignored. Missing parts
+/// count as 0, so "1.2" is the same as "1.2.0".
+#[derive(Debug, Clone)]
+struct Version(Vec<u64>);
+
+impl Version {
+    fn parse(value: &str) -> Option<Self> {
+        let number = value.trim().split(['-', '+']).next().unwrap();
+        number.split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u64>>>()
+            .map(Version)
+    }
+
+    fn part(&self, index: usize) -> u64 {
+        self.0.get(index).cloned().unwrap_or(0)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let parts = self.0.len().max(other.0.len());
+        (0..parts)
+            .map(|i| self.part(i).cmp(&other.part(i)))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use files::SotoProjectFileRunner;
+    use super::{RunnerSource, Version};
+
+    #[test]
+    fn it_compares_versions() {
+        let v = |s| Version::parse(s).unwrap();
+
+        assert!(v("0.1.0") < v("0.2.0"));
+        assert!(v("0.10.0") > v("0.9.3"));
+        assert!(v("1.2") == v("1.2.0"));
+        assert!(v("1.2.0-beta") == v("1.2.0"));
+        assert!(Version::parse("one.two").is_none());
+        assert!(Version::parse("").is_none());
+    }
+
+    #[test]
+    fn it_reads_runner_sources() {
+        let mut runner = SotoProjectFileRunner::default();
+        assert!(RunnerSource::from_declaration("r", &runner).unwrap() == RunnerSource::Search);
+
+        runner.cargo = Some("../soto-fbx".into());
+        assert!(
+            RunnerSource::from_declaration("r", &runner).unwrap() ==
+            RunnerSource::Cargo(PathBuf::from("../soto-fbx"))
+        );
+
+        runner.binary = Some("bin/soto-fbx".into());
+        assert!(RunnerSource::from_declaration("r", &runner).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+mod process;
+mod protocol;
+mod runner;
+mod wrapper;
+
+pub use self::process::{RunnerProcess};
+pub use self::protocol::{
+    PROTOCOL_VERSION, MESSAGE_PREFIX, CAPABILITY_PROGRESS, CAPABILITY_ARTIFACTS,
+    SotoMessage, SotoHello, RunnerMessage, RunnerHello, LogLevel, TaskLog, TaskProgress, Artifact,
+    TaskResult, TaskError, TaskErrorKind, TaskParameters,
+};
+pub use self::runner::{TaskRunner, TaskRunners, log_progress};
+pub use self::wrapper::{
+    RunnerInfo, task_wrapper, runner_wrapper, task_log, task_log_level, task_progress,
+    task_artifact,
+};
+
+use std::any::Any;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use slog::Logger;
+
+use files::{SotoProjectFile, SotoLocalFile};
+use Error;
+
+/// How the runner of a task gets run.
+#[derive(Clone)]
+pub enum RunnerHandle {
+    /// Full path to the runner's executable, which gets run as a separate process.
+    Executable(PathBuf),
+    /// A runner that runs in-process.
+    InProcess(Arc<TaskRunner>),
+}
+
+/// Describes a task to be run.
+pub struct Task {
+    pub runner: String,
+    pub handle: RunnerHandle,
+    pub task_file: PathBuf,
+    /// The project directory, tasks are run from here.
+    pub project_dir: PathBuf,
+    /// Directory for this task's intermediate files, not shared with other tasks.
+    pub working_dir: PathBuf,
+    /// Directory for this task's output files.
+    pub target_dir: PathBuf,
+}
+
+impl Task {
+    /// Creates a task for a task file in a project. The task gets its own working directory
+    /// based on the task file's path in the project, the output directory mirrors the project's
+    /// layout. For example "props/crate.toml" gets "target/working/props/crate" and
+    /// "target/dist/props".
+    pub fn new(
+        runner: String, handle: RunnerHandle, project_dir: &Path, task_file: &Path
+    ) -> Result<Self, Error> {
+        let relative = task_file.strip_prefix(project_dir)
+            .map_err(|_| Error::Task(format!(
+                "Task \"{}\" is not in the project directory", task_file.display()
+            )))?
+            .to_path_buf();
+
+        // Runners are run from the project directory, but may not resolve paths the same way
+        // we do, so give them full paths
+        let project_dir: PathBuf = env::current_dir()?.join(project_dir).components().collect();
+        let task_file = project_dir.join(&relative);
+
+        let target = project_dir.join("target");
+        let working_dir = target.join("working").join(relative.with_extension(""));
+        let target_dir = match relative.parent() {
+            Some(parent) => target.join("dist").join(parent),
+            None => target.join("dist"),
+        };
+
+        Ok(Task {
+            runner,
+            handle,
+            task_file,
+            project_dir,
+            working_dir,
+            target_dir,
+        })
+    }
+
+    /// Runs the task, returns who ran it and the artifacts it produced.
+    pub fn run(
+        &self, log: &Logger, project: SotoProjectFile, local: SotoLocalFile
+    ) -> Result<TaskOutput, Error> {
+        // Create the task paramters which we need to send over
+        let task_params = TaskParameters {
+            project_dir: self.project_dir.clone(),
+            working_dir: self.working_dir.clone(),
+            target_dir: self.target_dir.clone(),
+            target_toml: self.task_file.clone(),
+
+            project: project,
+            local: local,
+        };
+
+        // Create the directories this task is going to need
+        ::std::fs::create_dir_all(&task_params.working_dir)?;
+        ::std::fs::create_dir_all(&task_params.target_dir)?;
+
+        match self.handle {
+            RunnerHandle::Executable(ref executable) => {
+                let mut process = RunnerProcess::start(&self.runner, executable, &self.project_dir)?;
+                let runner = process.hello(log)?;
+                let artifacts = process.run(log, task_params)?;
+
+                Ok(TaskOutput {
+                    runner,
+                    artifacts,
+                })
+            },
+            RunnerHandle::InProcess(ref runner) => {
+                // We can't change directory without affecting everything else in this process, so
+                // in-process runners have to resolve paths against the project directory
+                let info = runner.info();
+                debug!(log, "Runner is {} {} (in-process)", info.name, info.version);
+                // A runner panicking shouldn't take the worker running it down with it, the build
+                // would never hear back about the task
+                let result = panic::catch_unwind(AssertUnwindSafe(|| runner.run(log, task_params)))
+                    .map_err(|payload| Error::Task(format!(
+                        "Runner \"{}\" panicked: {}", self.runner, panic_message(&payload)
+                    )))?;
+                let artifacts = result.map_err(Error::Runner)?;
+
+                Ok(TaskOutput {
+                    runner: RunnerHello {
+                        protocol: PROTOCOL_VERSION,
+                        name: info.name,
+                        version: info.version,
+                        capabilities: info.capabilities,
+                    },
+                    artifacts,
+                })
+            },
+        }
+    }
+}
+
+fn panic_message(payload: &Box<Any + Send>) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// The results of a task that ran successfully.
+#[derive(Debug, Clone)]
+pub struct TaskOutput {
+    pub runner: RunnerHello,
+    pub artifacts: Vec<Artifact>,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+    use slog::{Logger, Discard};
+    use files::{
+        SotoProjectFile, SotoProjectFileProject, SotoLocalFile, SotoLocalFileGame,
+    };
+    use super::{Artifact, RunnerHandle, RunnerInfo, Task, TaskError, TaskParameters, TaskRunner};
+
+    struct PanickingRunner;
+
+    impl TaskRunner for PanickingRunner {
+        fn info(&self) -> RunnerInfo {
+            RunnerInfo::new("panicking-runner", "1.0.0")
+        }
+
+        fn run(&self, _: &Logger, _: TaskParameters) -> Result<Vec<Artifact>, TaskError> {
+            panic!("Something went very wrong")
+        }
+    }
+
+    pub fn project() -> SotoProjectFile {
+        SotoProjectFile {
+            project: SotoProjectFileProject { prefix: "test".into() },
+            runners: None,
+        }
+    }
+
+    pub fn local() -> SotoLocalFile {
+        SotoLocalFile {
+            game: SotoLocalFileGame {
+                bin: "bin".into(),
+                content: "content".into(),
+                install: None,
+            },
+            compiler: None,
+        }
+    }
+
+    fn handle() -> RunnerHandle {
+        RunnerHandle::InProcess(Arc::new(PanickingRunner))
+    }
+
+    #[test]
+    fn it_gives_tasks_directories_based_on_their_path() {
+        let project_dir = env::temp_dir().join("soto-test-project");
+
+        let task = Task::new(
+            "runner".into(), handle(), &project_dir, &project_dir.join("props/crate.toml")
+        ).unwrap();
+        assert!(task.task_file == project_dir.join("props/crate.toml"));
+        assert!(task.working_dir == project_dir.join("target/working/props/crate"));
+        assert!(task.target_dir == project_dir.join("target/dist/props"));
+
+        let task = Task::new(
+            "runner".into(), handle(), &project_dir, &project_dir.join("crate.toml")
+        ).unwrap();
+        assert!(task.working_dir == project_dir.join("target/working/crate"));
+        assert!(task.target_dir == project_dir.join("target/dist"));
+    }
+
+    #[test]
+    fn it_makes_task_paths_absolute() {
+        let task = Task::new(
+            "runner".into(), handle(), Path::new("project"), Path::new("project/crate.toml")
+        ).unwrap();
+        let project_dir = env::current_dir().unwrap().join("project");
+
+        assert!(task.project_dir == project_dir);
+        assert!(task.task_file == project_dir.join("crate.toml"));
+    }
+
+    #[test]
+    fn it_refuses_tasks_outside_the_project() {
+        let project_dir = env::temp_dir().join("soto-test-project");
+        let task_file = env::temp_dir().join("other-project/crate.toml");
+
+        match Task::new("runner".into(), handle(), &project_dir, &task_file) {
+            Err(error) => assert!(format!("{}", error).contains("is not in the project directory")),
+            Ok(_) => panic!("Expected the task to be refused"),
+        }
+    }
+
+    #[test]
+    fn it_fails_tasks_of_panicking_runners() {
+        let project_dir = env::temp_dir().join("soto-test-panicking-runner");
+        let task = Task::new(
+            "panicking".into(), handle(), &project_dir, &project_dir.join("crate.toml")
+        ).unwrap();
+
+        let log = Logger::root(Discard, o!());
+        let error = task.run(&log, project(), local()).unwrap_err();
+
+        assert!(format!("{}", error).contains("Something went very wrong"));
+        let _ = fs::remove_dir_all(&project_dir);
+    }
+}
@@ -0,0 +1,239 @@
+use std::io::{self, BufReader, BufRead, Write};
+use std::path::Path;
+use std::process::{Stdio, Command, Child, ChildStdin, ChildStdout};
+use serde_json;
+use slog::Logger;
+
+use task::protocol::{
+    PROTOCOL_VERSION, MESSAGE_PREFIX, SotoMessage, SotoHello, RunnerMessage, RunnerHello, LogLevel,
+    Artifact, TaskParameters,
+};
+use task::runner::log_progress;
+use Error;
+
+/// A runner process soto is talking to.
+pub struct RunnerProcess {
+    runner: String,
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl RunnerProcess {
+    /// Starts a runner's executable in a directory and introduces soto to it.
+    pub fn start(runner: &str, executable: &Path, directory: &Path) -> Result<Self, Error> {
+        let mut child = Command::new(executable)
+            .arg("--soto")
+            .current_dir(directory)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| {
+                if let io::ErrorKind::NotFound = e.kind() {
+                    Error::Task(format!(
+                        "Can't find runner \"{}\" at \"{}\"", runner, executable.display()
+                    ))
+                } else {
+                    Error::Io(e)
+                }
+            })?;
+
+        let mut process = RunnerProcess {
+            runner: runner.into(),
+            stdin: child.stdin.take(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+        };
+        process.send(&SotoMessage::Hello(SotoHello {
+            protocol: PROTOCOL_VERSION,
+            soto_version: env!("CARGO_PKG_VERSION").into(),
+        }))?;
+
+        Ok(process)
+    }
+
+    /// Waits for the runner to introduce itself, and checks if it speaks our protocol.
+    pub fn hello(&mut self, log: &Logger) -> Result<RunnerHello, Error> {
+        let message = self.next_message(log)?;
+        let hello = check_hello(&self.runner, message)?;
+
+        debug!(
+            log, "Runner is {} {} with capabilities [{}]",
+            hello.name, hello.version, hello.capabilities.join(", ")
+        );
+        Ok(hello)
+    }
+
+    /// Runs a task on the runner, returns the artifacts it produced.
+    pub fn run(mut self, log: &Logger, params: TaskParameters) -> Result<Vec<Artifact>, Error> {
+        self.send(&SotoMessage::Run(Box::new(params)))?;
+
+        // Nothing else will be sent, closing stdin lets the runner know
+        self.stdin = None;
+
+        let mut artifacts = Vec::new();
+        loop {
+            match self.next_message(log)? {
+                RunnerMessage::Hello(_) => return Err(Error::Task(format!(
+                    "Runner \"{}\" introduced itself twice", self.runner
+                ))),
+                RunnerMessage::Log(message) => match message.level {
+                    LogLevel::Debug => debug!(log, "{}", message.text),
+                    LogLevel::Info => info!(log, "{}", message.text),
+                    LogLevel::Warning => warn!(log, "{}", message.text),
+                    LogLevel::Error => error!(log, "{}", message.text),
+                },
+                RunnerMessage::Progress(progress) => log_progress(
+                    log, progress.current, progress.total, &progress.text.unwrap_or_default()
+                ),
+                RunnerMessage::Artifact(artifact) => {
+                    debug!(log, "Produced \"{}\"", artifact.path.display());
+                    artifacts.push(artifact);
+                },
+                // A result means this task is done
+                RunnerMessage::Result(result) => {
+                    return match result.error {
+                        Some(error) => Err(Error::Runner(error)),
+                        None => Ok(artifacts),
+                    };
+                },
+            }
+        }
+    }
+
+    fn send(&mut self, message: &SotoMessage) -> Result<(), Error> {
+        let stdin = match self.stdin {
+            Some(ref mut v) => v,
+            None => return Ok(()),
+        };
+
+        match writeln!(stdin, "{}", serde_json::to_string(message).unwrap()) {
+            // If the runner closed already, reading will tell us what happened
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result.map_err(Error::Io),
+        }
+    }
+
+    fn next_message(&mut self, log: &Logger) -> Result<RunnerMessage, Error> {
+        read_message(&self.runner, &mut self.stdout, log)
+    }
+}
+
+/// Checks if a runner introduced itself, and if it speaks our protocol.
+fn check_hello(runner: &str, message: RunnerMessage) -> Result<RunnerHello, Error> {
+    let hello = match message {
+        RunnerMessage::Hello(hello) => hello,
+        _ => return Err(Error::Task(format!(
+            "Runner \"{}\" didn't introduce itself, it may not be a soto task runner", runner
+        ))),
+    };
+
+    if hello.protocol != PROTOCOL_VERSION {
+        return Err(Error::Task(format!(
+            "Runner \"{}\" {} speaks protocol version {}, but soto {} speaks version {}",
+            hello.name, hello.version, hello.protocol,
+            env!("CARGO_PKG_VERSION"), PROTOCOL_VERSION
+        )));
+    }
+
+    Ok(hello)
+}
+
+/// Reads the next message from a runner's output, passing through anything that isn't a message.
+fn read_message<R: BufRead>(
+    runner: &str, output: &mut R, log: &Logger
+) -> Result<RunnerMessage, Error> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        output.read_line(&mut line)?;
+
+        // If the line is empty, the runner unexpectedly closed
+        if line.is_empty() {
+            return Err(Error::Task(format!(
+                "Runner \"{}\" unexpectedly closed, this may not be a soto task runner or an \
+                 internal error occurred", runner
+            )));
+        }
+
+        let line = line.trim_end();
+        let message = match line.strip_prefix(MESSAGE_PREFIX) {
+            Some(v) => v,
+            None => {
+                // Not a message, probably something the runner printed
+                debug!(log, "{}", line);
+                continue;
+            },
+        };
+
+        return serde_json::from_str(message).map_err(|e| Error::Task(format!(
+            "Invalid message from runner \"{}\": {}\nMessage:\n{}", runner, e, message
+        )));
+    }
+}
+
+impl Drop for RunnerProcess {
+    fn drop(&mut self) {
+        // Close stdin before waiting, runners wait for soto to either send a task or close it
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use slog::{Logger, Discard};
+    use task::protocol::{PROTOCOL_VERSION, RunnerMessage, RunnerHello, LogLevel};
+    use super::{check_hello, read_message};
+
+    fn hello(protocol: u32) -> RunnerMessage {
+        RunnerMessage::Hello(RunnerHello {
+            protocol,
+            name: "test-runner".into(),
+            version: "1.2.3".into(),
+            capabilities: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn it_skips_lines_that_arent_messages() {
+        let log = Logger::root(Discard, o!());
+        let output = "Loading model...\n\
+                      soto {\"Log\":{\"level\":\"Info\",\"text\":\"Not a message\"}}\n\
+                      @soto {\"Log\":{\"level\":\"Warning\",\"text\":\"A message\"}}\n";
+
+        let mut output = output.as_bytes();
+        match read_message("test-runner", &mut output, &log).unwrap() {
+            RunnerMessage::Log(log) => {
+                assert!(log.level == LogLevel::Warning);
+                assert!(log.text == "A message");
+            },
+            message => panic!("Expected a log message, got {:?}", message),
+        }
+
+        // Nothing is left, so the runner closed
+        let error = read_message("test-runner", &mut output, &log).unwrap_err();
+        assert!(format!("{}", error).contains("unexpectedly closed"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_messages() {
+        let log = Logger::root(Discard, o!());
+        let mut output = "@soto {\"Shout\":\"Hello\"}\n".as_bytes();
+
+        let error = read_message("test-runner", &mut output, &log).unwrap_err();
+        assert!(format!("{}", error).contains("Invalid message"));
+    }
+
+    #[test]
+    fn it_checks_the_protocol_version() {
+        let runner = check_hello("test-runner", hello(PROTOCOL_VERSION)).unwrap();
+        assert!(runner.version == "1.2.3");
+
+        let error = check_hello("test-runner", hello(PROTOCOL_VERSION + 1)).unwrap_err();
+        let message = format!("{}", error);
+        assert!(message.contains(&format!("speaks protocol version {}", PROTOCOL_VERSION + 1)));
+        assert!(message.contains(&format!("speaks version {}", PROTOCOL_VERSION)));
+    }
+}
@@ -0,0 +1,251 @@
+//! Messages sent between soto and task runners. Soto starts a runner with the `--soto` argument
+//! and sends it messages over stdin, the runner replies over stdout. Every message is a single
+//! line of JSON. Messages from the runner start with `MESSAGE_PREFIX` so any other output of the
+//! runner can't be mistaken for a message.
+//!
+//! A run goes like this:
+//! 1. Soto sends `SotoMessage::Hello` with the protocol version it speaks.
+//! 2. The runner replies with `RunnerMessage::Hello`, telling soto who it is.
+//! 3. Soto sends `SotoMessage::Run` with the task to run, or closes stdin if it only wanted to
+//!    know the runner's version.
+//! 4. The runner sends any amount of `Log`, `Progress` and `Artifact` messages.
+//! 5. The runner sends `RunnerMessage::Result` and exits.
+
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+use files::{SotoProjectFile, SotoLocalFile};
+use Error;
+
+/// Version of the protocol, runners need to speak the same version as soto.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Lines from a runner starting with this are messages, other lines are passed through as logs.
+pub const MESSAGE_PREFIX: &str = "@soto ";
+
+/// The runner can report progress while it's running.
+pub const CAPABILITY_PROGRESS: &str = "progress";
+/// The runner reports the files it produces as artifacts.
+pub const CAPABILITY_ARTIFACTS: &str = "artifacts";
+
+/// Messages sent from soto to a runner.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum SotoMessage {
+    Hello(SotoHello),
+    Run(Box<TaskParameters>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SotoHello {
+    pub protocol: u32,
+    pub soto_version: String,
+}
+
+/// Messages sent from a runner to soto.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum RunnerMessage {
+    Hello(RunnerHello),
+    Log(TaskLog),
+    Progress(TaskProgress),
+    Artifact(Artifact),
+    /// The task is done, this is the last message.
+    Result(TaskResult),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunnerHello {
+    pub protocol: u32,
+    pub name: String,
+    pub version: String,
+    /// Optional features the runner supports, like `CAPABILITY_PROGRESS`.
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaskLog {
+    pub level: LogLevel,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaskProgress {
+    /// The amount of steps that are done.
+    pub current: u32,
+    pub total: u32,
+    /// What the runner is currently doing.
+    pub text: Option<String>,
+}
+
+/// A file produced by a task.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub path: PathBuf,
+    /// What kind of file this is, for example "mdl".
+    pub kind: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaskResult {
+    pub error: Option<TaskError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TaskErrorKind {
+    /// The task's TOML or the project's settings are invalid.
+    InvalidTask,
+    /// An input file is missing or couldn't be read.
+    Input,
+    /// An external tool the runner uses failed.
+    Tool,
+    Other,
+}
+
+/// An error that made a task fail.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskError {
+    pub kind: TaskErrorKind,
+    pub message: String,
+    /// The file the error is about, if it's about a specific file.
+    pub file: Option<PathBuf>,
+}
+
+impl TaskError {
+    pub fn new<S: Into<String>>(kind: TaskErrorKind, message: S) -> Self {
+        TaskError {
+            kind,
+            message: message.into(),
+            file: None,
+        }
+    }
+
+    pub fn with_file<P: Into<PathBuf>>(mut self, file: P) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+}
+
+impl Display for TaskError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => write!(f, "{} (in \"{}\")", self.message, file.display()),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<Error> for TaskError {
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::RequiredFileRead(_, _) | Error::TomlParse(_) => TaskErrorKind::InvalidTask,
+            Error::Runner(ref e) => return e.clone(),
+            _ => TaskErrorKind::Other,
+        };
+        TaskError::new(kind, format!("{}", error))
+    }
+}
+
+/// The task a runner has to run.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TaskParameters {
+    /// The project directory, relative paths in the task are relative to this.
+    pub project_dir: PathBuf,
+    pub working_dir: PathBuf,
+    pub target_dir: PathBuf,
+    pub target_toml: PathBuf,
+
+    pub project: SotoProjectFile,
+    pub local: SotoLocalFile,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use task::tests::{project, local};
+    use super::{
+        PROTOCOL_VERSION, SotoMessage, SotoHello, RunnerMessage, RunnerHello, TaskResult, TaskError,
+        TaskErrorKind, TaskParameters, Artifact,
+    };
+
+    fn round_trip_soto(message: SotoMessage) -> SotoMessage {
+        serde_json::from_str(&serde_json::to_string(&message).unwrap()).unwrap()
+    }
+
+    fn round_trip_runner(message: RunnerMessage) -> RunnerMessage {
+        serde_json::from_str(&serde_json::to_string(&message).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn it_round_trips_soto_messages() {
+        let hello = round_trip_soto(SotoMessage::Hello(SotoHello {
+            protocol: PROTOCOL_VERSION,
+            soto_version: "0.1.0".into(),
+        }));
+        match hello {
+            SotoMessage::Hello(hello) => {
+                assert!(hello.protocol == PROTOCOL_VERSION);
+                assert!(hello.soto_version == "0.1.0");
+            },
+            message => panic!("Expected hello, got {:?}", message),
+        }
+
+        let run = round_trip_soto(SotoMessage::Run(Box::new(TaskParameters {
+            project_dir: "project".into(),
+            working_dir: "project/target/working/crate".into(),
+            target_dir: "project/target/dist".into(),
+            target_toml: "project/crate.toml".into(),
+            project: project(),
+            local: local(),
+        })));
+        match run {
+            SotoMessage::Run(params) => {
+                assert!(params.working_dir.to_str() == Some("project/target/working/crate"));
+                assert!(params.target_toml.to_str() == Some("project/crate.toml"));
+                assert!(params.project.project.prefix == "test");
+                assert!(params.local.game.content.to_str() == Some("content"));
+            },
+            message => panic!("Expected run, got {:?}", message),
+        }
+    }
+
+    #[test]
+    fn it_round_trips_runner_messages() {
+        let hello = round_trip_runner(RunnerMessage::Hello(RunnerHello {
+            protocol: PROTOCOL_VERSION,
+            name: "test-runner".into(),
+            version: "1.2.3".into(),
+            capabilities: vec!["artifacts".into()],
+        }));
+        match hello {
+            RunnerMessage::Hello(hello) => {
+                assert!(hello.name == "test-runner");
+                assert!(hello.capabilities == vec!["artifacts".to_string()]);
+            },
+            message => panic!("Expected hello, got {:?}", message),
+        }
+
+        let artifact = Artifact { path: "crate.mdl".into(), kind: Some("mdl".into()) };
+        match round_trip_runner(RunnerMessage::Artifact(artifact.clone())) {
+            RunnerMessage::Artifact(received) => assert!(received == artifact),
+            message => panic!("Expected artifact, got {:?}", message),
+        }
+
+        let result = round_trip_runner(RunnerMessage::Result(TaskResult {
+            error: Some(TaskError::new(TaskErrorKind::Input, "Missing mesh").with_file("a.fbx")),
+        }));
+        match result {
+            RunnerMessage::Result(TaskResult { error: Some(error) }) => {
+                assert!(error.kind == TaskErrorKind::Input);
+                assert!(format!("{}", error) == "Missing mesh (in \"a.fbx\")");
+            },
+            message => panic!("Expected a failed result, got {:?}", message),
+        }
+    }
+}
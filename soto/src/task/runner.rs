@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use slog::Logger;
+
+use task::protocol::{Artifact, TaskError, TaskParameters};
+use task::wrapper::RunnerInfo;
+
+/// A task runner that can be run in-process, without spawning an executable. Runners
+/// implementing this can also be turned into an executable runner using `runner_wrapper`.
+pub trait TaskRunner: Send + Sync {
+    /// Who this runner is, the same as a runner executable would introduce itself.
+    fn info(&self) -> RunnerInfo;
+
+    /// Runs a task, returns the files it produced. Anything the runner has to tell the user goes
+    /// through the logger.
+    fn run(&self, log: &Logger, params: TaskParameters) -> Result<Vec<Artifact>, TaskError>;
+}
+
+/// In-process task runners by the runner name tasks use to refer to them. A registered runner is
+/// used instead of looking for a runner executable.
+#[derive(Clone, Default)]
+pub struct TaskRunners {
+    runners: HashMap<String, Arc<TaskRunner>>,
+}
+
+impl TaskRunners {
+    pub fn register<S: Into<String>, R: TaskRunner + 'static>(&mut self, name: S, runner: R) {
+        self.runners.insert(name.into(), Arc::new(runner));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<TaskRunner>> {
+        self.runners.get(name).cloned()
+    }
+}
+
+impl Debug for TaskRunners {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_set().entries(self.runners.keys()).finish()
+    }
+}
+
+/// Logs progress of a task the same way soto logs progress reported by a runner executable.
+pub fn log_progress(log: &Logger, current: u32, total: u32, text: &str) {
+    info!(log, "[{}/{}] {}", current, total, text);
+}
+
+#[cfg(test)]
+mod tests {
+    use slog::Logger;
+    use task::{Artifact, RunnerInfo, TaskError, TaskParameters};
+    use super::{TaskRunner, TaskRunners};
+
+    struct TestRunner;
+
+    impl TaskRunner for TestRunner {
+        fn info(&self) -> RunnerInfo {
+            RunnerInfo::new("test-runner", "1.0.0")
+        }
+
+        fn run(&self, _: &Logger, _: TaskParameters) -> Result<Vec<Artifact>, TaskError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn it_finds_registered_runners() {
+        let mut runners = TaskRunners::default();
+        runners.register("test", TestRunner);
+
+        assert!(runners.get("test").unwrap().info().name == "test-runner");
+        assert!(runners.get("soto-fbx").is_none());
+    }
+}
@@ -0,0 +1,213 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use serde::Serialize;
+use serde_json;
+use slog::{Drain, Level, Logger, Never, OwnedKeyValueList, Record};
+
+use task::protocol::{
+    PROTOCOL_VERSION, MESSAGE_PREFIX, SotoMessage, RunnerMessage, RunnerHello, LogLevel, TaskLog,
+    TaskProgress, Artifact, TaskResult, TaskError, TaskErrorKind, TaskParameters,
+};
+use task::runner::TaskRunner;
+
+/// Describes a runner to soto.
+#[derive(Debug, Clone)]
+pub struct RunnerInfo {
+    pub name: String,
+    pub version: String,
+    pub capabilities: Vec<String>,
+}
+
+impl RunnerInfo {
+    /// Creates runner info, usually with `env!("CARGO_PKG_NAME")` and
+    /// `env!("CARGO_PKG_VERSION")`.
+    pub fn new<S: Into<String>, V: Into<String>>(name: S, version: V) -> Self {
+        RunnerInfo {
+            name: name.into(),
+            version: version.into(),
+            capabilities: Vec::new(),
+        }
+    }
+
+    pub fn with_capability<S: Into<String>>(mut self, capability: S) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+}
+
+/// Turns the binary into a soto task runner, handles talking to soto and reports the result.
+pub fn task_wrapper<E, F>(info: RunnerInfo, task: F)
+    where E: Into<TaskError>, F: FnOnce(TaskParameters) -> Result<(), E>
+{
+    serve(info, |params| task(params).map(|_| Vec::new()).map_err(|e| e.into()));
+}
+
+/// Turns the binary into a soto task runner for an in-process runner. Everything the runner logs
+/// is relayed back to soto.
+pub fn runner_wrapper<R: TaskRunner>(runner: R) {
+    let log = Logger::root(ProtocolDrain, o!());
+    serve(runner.info(), |params| runner.run(&log, params));
+}
+
+fn serve<F>(info: RunnerInfo, task: F)
+    where F: FnOnce(TaskParameters) -> Result<Vec<Artifact>, TaskError>
+{
+    // Soto tells us it's soto running us, so we can tell people not to run us directly
+    match env::args().nth(1).as_deref() {
+        Some("--soto") => {},
+        Some("--version") => {
+            println!("{} {}", info.name, info.version);
+            return;
+        },
+        _ => {
+            println!("This is a soto task runner, do not run this directly!");
+            println!("If you need to run this as part of another tool, use the soto library.");
+            return;
+        },
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    // Introduce ourselves, even if soto speaks another protocol version, so it can tell the user
+    let hello = match read_message(&mut lines) {
+        Some(Ok(SotoMessage::Hello(hello))) => hello,
+        Some(Ok(_)) => return send_error(TaskError::new(
+            TaskErrorKind::Other, "Expected hello from soto"
+        )),
+        Some(Err(e)) => return send_error(e),
+        None => return,
+    };
+    send_message(&RunnerMessage::Hello(RunnerHello {
+        protocol: PROTOCOL_VERSION,
+        name: info.name,
+        version: info.version,
+        capabilities: info.capabilities,
+    }));
+    if hello.protocol != PROTOCOL_VERSION {
+        return send_error(TaskError::new(TaskErrorKind::Other, format!(
+            "Runner speaks protocol version {} but soto {} speaks {}",
+            PROTOCOL_VERSION, hello.soto_version, hello.protocol
+        )));
+    }
+
+    // If soto doesn't send anything else, it only wanted to know who we are
+    let params = match read_message(&mut lines) {
+        Some(Ok(SotoMessage::Run(params))) => *params,
+        Some(Ok(_)) => return send_error(TaskError::new(
+            TaskErrorKind::Other, "Expected task to run from soto"
+        )),
+        Some(Err(e)) => return send_error(e),
+        None => return,
+    };
+
+    // Run the task itself and report back how it went
+    let result = task(params).map(|artifacts| for artifact in artifacts {
+        send_message(&RunnerMessage::Artifact(artifact));
+    });
+    send_message(&RunnerMessage::Result(TaskResult {
+        error: result.err(),
+    }));
+}
+
+/// Relays logging records to soto as log messages.
+struct ProtocolDrain;
+
+impl Drain for ProtocolDrain {
+    type Error = Never;
+
+    fn log(&self, record: &Record, _: &OwnedKeyValueList) -> Result<(), Never> {
+        let level = match record.level() {
+            Level::Critical | Level::Error => LogLevel::Error,
+            Level::Warning => LogLevel::Warning,
+            Level::Info => LogLevel::Info,
+            Level::Debug | Level::Trace => LogLevel::Debug,
+        };
+        task_log_level(level, record.msg());
+        Ok(())
+    }
+}
+
+fn read_message<I: Iterator<Item=io::Result<String>>>(
+    lines: &mut I
+) -> Option<Result<SotoMessage, TaskError>> {
+    let line = match lines.next() {
+        Some(Ok(line)) => line,
+        Some(Err(e)) => return Some(Err(TaskError::new(TaskErrorKind::Other, format!(
+            "Unable to read from soto: {}", e
+        )))),
+        None => return None,
+    };
+
+    Some(serde_json::from_str(&line).map_err(|e| TaskError::new(TaskErrorKind::Other, format!(
+        "Invalid message from soto: {}", e
+    ))))
+}
+
+fn send_error(error: TaskError) {
+    send_message(&RunnerMessage::Result(TaskResult {
+        error: Some(error),
+    }));
+}
+
+fn send_message<M: Serialize>(message: &M) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let _ = writeln!(stdout, "{}{}", MESSAGE_PREFIX, serde_json::to_string(message).unwrap());
+    let _ = stdout.flush();
+}
+
+/// Relays a debug logging message from a task back to soto.
+pub fn task_log<S: ToString>(text: S) {
+    task_log_level(LogLevel::Debug, text);
+}
+
+/// Relays a logging message from a task back to soto.
+pub fn task_log_level<S: ToString>(level: LogLevel, text: S) {
+    send_message(&RunnerMessage::Log(TaskLog {
+        level,
+        text: text.to_string(),
+    }));
+}
+
+/// Tells soto how far along the task is.
+pub fn task_progress(current: u32, total: u32, text: Option<String>) {
+    send_message(&RunnerMessage::Progress(TaskProgress {
+        current,
+        total,
+        text,
+    }));
+}
+
+/// Tells soto about a file the task produced.
+pub fn task_artifact<P: Into<PathBuf>>(path: P, kind: Option<String>) {
+    send_message(&RunnerMessage::Artifact(Artifact {
+        path: path.into(),
+        kind,
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use task::protocol::{PROTOCOL_VERSION, SotoMessage};
+    use super::read_message;
+
+    #[test]
+    fn it_reads_messages_from_soto() {
+        let hello = format!(
+            "{{\"Hello\":{{\"protocol\":{},\"soto_version\":\"0.1.0\"}}}}", PROTOCOL_VERSION
+        );
+        let mut lines = vec![Ok(hello), Ok("Hello".to_string())].into_iter();
+
+        match read_message(&mut lines) {
+            Some(Ok(SotoMessage::Hello(hello))) => assert!(hello.protocol == PROTOCOL_VERSION),
+            message => panic!("Expected hello, got {:?}", message),
+        }
+        match read_message(&mut lines) {
+            Some(Err(error)) => assert!(error.message.contains("Invalid message")),
+            message => panic!("Expected an error, got {:?}", message),
+        }
+        assert!(read_message(&mut lines).is_none());
+    }
+}
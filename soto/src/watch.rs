@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{self, DebouncedEvent, RecursiveMode, Watcher};
+use slog::Logger;
+
+use build::{self, BuildOptions};
+use cache::{self, BuildCache};
+use graph;
+use project::{self, TaskFile};
+use Error;
+
+/// How long files have to stay unchanged before a build starts, so saving a file in multiple
+/// steps or saving multiple files at once only triggers one build.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Files that affect all tasks in the project.
+const PROJECT_FILES: &[&str] = &["SoTo.toml", "SoTo.Local.toml"];
+
+/// Input files by task, relative to the project directory.
+type TaskInputs = BTreeMap<PathBuf, BTreeSet<PathBuf>>;
+
+/// Builds the project, and then keeps building the tasks affected by changes to task TOMLs and
+/// their input files until stopped. Errors during builds are logged, after which soto continues
+/// watching for changes.
+pub fn watch(log: &Logger, directory: &Path, options: &BuildOptions) -> Result<(), Error> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::watcher(sender, DEBOUNCE).map_err(watch_error)?;
+    watcher.watch(directory, RecursiveMode::Recursive).map_err(watch_error)?;
+    let root = directory.canonicalize()?;
+
+    let (_, mut inputs) = scan(log, directory);
+    run_build(log, directory, options, None);
+
+    loop {
+        // Wait for something to change, and give things time to settle down
+        let mut changed = BTreeSet::new();
+        let event = receiver.recv()
+            .map_err(|_| Error::Task("Stopped watching for changes".into()))?;
+        let mut rescan = add_changes(log, &root, event, &mut changed);
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            rescan |= add_changes(log, &root, event, &mut changed);
+        }
+        if !rescan && changed.is_empty() {
+            continue;
+        }
+
+        // Tasks may have been added, removed or changed their inputs, so check what they are now
+        let previous = inputs;
+        let (tasks, current) = scan(log, directory);
+        inputs = current;
+
+        let affected = if rescan {
+            None
+        } else {
+            affected_tasks(&changed, &previous, &inputs)
+        };
+        let affected = match affected {
+            Some(ref affected) if affected.is_empty() => continue,
+            Some(affected) => Some(with_dependents(directory, &tasks, affected)),
+            None => None,
+        };
+
+        for path in &changed {
+            debug!(log, "Changed \"{}\"", path.display());
+        }
+        run_build(log, directory, options, affected);
+    }
+}
+
+fn run_build(
+    log: &Logger, directory: &Path, options: &BuildOptions, tasks: Option<BTreeSet<PathBuf>>
+) {
+    let mut options = options.clone();
+    options.tasks = tasks;
+
+    // A failed build is something to fix before the next change, not a reason to stop watching
+    if let Err(e) = build::build(log, directory, &options) {
+        error!(log, "Build failed: {}", e);
+    }
+
+    info!(log, "Watching for changes, press Ctrl+C to stop");
+}
+
+/// Adds the files an event is about to the changed files, relative to the project directory.
+/// Returns true if everything has to be checked again.
+fn add_changes(
+    log: &Logger, root: &Path, event: DebouncedEvent, changed: &mut BTreeSet<PathBuf>
+) -> bool {
+    let paths = match event {
+        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) |
+        DebouncedEvent::Remove(path) => vec!(path),
+        DebouncedEvent::Rename(from, to) => vec!(from, to),
+        DebouncedEvent::Rescan => return true,
+        DebouncedEvent::Error(e, path) => {
+            warn!(log, "Error while watching for changes: {}", e);
+            path.into_iter().collect()
+        },
+        // Notices come before the actual event, and permissions don't change contents
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) |
+        DebouncedEvent::Chmod(_) => Vec::new(),
+    };
+
+    for path in paths {
+        let relative = match path.strip_prefix(root) {
+            Ok(v) => graph::normalize(v),
+            Err(_) => continue,
+        };
+
+        // Everything in target is generated by the build itself
+        if !relative.starts_with("target") {
+            changed.insert(relative);
+        }
+    }
+
+    false
+}
+
+/// Finds the tasks in a project and their input files. Tasks that can't be read are included
+/// without inputs, so the build can tell what's wrong with them when they change.
+fn scan(log: &Logger, directory: &Path) -> (Vec<TaskFile>, TaskInputs) {
+    let mut tasks = Vec::new();
+    let mut inputs = TaskInputs::new();
+    for task in project::find_tasks(log, directory) {
+        match task {
+            Ok(task) => {
+                let files = cache::find_inputs(directory, &task.path, &task.toml).iter()
+                    .map(|f| graph::normalize(f))
+                    .collect();
+                inputs.insert(task_path(directory, &task.path), files);
+                tasks.push(task);
+            },
+            Err((path, _)) => {
+                inputs.insert(task_path(directory, &path), BTreeSet::new());
+            },
+        }
+    }
+
+    (tasks, inputs)
+}
+
+fn task_path(directory: &Path, path: &Path) -> PathBuf {
+    graph::normalize(project::relative_path(directory, path))
+}
+
+/// Finds the tasks affected by changed files, using the inputs of the tasks from before and after
+/// the change so removed and renamed files are also noticed. Returns None if all tasks are
+/// affected.
+fn affected_tasks(
+    changed: &BTreeSet<PathBuf>, previous: &TaskInputs, current: &TaskInputs
+) -> Option<BTreeSet<PathBuf>> {
+    if PROJECT_FILES.iter().any(|f| changed.contains(Path::new(f))) {
+        return None;
+    }
+
+    let mut affected = BTreeSet::new();
+    for inputs in &[previous, current] {
+        for (task, files) in inputs.iter() {
+            if changed.contains(task) || !files.is_disjoint(changed) {
+                affected.insert(task.clone());
+            }
+        }
+    }
+
+    // Removed tasks can't be built anymore
+    Some(affected.into_iter().filter(|t| current.contains_key(t)).collect())
+}
+
+/// Adds the tasks that depend on the affected tasks, so they're built with the changes.
+fn with_dependents(
+    directory: &Path, tasks: &[TaskFile], affected: BTreeSet<PathBuf>
+) -> BTreeSet<PathBuf> {
+    let cache = BuildCache::load(&directory.join("target").join("cache.json"));
+    let (graph, _) = graph::resolve_dependencies(directory, tasks, &cache);
+
+    let mut indices: BTreeSet<usize> = (0..tasks.len())
+        .filter(|i| affected.contains(&task_path(directory, &tasks[*i].path)))
+        .collect();
+    graph.with_dependents(&mut indices);
+
+    indices.into_iter().map(|i| task_path(directory, &tasks[i].path)).collect()
+}
+
+fn watch_error(error: notify::Error) -> Error {
+    Error::Task(format!("Unable to watch for changes: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+    use super::{affected_tasks, TaskInputs};
+
+    fn paths(values: &[&str]) -> BTreeSet<PathBuf> {
+        values.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn it_finds_affected_tasks() {
+        let mut previous = TaskInputs::new();
+        previous.insert("crate.toml".into(), paths(&["crate.fbx", "crate_open.fbx"]));
+        previous.insert("barrel.toml".into(), paths(&["barrel.fbx"]));
+        previous.insert("removed.toml".into(), paths(&["crate.fbx"]));
+
+        // The crate's animation got renamed, so it's only an input from before the change
+        let mut current = previous.clone();
+        current.insert("crate.toml".into(), paths(&["crate.fbx", "crate_opening.fbx"]));
+        current.remove(&PathBuf::from("removed.toml"));
+
+        assert!(affected_tasks(&paths(&["crate_open.fbx"]), &previous, &current) ==
+            Some(paths(&["crate.toml"])));
+        assert!(affected_tasks(&paths(&["barrel.toml", "notes.txt"]), &previous, &current) ==
+            Some(paths(&["barrel.toml"])));
+        assert!(affected_tasks(&paths(&["notes.txt"]), &previous, &current) == Some(paths(&[])));
+        assert!(affected_tasks(&paths(&["SoTo.Local.toml"]), &previous, &current).is_none());
+    }
+}
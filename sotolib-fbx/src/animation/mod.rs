@@ -1,53 +1,96 @@
 use OwnedProperty;
-use simple::{SimpleFbx, ObjectId, ObjectType, Property};
+use simple::{SimpleFbx, ObjectId, ObjectType, Property, TIME_SECOND};
+use id_name;
 
+/// The animation in a single animation stack, also known as a take.
 pub struct Animation {
+    /// Name of the animation stack, without the FBX class suffix.
+    pub name: String,
+    pub stack: ObjectId,
     curve_nodes: Vec<ObjectId>,
+    /// Time range of the animation, from the stack's "LocalStart" and "LocalStop" if it has them
+    /// or otherwise from the first and last key of all curves in the animation.
+    time_range: Option<(i64, i64)>,
 }
 
 impl Animation {
+    /// Gets the first animation stack in the file, ordered by name.
     pub fn from_simple(fbx: &SimpleFbx) -> Option<Self> {
-        // Find the first animation stack we can see TODO: Support multiple
-        if let Some((&stack_id, ref _stack)) = fbx.objects.iter()
-            .find(|&(_, o)| o.class.type_name() == "AnimationStack") {
-            // Found a stack to attach to, now we need all its curve nodes
-            let mut curve_nodes = Vec::new();
-            for layer in fbx.children_of(stack_id) {
-                for node in fbx.children_of(layer.id) {
-                    curve_nodes.push(node.id);
-                }
+        Self::all_from_simple(fbx).into_iter().next()
+    }
+
+    /// Gets all animation stacks in the file, ordered by name.
+    pub fn all_from_simple(fbx: &SimpleFbx) -> Vec<Self> {
+        let mut stacks: Vec<_> = fbx.objects.values()
+            .filter(|o| o.class == ObjectType::AnimationStack)
+            .map(|o| Self::from_stack(fbx, o.id))
+            .collect();
+        stacks.sort_by(|a, b| a.name.cmp(&b.name).then(a.stack.cmp(&b.stack)));
+        stacks
+    }
+
+    /// Gets the animation stack with the given name, if there is one.
+    pub fn by_name(fbx: &SimpleFbx, name: &str) -> Option<Self> {
+        Self::all_from_simple(fbx).into_iter().find(|a| a.name == name)
+    }
+
+    fn from_stack(fbx: &SimpleFbx, stack_id: ObjectId) -> Self {
+        let stack = &fbx.objects[&stack_id];
+
+        // We need all the curve nodes in all of the stack's layers
+        let mut curve_nodes = Vec::new();
+        for layer in fbx.children_of(stack_id) {
+            for node in fbx.children_of(layer.id) {
+                curve_nodes.push(node.id);
             }
+        }
 
-            Some(Animation {
-                curve_nodes: curve_nodes,
-            })
+        // If the stack doesn't tell us its range, it runs from the first key to the last key of
+        // any of its curves
+        let local_start = stack.properties.get("LocalStart").and_then(|p| p.values[0].get_i64());
+        let local_stop = stack.properties.get("LocalStop").and_then(|p| p.values[0].get_i64());
+        let time_range = if let (Some(start), Some(stop)) = (local_start, local_stop) {
+            Some((start, stop))
         } else {
-            // Nothing found
-            None
+            key_time_range(fbx, &curve_nodes)
+        };
+
+        Animation {
+            name: id_name(&stack.name).unwrap_or_default(),
+            stack: stack_id,
+            curve_nodes: curve_nodes,
+            time_range: time_range,
         }
     }
 
-    pub fn frame_count(&self, fbx: &SimpleFbx) -> i32 {
-        // Find the first curve node with curves attached
-        for &node in &self.curve_nodes {
-            let curves = fbx.driven_properties_of(node);
-            if curves.len() > 0 {
-                let curve_obj = &fbx.objects[&curves[0].driver];
-
-                // We've found a curve, return its frame count
-                if let ObjectType::AnimationCurve(ref curve) = curve_obj.class {
-                    return curve.frames
-                } else {
-                    panic!("Non-curve when curve was expected, found: {:?}", curve_obj);
-                }
-            }
+    /// Gets the start and stop time of the animation, if it has any.
+    pub fn time_range(&self) -> Option<(i64, i64)> {
+        self.time_range
+    }
+
+    /// Gets the amount of frames needed to sample the entire animation at the given frame rate.
+    pub fn frame_count(&self, frame_rate: f32) -> i32 {
+        if let Some((start, stop)) = self.time_range {
+            // Allow for a bit of rounding error so we don't miss the last key
+            let seconds = (stop - start) as f64 / TIME_SECOND as f64;
+            (seconds * frame_rate as f64 + 0.001).floor() as i32 + 1
+        } else {
+            0
         }
+    }
+
+    /// Gets the FBX time of a frame sampled at the given frame rate.
+    pub fn frame_time(&self, frame: i32, frame_rate: f32) -> i64 {
+        let start = self.time_range.map(|r| r.0).unwrap_or(0);
+        start + (frame as f64 * TIME_SECOND as f64 / frame_rate as f64).round() as i64
+    }
 
-        // We didn't find anything, just return 0
-        0
+    pub fn transform_fbx_to_frame(&self, fbx: &mut SimpleFbx, frame: i32, frame_rate: f32) {
+        let time = self.frame_time(frame, frame_rate);
+        self.transform_fbx_to_time(fbx, time);
     }
 
-    pub fn transform_fbx_to_frame(&self, fbx: &mut SimpleFbx, frame: i32) {
+    pub fn transform_fbx_to_time(&self, fbx: &mut SimpleFbx, time: i64) {
         // Go over all curve nodes of this animation
         for &node in &self.curve_nodes {
             // First, update the node using curves that drive it
@@ -57,7 +100,7 @@ impl Animation {
                     let curve = &fbx.objects[&prop.driver].class.as_animation_curve().unwrap();
 
                     // Now, apply the curve on the node's property
-                    curve.values[frame as usize].clone()
+                    curve.evaluate(time)
                 };
 
                 // Get the property we need to change
@@ -105,9 +148,30 @@ impl Animation {
     }
 }
 
+fn key_time_range(fbx: &SimpleFbx, curve_nodes: &[ObjectId]) -> Option<(i64, i64)> {
+    let mut time_range: Option<(i64, i64)> = None;
+
+    for &node in curve_nodes {
+        for prop in fbx.driven_properties_of(node) {
+            let curve = match fbx.objects[&prop.driver].class.as_animation_curve() {
+                Some(curve) => curve,
+                None => continue,
+            };
+            if let Some((start, stop)) = curve.time_range() {
+                time_range = Some(match time_range {
+                    Some((s, e)) => (s.min(start), e.max(stop)),
+                    None => (start, stop),
+                });
+            }
+        }
+    }
+
+    time_range
+}
+
 #[cfg(test)]
 mod tests {
-    use simple::{ObjectType, AnimationCurve, Property};
+    use simple::{ObjectType, AnimationCurve, AnimationKey, Interpolation, Property};
     use OwnedProperty;
     use super::*;
 
@@ -124,7 +188,7 @@ mod tests {
 
         let anim = Animation::from_simple(&fbx).unwrap();
 
-        assert!(anim.frame_count(&fbx) == 0);
+        assert!(anim.frame_count(30.0) == 0);
     }
 
     #[test]
@@ -133,7 +197,8 @@ mod tests {
 
         let anim = Animation::from_simple(&fbx).unwrap();
 
-        assert!(anim.frame_count(&fbx) == 2);
+        assert!(anim.frame_count(30.0) == 2);
+        assert!(anim.frame_count(60.0) == 3);
     }
 
     #[test]
@@ -141,10 +206,51 @@ mod tests {
         let mut fbx = init_fbx_with_node();
         let anim = Animation::from_simple(&fbx).unwrap();
 
-        anim.transform_fbx_to_frame(&mut fbx, 1);
+        anim.transform_fbx_to_frame(&mut fbx, 1, 30.0);
+
+        assert!(blah_value(&fbx) == 2.0);
+    }
+
+    #[test]
+    fn it_samples_between_and_past_keys() {
+        let mut fbx = init_fbx_with_node();
+        let anim = Animation::from_simple(&fbx).unwrap();
+
+        anim.transform_fbx_to_frame(&mut fbx, 1, 60.0);
+        assert!(blah_value(&fbx) == 1.5);
 
+        anim.transform_fbx_to_frame(&mut fbx, 10, 30.0);
+        assert!(blah_value(&fbx) == 2.0);
+    }
+
+    #[test]
+    fn it_finds_all_stacks_by_name() {
+        let mut fbx = init_fbx_with_node();
+        let walk = fbx.new_object(ObjectType::AnimationStack);
+        {
+            let walk = fbx.objects.get_mut(&walk).unwrap();
+            walk.name = "walk\u{0}\u{1}AnimStack".into();
+            for (name, time) in &[("LocalStart", TIME_SECOND), ("LocalStop", TIME_SECOND * 2)] {
+                walk.properties.insert(name.to_string(), Property {
+                    name: name.to_string(),
+                    values: vec!(OwnedProperty::I64(*time)),
+                });
+            }
+        }
+
+        let names: Vec<_> = Animation::all_from_simple(&fbx).into_iter().map(|a| a.name).collect();
+        let anim = Animation::by_name(&fbx, "walk").unwrap();
+
+        assert!(names == ["AnimationStack", "walk"]);
+        assert!(anim.stack == walk);
+        assert!(anim.time_range() == Some((TIME_SECOND, TIME_SECOND * 2)));
+        assert!(anim.frame_count(30.0) == 31);
+        assert!(Animation::by_name(&fbx, "run").is_none());
+    }
+
+    fn blah_value(fbx: &SimpleFbx) -> f32 {
         let model = fbx.objects.iter().find(|&(_, o)| o.class.type_name() == "FooBar").unwrap();
-        assert!(model.1.properties["Blah"].values[0].get_f32().unwrap() == 2.0);
+        model.1.properties["Blah"].values[0].get_f32().unwrap()
     }
 
     fn init_fbx_with_node() -> SimpleFbx {
@@ -177,11 +283,21 @@ mod tests {
         fbx.connect_property_object(foobar_id, "Blah", node_id);
 
         let curve_id = fbx.new_object(ObjectType::AnimationCurve(AnimationCurve {
-            frames: 2,
-            values: vec!(1.0, 2.0),
+            default: 0.0,
+            keys: vec!(linear_key(0, 1.0), linear_key(TIME_SECOND / 30, 2.0)),
         }));
         fbx.connect_property_object(node_id, "d|Blah", curve_id);
 
         fbx
     }
+
+    fn linear_key(time: i64, value: f32) -> AnimationKey {
+        AnimationKey {
+            time,
+            value,
+            interpolation: Interpolation::Linear,
+            right_slope: 0.0,
+            next_left_slope: 0.0,
+        }
+    }
 }
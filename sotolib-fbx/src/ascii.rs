@@ -0,0 +1,427 @@
+//! Parser for ASCII FBX files, producing the same node tree as the binary parser.
+
+use fbx_direct::common::OwnedProperty;
+
+use {RawNode, Error};
+
+/// Array nodes that always contain floating point data, even if an exporter happened to write
+/// every value in them without a decimal point.
+const FLOAT_ARRAYS: &[&str] = &[
+    "Vertices", "Normals", "NormalsW", "Binormals", "BinormalsW", "Tangents", "TangentsW", "UV",
+    "Colors", "Weights", "Transform", "TransformLink", "TransformAssociateModel", "Matrix",
+    "KeyValueFloat", "KeyAttrDataFloat", "FullWeights",
+];
+
+/// Array nodes that contain 64-bit integers.
+const I64_ARRAYS: &[&str] = &["KeyTime"];
+
+pub fn parse(text: &str) -> Result<Vec<RawNode>, Error> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+
+    // The file itself is just a list of nodes without a surrounding block
+    let mut nodes = Vec::new();
+    while parser.peek().is_some() {
+        nodes.push(parser.parse_node()?);
+    }
+
+    // ASCII FBX writes object names as "Class::Name" while binary FBX uses "Name\0\x01Class", so
+    // convert them to make sure everything else can deal with both the same way
+    for objects in nodes.iter_mut().filter(|n| n.name == "Objects") {
+        for object in &mut objects.children {
+            if let Some(&mut OwnedProperty::String(ref mut name)) = object.properties.get_mut(1) {
+                if let Some(separator) = name.find("::") {
+                    *name = format!("{}\u{0}\u{1}{}", &name[separator + 2..], &name[..separator]);
+                }
+            }
+        }
+    }
+
+    Ok(nodes)
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenKind {
+    /// A node name, written directly followed by a colon.
+    Name(String),
+    /// A value that isn't quoted, such as a number or a single character like "T".
+    Word(String),
+    String(String),
+    /// Element count of an array, written as "*N".
+    Count(usize),
+    Comma,
+    OpenBrace,
+    CloseBrace,
+}
+
+struct Token {
+    line: usize,
+    kind: TokenKind,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        let kind = match c {
+            '\n' => { line += 1; continue; },
+            c if c.is_whitespace() => continue,
+            // Comments last until the end of the line
+            ';' => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+                continue;
+            },
+            ',' => TokenKind::Comma,
+            '{' => TokenKind::OpenBrace,
+            '}' => TokenKind::CloseBrace,
+            '"' => {
+                let start_line = line;
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' { line += 1; }
+                            value.push(c);
+                        },
+                        None => return Err(Error::AsciiParse(start_line, "Unterminated string".into())),
+                    }
+                }
+                TokenKind::String(value.replace("&quot;", "\""))
+            },
+            '*' => {
+                let mut value = String::new();
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    value.push(chars.next().unwrap());
+                }
+                let count = value.parse()
+                    .map_err(|_| Error::AsciiParse(line, "Invalid array count".into()))?;
+                TokenKind::Count(count)
+            },
+            c => {
+                let mut value = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ',' || c == ':' || c == '{' || c == '}' || c == '"' {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+
+                // Names are directly followed by a colon
+                if chars.peek() == Some(&':') {
+                    chars.next();
+                    TokenKind::Name(value)
+                } else {
+                    TokenKind::Word(value)
+                }
+            },
+        };
+
+        tokens.push(Token {
+            line,
+            kind,
+        });
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_token(&mut self) -> Result<&Token, Error> {
+        let last_line = self.tokens.last().map(|t| t.line).unwrap_or(0);
+        let token = self.tokens.get(self.position)
+            .ok_or_else(|| Error::AsciiParse(last_line, "Unexpected end of file".into()))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_is(&self, kind: &TokenKind) -> bool {
+        self.peek().map(|t| &t.kind == kind).unwrap_or(false)
+    }
+
+    fn parse_node(&mut self) -> Result<RawNode, Error> {
+        let token = self.next_token()?;
+        let line = token.line;
+        let name = match token.kind {
+            TokenKind::Name(ref name) => name.clone(),
+            _ => return Err(Error::AsciiParse(line, "Expected node name".into())),
+        };
+
+        // Arrays have a special layout, with their values in a child node "a"
+        if let Some(&Token { kind: TokenKind::Count(count), .. }) = self.peek() {
+            self.position += 1;
+            return self.parse_array(name, line, count);
+        }
+
+        // Read in the properties, which are separated by commas
+        let mut properties = Vec::new();
+        if self.peek().is_some_and(|t| is_value(&t.kind)) {
+            properties.push(self.parse_value()?);
+            while self.next_is(&TokenKind::Comma) {
+                self.position += 1;
+                properties.push(self.parse_value()?);
+            }
+        }
+
+        // Finally the children, if there's a block
+        let mut children = Vec::new();
+        if self.next_is(&TokenKind::OpenBrace) {
+            self.position += 1;
+            while !self.next_is(&TokenKind::CloseBrace) {
+                if self.peek().is_none() {
+                    return Err(Error::AsciiParse(line, format!("Unterminated node \"{}\"", name)));
+                }
+                children.push(self.parse_node()?);
+            }
+            self.position += 1;
+        }
+
+        let mut node = RawNode {
+            name,
+            properties,
+            children,
+        };
+
+        // Properties have their type stored in them, so we can give them the same types as the
+        // binary format does
+        if node.name == "P" {
+            coerce_property_values(&mut node);
+        }
+
+        Ok(node)
+    }
+
+    fn parse_array(&mut self, name: String, line: usize, count: usize) -> Result<RawNode, Error> {
+        // The values are in a block with a single "a" node
+        if !self.next_is(&TokenKind::OpenBrace) {
+            return Err(Error::AsciiParse(line, format!("Expected block for array \"{}\"", name)));
+        }
+        self.position += 1;
+
+        let mut values = Vec::new();
+        if !self.next_is(&TokenKind::CloseBrace) {
+            let values_node = self.parse_node()?;
+            if values_node.name != "a" {
+                return Err(Error::AsciiParse(
+                    line, format!("Expected \"a\" in array \"{}\", found \"{}\"", name, values_node.name)
+                ));
+            }
+            values = values_node.properties;
+        }
+        if !self.next_is(&TokenKind::CloseBrace) {
+            return Err(Error::AsciiParse(line, format!("Unterminated array \"{}\"", name)));
+        }
+        self.position += 1;
+
+        if values.len() != count {
+            return Err(Error::AsciiParse(line, format!(
+                "Array \"{}\" has {} values but should have {}", name, values.len(), count
+            )));
+        }
+
+        let array = array_property(&name, values)
+            .ok_or_else(|| Error::AsciiParse(line, format!("Array \"{}\" is not numeric", name)))?;
+        Ok(RawNode {
+            name,
+            properties: vec!(array),
+            children: Vec::new(),
+        })
+    }
+
+    fn parse_value(&mut self) -> Result<OwnedProperty, Error> {
+        let token = self.next_token()?;
+        match token.kind {
+            TokenKind::String(ref value) => Ok(OwnedProperty::String(value.clone())),
+            TokenKind::Word(ref value) => Ok(word_property(value)),
+            _ => Err(Error::AsciiParse(token.line, "Expected value".into())),
+        }
+    }
+}
+
+fn is_value(kind: &TokenKind) -> bool {
+    matches!(*kind, TokenKind::String(_) | TokenKind::Word(_))
+}
+
+fn word_property(value: &str) -> OwnedProperty {
+    // Single character booleans
+    match value {
+        "T" | "Y" => return OwnedProperty::Bool(true),
+        "F" | "N" => return OwnedProperty::Bool(false),
+        _ => {},
+    }
+
+    // Numbers, integers use the smallest type they fit in like the binary format
+    if let Ok(value) = value.parse::<i64>() {
+        if value >= i32::MIN as i64 && value <= i32::MAX as i64 {
+            return OwnedProperty::I32(value as i32);
+        }
+        return OwnedProperty::I64(value);
+    }
+    if let Ok(value) = value.parse::<f64>() {
+        return OwnedProperty::F64(value);
+    }
+
+    // Anything else we'll just have to keep as text
+    OwnedProperty::String(value.to_string())
+}
+
+fn array_property(name: &str, values: Vec<OwnedProperty>) -> Option<OwnedProperty> {
+    let is_float = FLOAT_ARRAYS.contains(&name) ||
+        values.iter().any(|v| matches!(*v, OwnedProperty::F64(_)));
+    let is_i64 = I64_ARRAYS.contains(&name) ||
+        values.iter().any(|v| matches!(*v, OwnedProperty::I64(_)));
+
+    if is_float {
+        values.iter().map(to_f64).collect::<Option<_>>().map(OwnedProperty::VecF64)
+    } else if is_i64 {
+        values.iter().map(|v| v.get_i64()).collect::<Option<_>>().map(OwnedProperty::VecI64)
+    } else {
+        values.iter().map(|v| v.get_i32()).collect::<Option<_>>().map(OwnedProperty::VecI32)
+    }
+}
+
+fn coerce_property_values(node: &mut RawNode) {
+    let kind = match node.properties.get(1).and_then(|p| p.get_string()) {
+        Some(v) => v.clone(),
+        None => return,
+    };
+
+    for value in node.properties.iter_mut().skip(4) {
+        let coerced = match kind.as_str() {
+            "KTime" | "ULongLong" => value.get_i64().map(OwnedProperty::I64),
+            "int" | "Integer" | "enum" | "bool" | "Bool" => value.get_i32().map(OwnedProperty::I32),
+            "KString" | "DateTime" | "object" | "Compound" => None,
+            _ => to_f64(value).map(OwnedProperty::F64),
+        };
+
+        if let Some(coerced) = coerced {
+            *value = coerced;
+        }
+    }
+}
+
+fn to_f64(value: &OwnedProperty) -> Option<f64> {
+    match *value {
+        OwnedProperty::I32(v) => Some(v as f64),
+        OwnedProperty::I64(v) => Some(v as f64),
+        ref v => v.get_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fbx_direct::common::OwnedProperty;
+    use simple::{SimpleFbx, ObjectType};
+    use {RawFbx, Error, id_name};
+
+    const TRIANGLE: &str = r#"; FBX 7.4.0 project file
+; ----------------------------------------------------
+
+FBXHeaderExtension:  {
+	FBXHeaderVersion: 1003
+	Creator: "Some Exporter"
+}
+Objects:  {
+	Geometry: 1000, "Geometry::Triangle", "Mesh" {
+		Vertices: *9 {
+			a: 0,0,0,1,0,0,
+			0,1,0
+		}
+		PolygonVertexIndex: *3 {
+			a: 0,1,-3
+		}
+		LayerElementNormal: 0 {
+			MappingInformationType: "ByPolygonVertex"
+			ReferenceInformationType: "Direct"
+			Normals: *9 {
+				a: 0,0,1,0,0,1,0,0,1
+			}
+		}
+		LayerElementUV: 0 {
+			MappingInformationType: "ByPolygonVertex"
+			ReferenceInformationType: "IndexToDirect"
+			UV: *6 {
+				a: 0,0,1,0,0,1
+			}
+			UVIndex: *3 {
+				a: 0,1,2
+			}
+		}
+	}
+	Model: 2000, "Model::Triangle", "Mesh" {
+		Properties70:  {
+			P: "Lcl Translation", "Lcl Translation", "", "A",1,2.5,3
+			P: "RotationOrder", "enum", "", "",0
+		}
+		Shading: T
+		Culling: "CullingOff"
+	}
+}
+Connections:  {
+	;Model::Triangle, Model::RootNode
+	C: "OO",2000,0
+
+	;Geometry::Triangle, Model::Triangle
+	C: "OO",1000,2000
+}
+"#;
+
+    #[test]
+    fn it_parses_ascii_nodes() {
+        let fbx = RawFbx::parse(TRIANGLE.as_bytes()).unwrap();
+
+        assert!(fbx.nodes.len() == 3);
+        let header = fbx.nodes[0].find_child("FBXHeaderVersion").unwrap();
+        assert!(header.properties[0] == OwnedProperty::I32(1003));
+
+        let objects = &fbx.nodes[1];
+        let vertices = objects.children[0].find_child("Vertices").unwrap();
+        assert!(vertices.properties[0].get_vec_f32().unwrap().len() == 9);
+        let indices = objects.children[0].find_child("PolygonVertexIndex").unwrap();
+        assert!(indices.properties[0] == OwnedProperty::VecI32(vec!(0, 1, -3)));
+
+        let model = &objects.children[1];
+        assert!(id_name(model.properties[1].get_string().unwrap()).unwrap() == "Triangle");
+        assert!(model.find_child("Shading").unwrap().properties[0] == OwnedProperty::Bool(true));
+    }
+
+    #[test]
+    fn it_reads_ascii_into_simple_fbx() {
+        let fbx = SimpleFbx::from_raw(&RawFbx::parse(TRIANGLE.as_bytes()).unwrap()).unwrap();
+
+        let geometry = match fbx.objects[&1000].class {
+            ObjectType::Geometry(ref geometry) => geometry,
+            _ => panic!("Geometry not parsed"),
+        };
+        assert!(geometry.triangles().unwrap().len() == 1);
+        assert!(fbx.children_of(2000)[0].id == 1000);
+        assert!(fbx.objects[&2000].properties["Lcl Translation"].to_vector3() == [1.0, 2.5, 3.0]);
+    }
+
+    #[test]
+    fn it_refuses_wrong_array_counts() {
+        let result = RawFbx::parse("; FBX\nVertices: *4 {\n\ta: 0,1,2\n}\n".as_bytes());
+
+        match result {
+            Err(Error::AsciiParse(2, _)) => {},
+            _ => panic!("Didn't receive right error"),
+        }
+    }
+}
@@ -1,31 +1,44 @@
 extern crate fbx_direct;
+extern crate flate2;
 
 pub mod animation;
 pub mod simple;
+mod ascii;
 mod raw;
 mod tree;
+mod writer;
 
 pub use fbx_direct::reader::Error as FbxDirectError;
 pub use fbx_direct::common::OwnedProperty;
 
 pub use self::raw::{RawFbx, RawNode};
 pub use self::tree::{ObjectTreeNode};
+pub use self::writer::{WriteOptions};
 
 use std::fmt::{self, Display, Formatter};
+use std::io;
 
 #[derive(Debug)]
 pub enum Error {
     FbxDirect(FbxDirectError),
+    Io(io::Error),
+    AsciiParse(usize, String), // Line, Message
     WrongNode(String, String), // Expected, Actual
     WrongNodeLayout(String),
+    InvalidPolygon(usize, String), // Polygon, Reason
+    UnsupportedVersion(u32),
 }
 
 impl ::std::error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::FbxDirect(_) => "fbx_direct Parsing Error",
+            Error::Io(_) => "IO Error",
+            Error::AsciiParse(_, _) => "ASCII FBX Parsing Error",
             Error::WrongNode(_, _) => "Wrong Node",
             Error::WrongNodeLayout(_) => "Wrong Node Layout",
+            Error::InvalidPolygon(_, _) => "Invalid Polygon",
+            Error::UnsupportedVersion(_) => "Unsupported FBX Version",
         }
     }
 }
@@ -35,6 +48,12 @@ impl Display for Error {
         match *self {
             Error::WrongNode(ref expected, ref actual) =>
                 write!(f, "Found {} instead of expected {}", actual, expected),
+            Error::AsciiParse(line, ref message) =>
+                write!(f, "Line {}: {}", line, message),
+            Error::InvalidPolygon(polygon, ref reason) =>
+                write!(f, "Can't triangulate polygon {}: {}", polygon, reason),
+            Error::UnsupportedVersion(version) =>
+                write!(f, "FBX version {} is not supported", version),
             _ => write!(f, "{:?}", self),
         }
     }
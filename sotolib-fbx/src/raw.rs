@@ -1,11 +1,13 @@
 use std::io::{Read};
 
+use ascii;
+
 use fbx_direct::common::OwnedProperty;
 use fbx_direct::reader::{FbxEvent, EventReader};
 
 use {FbxDirectError, Error};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RawNode {
     pub name: String,
     pub properties: Vec<OwnedProperty>,
@@ -34,20 +36,60 @@ impl RawNode {
         Ok(node)
     }
 
+    pub fn new<S: Into<String>>(name: S, properties: Vec<OwnedProperty>) -> Self {
+        RawNode {
+            name: name.into(),
+            properties,
+            children: Vec::new(),
+        }
+    }
+
     pub fn find_child(&self, name: &str) -> Option<&RawNode> {
         self.children.iter().find(|c| c.name == name)
     }
+
+    pub fn find_child_mut(&mut self, name: &str) -> Option<&mut RawNode> {
+        self.children.iter_mut().find(|c| c.name == name)
+    }
+
+    /// Replaces the first child with the same name as the given node, or adds it to the end if
+    /// there is none.
+    pub fn set_child(&mut self, node: RawNode) {
+        if let Some(child) = self.find_child_mut(&node.name) {
+            *child = node;
+            return;
+        }
+        self.children.push(node);
+    }
+
+    /// Removes all children with the given name.
+    pub fn remove_child(&mut self, name: &str) {
+        self.children.retain(|c| c.name != name);
+    }
 }
 
-#[derive(Debug)]
+pub const BINARY_MAGIC: &[u8] = b"Kaydara FBX Binary  \0";
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct RawFbx {
     pub nodes: Vec<RawNode>,
 }
 
 impl RawFbx {
-    pub fn parse<R: Read>(read: R) -> Result<Self, Error> {
+    /// Parses a binary or ASCII FBX file, the format is detected from the header.
+    pub fn parse<R: Read>(mut read: R) -> Result<Self, Error> {
+        // We need to look at the header to know what format we're dealing with
+        let mut data = Vec::new();
+        read.read_to_end(&mut data).map_err(Error::Io)?;
+        if !data.starts_with(BINARY_MAGIC) {
+            let nodes = ascii::parse(&String::from_utf8_lossy(&data))?;
+            return Ok(RawFbx {
+                nodes: nodes,
+            });
+        }
+
         // Set up the parser
-        let mut parser = EventReader::new(read);
+        let mut parser = EventReader::new(data.as_slice());
         let mut fbx = RawFbx {
             nodes: Vec::new(),
         };
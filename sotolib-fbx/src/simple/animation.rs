@@ -1,24 +1,307 @@
-use {RawNode};
+use std::borrow::{Cow};
 
-#[derive(Clone, Debug, Default)]
+use {RawNode, OwnedProperty, Error};
+
+/// FBX time units in one second.
+pub const TIME_SECOND: i64 = 46_186_158_000;
+
+// Bits in KeyAttrFlags we care about
+const INTERPOLATION_CONSTANT: i32 = 0x0000_0002;
+const INTERPOLATION_LINEAR: i32 = 0x0000_0004;
+const INTERPOLATION_CUBIC: i32 = 0x0000_0008;
+const CONSTANT_NEXT: i32 = 0x0000_0100;
+const TANGENT_USER: i32 = 0x0000_0400;
+
+/// Tangent weights of 1/3 in both directions, stored as two packed 16 bit values.
+const DEFAULT_WEIGHTS: u32 = 0x0d05_0d05;
+
+/// How the value of a curve changes between a key and the one after it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Keep the value of this key until the next key.
+    Constant,
+    /// Use the value of the next key right after this key.
+    ConstantNext,
+    Linear,
+    /// Cubic Hermite interpolation using the key's tangent slopes.
+    Cubic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationKey {
+    /// Time of this key, in FBX time units.
+    pub time: i64,
+    pub value: f32,
+    pub interpolation: Interpolation,
+    /// Slope of the tangent leaving this key, in value per second.
+    pub right_slope: f32,
+    /// Slope of the tangent arriving at the next key, in value per second.
+    pub next_left_slope: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct AnimationCurve {
-    pub frames: i32,
-    pub values: Vec<f32>,
+    /// Value of the curve if it doesn't have any keys.
+    pub default: f32,
+    /// Keys in the curve, sorted by time.
+    pub keys: Vec<AnimationKey>,
 }
 
 impl AnimationCurve {
-    pub fn from_node(node: &RawNode) -> Self {
-        // Just parse in the entire list of key values, we're assuming each is a frame which will
-        // be correct for baked animations. TODO: Support non-baked
-        let values = node_to_floats(node.find_child("KeyValueFloat").unwrap());
+    pub fn from_node(node: &RawNode) -> Result<Self, Error> {
+        let default = node.find_child("Default")
+            .and_then(|n| n.properties[0].get_f32())
+            .unwrap_or(0.0);
+        let times = child_array(node, "KeyTime", OwnedProperty::get_vec_i64)?;
+        let values = child_array(node, "KeyValueFloat", OwnedProperty::get_vec_f32)?;
+        if times.len() != values.len() {
+            return Err(Error::WrongNodeLayout(format!(
+                "AnimationCurve has {} key times but {} values", times.len(), values.len()
+            )));
+        }
 
-        AnimationCurve {
-            frames: values.len() as i32,
-            values: values,
+        // Key attributes are shared by consecutive keys, the reference count says how many
+        let flags = child_array(node, "KeyAttrFlags", OwnedProperty::get_vec_i32)?;
+        let data = child_array(node, "KeyAttrDataFloat", OwnedProperty::get_vec_f32)?;
+        let ref_counts = child_array(node, "KeyAttrRefCount", OwnedProperty::get_vec_i32)?;
+        if flags.len() != ref_counts.len() || data.len() != flags.len() * 4 {
+            return Err(Error::WrongNodeLayout(format!(
+                "AnimationCurve has {} key attribute flags, {} data values and {} ref counts",
+                flags.len(), data.len(), ref_counts.len()
+            )));
+        }
+        let mut attributes = Vec::new();
+        for (i, count) in ref_counts.iter().enumerate() {
+            for _ in 0..*count {
+                attributes.push((flags[i], &data[i * 4..i * 4 + 4]));
+            }
+        }
+
+        let mut keys = Vec::new();
+        for (i, (time, value)) in times.iter().zip(values.iter()).enumerate() {
+            // Keys without attributes are linear, which is what baked curves need anyways
+            let (flags, data) = attributes.get(i).cloned()
+                .unwrap_or((INTERPOLATION_LINEAR, &[0.0; 4]));
+            let interpolation = if flags & INTERPOLATION_CONSTANT != 0 {
+                if flags & CONSTANT_NEXT != 0 {
+                    Interpolation::ConstantNext
+                } else {
+                    Interpolation::Constant
+                }
+            } else if flags & INTERPOLATION_CUBIC != 0 {
+                Interpolation::Cubic
+            } else {
+                Interpolation::Linear
+            };
+
+            keys.push(AnimationKey {
+                time: *time,
+                value: *value,
+                interpolation,
+                right_slope: data[0],
+                next_left_slope: data[1],
+            });
+        }
+        keys.sort_by_key(|k| k.time);
+
+        Ok(AnimationCurve {
+            default,
+            keys,
+        })
+    }
+
+    /// Gets the time of the first and last key, if the curve has any keys.
+    pub fn time_range(&self) -> Option<(i64, i64)> {
+        match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => Some((first.time, last.time)),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the curve at a time in FBX time units. Before the first key and after the last
+    /// key the curve keeps the value of those keys. Tangent weights are not supported, cubic
+    /// segments are evaluated as if the weights are the default 1/3.
+    pub fn evaluate(&self, time: i64) -> f32 {
+        let first = match self.keys.first() {
+            Some(first) => first,
+            None => return self.default,
+        };
+        if time <= first.time {
+            return first.value;
+        }
+
+        // Find the segment this time is in, the partition point is the first key after the time
+        let next_index = self.keys.partition_point(|k| k.time <= time);
+        if next_index >= self.keys.len() {
+            return self.keys[self.keys.len() - 1].value;
+        }
+        let key = &self.keys[next_index - 1];
+        let next = &self.keys[next_index];
+
+        let duration = (next.time - key.time) as f64;
+        let s = ((time - key.time) as f64 / duration) as f32;
+        match key.interpolation {
+            Interpolation::Constant => key.value,
+            Interpolation::ConstantNext => next.value,
+            Interpolation::Linear => key.value + (next.value - key.value) * s,
+            Interpolation::Cubic => {
+                // Slopes are per second, scale them to the length of this segment
+                let seconds = (duration / TIME_SECOND as f64) as f32;
+                let m0 = key.right_slope * seconds;
+                let m1 = key.next_left_slope * seconds;
+
+                let s2 = s * s;
+                let s3 = s2 * s;
+                (2.0 * s3 - 3.0 * s2 + 1.0) * key.value
+                    + (s3 - 2.0 * s2 + s) * m0
+                    + (-2.0 * s3 + 3.0 * s2) * next.value
+                    + (s3 - s2) * m1
+            },
+        }
+    }
+
+    /// Writes the curve data into an "AnimationCurve" node, replacing the data already in there.
+    pub fn write_to_node(&self, node: &mut RawNode) {
+        node.set_child(RawNode::new("Default", vec!(OwnedProperty::F64(self.default as f64))));
+        if node.find_child("KeyVer").is_none() {
+            node.set_child(RawNode::new("KeyVer", vec!(OwnedProperty::I32(4009))));
+        }
+        node.set_child(RawNode::new("KeyTime", vec!(OwnedProperty::VecI64(
+            self.keys.iter().map(|k| k.time).collect()
+        ))));
+        node.set_child(RawNode::new("KeyValueFloat", vec!(OwnedProperty::VecF32(
+            self.keys.iter().map(|k| k.value).collect()
+        ))));
+
+        // Consecutive keys with the same attributes share them
+        let mut flags: Vec<i32> = Vec::new();
+        let mut data: Vec<f32> = Vec::new();
+        let mut ref_counts: Vec<i32> = Vec::new();
+        for key in &self.keys {
+            let key_flags = match key.interpolation {
+                Interpolation::Constant => INTERPOLATION_CONSTANT,
+                Interpolation::ConstantNext => INTERPOLATION_CONSTANT | CONSTANT_NEXT,
+                Interpolation::Linear => INTERPOLATION_LINEAR | TANGENT_USER,
+                Interpolation::Cubic => INTERPOLATION_CUBIC | TANGENT_USER,
+            };
+            let key_data = [
+                key.right_slope, key.next_left_slope, f32::from_bits(DEFAULT_WEIGHTS), 0.0,
+            ];
+
+            let last = flags.len();
+            if last > 0 && flags[last - 1] == key_flags && data[(last - 1) * 4..] == key_data {
+                ref_counts[last - 1] += 1;
+            } else {
+                flags.push(key_flags);
+                data.extend_from_slice(&key_data);
+                ref_counts.push(1);
+            }
         }
+        node.set_child(RawNode::new("KeyAttrFlags", vec!(OwnedProperty::VecI32(flags))));
+        node.set_child(RawNode::new("KeyAttrDataFloat", vec!(OwnedProperty::VecF32(data))));
+        node.set_child(RawNode::new("KeyAttrRefCount", vec!(OwnedProperty::VecI32(ref_counts))));
     }
 }
 
-fn node_to_floats(node: &RawNode) -> Vec<f32> {
-    node.properties[0].get_vec_f32().unwrap().iter().map(|v| *v).collect()
+/// Reads the array in the first property of a child node, or an empty array if there's no child.
+fn child_array<T: Clone, F>(node: &RawNode, name: &str, get: F) -> Result<Vec<T>, Error>
+    where F: for<'a> Fn(&'a OwnedProperty) -> Option<Cow<'a, [T]>>
+{
+    let child = match node.find_child(name) {
+        Some(child) => child,
+        None => return Ok(Vec::new()),
+    };
+
+    child.properties.first().and_then(get)
+        .map(|v| v.into_owned())
+        .ok_or_else(|| Error::WrongNodeLayout(format!("AnimationCurve {} is not an array", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_interpolates_linear_keys() {
+        let curve = curve(vec!(
+            key(0, 1.0, Interpolation::Linear), key(10, 3.0, Interpolation::Linear),
+        ));
+
+        assert!(curve.evaluate(5) == 2.0);
+        assert!(curve.evaluate(-5) == 1.0);
+        assert!(curve.evaluate(20) == 3.0);
+    }
+
+    #[test]
+    fn it_interpolates_constant_keys() {
+        let curve = curve(vec!(
+            key(0, 1.0, Interpolation::Constant), key(10, 3.0, Interpolation::ConstantNext),
+            key(20, 5.0, Interpolation::Linear),
+        ));
+
+        assert!(curve.evaluate(9) == 1.0);
+        assert!(curve.evaluate(11) == 5.0);
+    }
+
+    #[test]
+    fn it_interpolates_cubic_keys() {
+        // With slopes matching a straight line, a cubic segment is the same as a linear one
+        let mut start = key(0, 0.0, Interpolation::Cubic);
+        start.right_slope = 2.0;
+        start.next_left_slope = 2.0;
+        let curve = curve(vec!(start, key(TIME_SECOND, 2.0, Interpolation::Cubic)));
+
+        assert!((curve.evaluate(TIME_SECOND / 4) - 0.5).abs() < 0.0001);
+
+        // And with flat slopes it eases in and out
+        let mut flat = curve.clone();
+        flat.keys[0].right_slope = 0.0;
+        flat.keys[0].next_left_slope = 0.0;
+
+        assert!(flat.evaluate(TIME_SECOND / 4) < 0.5);
+        assert!((flat.evaluate(TIME_SECOND / 2) - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn it_gives_default_without_keys() {
+        let mut curve = curve(Vec::new());
+        curve.default = 4.0;
+
+        assert!(curve.evaluate(100) == 4.0);
+        assert!(curve.time_range().is_none());
+    }
+
+    #[test]
+    fn it_writes_curves_that_read_back() {
+        let mut cubic = key(20, 5.0, Interpolation::Cubic);
+        cubic.right_slope = 1.5;
+        let curve = curve(vec!(
+            key(0, 1.0, Interpolation::Linear), key(10, 3.0, Interpolation::Linear), cubic,
+            key(30, 2.0, Interpolation::Constant),
+        ));
+        let mut node = RawNode::new("AnimationCurve", Vec::new());
+
+        curve.write_to_node(&mut node);
+
+        assert!(AnimationCurve::from_node(&node).unwrap() == curve);
+        assert!(node.find_child("KeyAttrRefCount").unwrap().properties[0] ==
+            OwnedProperty::VecI32(vec!(2, 1, 1)));
+    }
+
+    fn curve(keys: Vec<AnimationKey>) -> AnimationCurve {
+        AnimationCurve {
+            default: 0.0,
+            keys,
+        }
+    }
+
+    fn key(time: i64, value: f32, interpolation: Interpolation) -> AnimationKey {
+        AnimationKey {
+            time,
+            value,
+            interpolation,
+            right_slope: 0.0,
+            next_left_slope: 0.0,
+        }
+    }
 }
@@ -0,0 +1,184 @@
+use {RawNode, OwnedProperty, Error};
+
+/// A skin deformer, connects a geometry to the clusters that deform it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Skin {
+}
+
+impl Skin {
+    pub fn from_node(_node: &RawNode) -> Self {
+        Skin {
+        }
+    }
+}
+
+/// A skin cluster, contains the influence of a single bone on the control points of a geometry.
+/// The bone itself is the model connected as a child of the cluster.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cluster {
+    /// Indices of the control points in the geometry this cluster affects.
+    pub indexes: Vec<u32>,
+    /// Weights for the control points in `indexes`.
+    pub weights: Vec<f32>,
+    /// Global transform of the geometry at the time of binding, column-major.
+    pub transform: [f32; 16],
+    /// Global transform of the bone at the time of binding, column-major.
+    pub transform_link: [f32; 16],
+}
+
+impl Default for Cluster {
+    fn default() -> Self {
+        Cluster {
+            indexes: Vec::new(),
+            weights: Vec::new(),
+            transform: IDENTITY,
+            transform_link: IDENTITY,
+        }
+    }
+}
+
+impl Cluster {
+    pub fn from_node(node: &RawNode) -> Result<Self, Error> {
+        // Bones that don't influence any control points don't get indexes and weights at all
+        let indexes: Vec<u32> = if let Some(indexes_node) = node.find_child("Indexes") {
+            indexes_node.properties[0].get_vec_i32()
+                .ok_or_else(|| Error::WrongNodeLayout("Cluster Indexes is not an array".into()))?
+                .iter().map(|v| *v as u32).collect()
+        } else {
+            Vec::new()
+        };
+        let weights: Vec<f32> = if let Some(weights_node) = node.find_child("Weights") {
+            weights_node.properties[0].get_vec_f32()
+                .ok_or_else(|| Error::WrongNodeLayout("Cluster Weights is not an array".into()))?
+                .into_owned()
+        } else {
+            Vec::new()
+        };
+        if indexes.len() != weights.len() {
+            return Err(Error::WrongNodeLayout(format!(
+                "Cluster has {} indexes but {} weights", indexes.len(), weights.len()
+            )));
+        }
+
+        Ok(Cluster {
+            indexes,
+            weights,
+            transform: node_to_matrix(node, "Transform")?,
+            transform_link: node_to_matrix(node, "TransformLink")?,
+        })
+    }
+
+    /// Writes the cluster data into a "Deformer" node, replacing the data already in there.
+    pub fn write_to_node(&self, node: &mut RawNode) {
+        if self.indexes.is_empty() {
+            node.remove_child("Indexes");
+            node.remove_child("Weights");
+        } else {
+            node.set_child(RawNode::new("Indexes", vec!(OwnedProperty::VecI32(
+                self.indexes.iter().map(|v| *v as i32).collect()
+            ))));
+            node.set_child(RawNode::new("Weights", vec!(OwnedProperty::VecF64(
+                self.weights.iter().map(|v| *v as f64).collect()
+            ))));
+        }
+
+        node.set_child(matrix_to_node("Transform", &self.transform));
+        node.set_child(matrix_to_node("TransformLink", &self.transform_link));
+    }
+}
+
+const IDENTITY: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+fn node_to_matrix(node: &RawNode, name: &str) -> Result<[f32; 16], Error> {
+    let matrix_node = match node.find_child(name) {
+        Some(v) => v,
+        None => return Ok(IDENTITY),
+    };
+
+    let values = matrix_node.properties[0].get_vec_f32()
+        .ok_or_else(|| Error::WrongNodeLayout(format!("Cluster {} is not an array", name)))?;
+    if values.len() != 16 {
+        return Err(Error::WrongNodeLayout(format!(
+            "Cluster {} has {} values instead of 16", name, values.len()
+        )));
+    }
+
+    let mut matrix = [0.0; 16];
+    matrix.copy_from_slice(&values);
+    Ok(matrix)
+}
+
+fn matrix_to_node(name: &str, matrix: &[f32; 16]) -> RawNode {
+    RawNode::new(name, vec!(OwnedProperty::VecF64(matrix.iter().map(|v| *v as f64).collect())))
+}
+
+#[cfg(test)]
+mod tests {
+    use fbx_direct::common::OwnedProperty;
+    use {RawNode, Error};
+    use super::*;
+
+    #[test]
+    fn it_parses_cluster_weights() {
+        let cluster = Cluster::from_node(&cluster_node(vec!(2, 5), vec!(0.25, 1.0))).unwrap();
+
+        assert!(cluster.indexes == [2, 5]);
+        assert!(cluster.weights == [0.25, 1.0]);
+        assert!(cluster.transform == IDENTITY);
+        assert!(cluster.transform_link[12] == 3.0);
+    }
+
+    #[test]
+    fn it_writes_clusters_that_read_back() {
+        let mut cluster = Cluster::from_node(&cluster_node(vec!(2, 5), vec!(0.25, 1.0))).unwrap();
+        cluster.indexes.push(7);
+        cluster.weights.push(0.5);
+        let mut node = cluster_node(Vec::new(), Vec::new());
+
+        cluster.write_to_node(&mut node);
+
+        assert!(Cluster::from_node(&node).unwrap() == cluster);
+    }
+
+    #[test]
+    fn it_refuses_mismatched_weights() {
+        let result = Cluster::from_node(&cluster_node(vec!(2, 5), vec!(0.25)));
+
+        match result {
+            Err(Error::WrongNodeLayout(_)) => {},
+            _ => panic!("Didn't receive right error"),
+        }
+    }
+
+    fn cluster_node(indexes: Vec<i32>, weights: Vec<f64>) -> RawNode {
+        let mut transform_link = IDENTITY.iter().map(|v| *v as f64).collect::<Vec<_>>();
+        transform_link[12] = 3.0;
+
+        RawNode {
+            name: "Deformer".into(),
+            properties: vec!(
+                OwnedProperty::I64(1),
+                OwnedProperty::String("SubDeformer::".into()),
+                OwnedProperty::String("Cluster".into()),
+            ),
+            children: vec!(
+                array_node("Indexes", OwnedProperty::VecI32(indexes)),
+                array_node("Weights", OwnedProperty::VecF64(weights)),
+                array_node("TransformLink", OwnedProperty::VecF64(transform_link)),
+            ),
+        }
+    }
+
+    fn array_node(name: &str, value: OwnedProperty) -> RawNode {
+        RawNode {
+            name: name.into(),
+            properties: vec!(value),
+            children: Vec::new(),
+        }
+    }
+}
@@ -1,6 +1,24 @@
-use {RawNode};
+use simple::triangulate::{triangulate, has_area};
+use {RawNode, OwnedProperty, Error};
 
-#[derive(Clone, Debug, Default)]
+/// A single corner of a triangle, with its per-polygon-vertex data resolved.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TriangleVertex {
+    /// Index of the control point in `Geometry::vertices` this corner uses.
+    pub index: u32,
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Triangle {
+    /// Index into the materials of the model this geometry belongs to, if any are assigned.
+    pub material: Option<u32>,
+    pub vertices: [TriangleVertex; 3],
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Geometry {
     /// Vertices that make up the polygon.
     pub vertices: Vec<[f32; 3]>,
@@ -12,16 +30,19 @@ pub struct Geometry {
     /// UVs for polygon vertices. Other uv types than "ByPolygonVertex", "IndexToDirect" not
     /// currently supported.
     pub uvs: Vec<[f32; 2]>,
+    /// Material index for every polygon, empty if the geometry has no materials assigned. Indices
+    /// refer to the materials connected to the model this geometry belongs to, in order.
+    pub materials: Vec<u32>,
 }
 
 impl Geometry {
-    pub fn from_node(node: &RawNode) -> Self {
+    pub fn from_node(node: &RawNode) -> Result<Self, Error> {
         // First, make sure we've got a mesh
         // TODO: Support other geometry types
         let class = node.properties[2].get_string().unwrap();
         if class != "Mesh" {
             // It's not a mesh, just return an empty geometry
-            return Default::default();
+            return Ok(Default::default());
         }
 
         // Read in the vertex data, which is just stored in the sub-node "Vertices"
@@ -69,41 +90,183 @@ impl Geometry {
         let uvs_data = node_to_vector2s(uvs_data_node);
         let uvs = flatten_mapping_to_vertices(uvs_node, uvs_data, "UVIndex", &vertex_indices);
 
+        // Read in the materials, which are optional
+        let materials = if let Some(materials_node) = node.find_child("LayerElementMaterial") {
+            flatten_materials_to_polygons(materials_node, polygons.len())?
+        } else {
+            Vec::new()
+        };
+
         // Finish off the geometry type
-        Geometry {
+        Ok(Geometry {
             vertices: vertices,
             polygons: polygons,
             normals: normals,
             uvs: uvs,
+            materials: materials,
+        })
+    }
+
+    /// Writes the geometry data into a "Geometry" node, replacing the data already in there.
+    /// Normals and UVs are always written "ByPolygonVertex", "Direct".
+    pub fn write_to_node(&self, node: &mut RawNode) {
+        node.set_child(RawNode::new("Vertices", vec!(OwnedProperty::VecF64(
+            self.vertices.iter().flat_map(|v| v.iter()).map(|v| *v as f64).collect()
+        ))));
+
+        // The last index of every polygon is stored negative to mark the end
+        let mut indices = Vec::new();
+        for polygon in &self.polygons {
+            for (i, index) in polygon.iter().enumerate() {
+                if i == polygon.len() - 1 {
+                    indices.push(-(*index as i32) - 1);
+                } else {
+                    indices.push(*index as i32);
+                }
+            }
+        }
+        node.set_child(RawNode::new("PolygonVertexIndex", vec!(OwnedProperty::VecI32(indices))));
+
+        write_layer_element(node, "LayerElementNormal", "ByPolygonVertex", "Normals", "NormalsIndex",
+            OwnedProperty::VecF64(
+                self.normals.iter().flat_map(|v| v.iter()).map(|v| *v as f64).collect()
+            ),
+        );
+        write_layer_element(node, "LayerElementUV", "ByPolygonVertex", "UV", "UVIndex",
+            OwnedProperty::VecF64(
+                self.uvs.iter().flat_map(|v| v.iter()).map(|v| *v as f64).collect()
+            ),
+        );
+        if self.materials.is_empty() {
+            node.remove_child("LayerElementMaterial");
+        } else {
+            write_layer_element(node, "LayerElementMaterial", "ByPolygon", "Materials", "",
+                OwnedProperty::VecI32(self.materials.iter().map(|v| *v as i32).collect()),
+            );
+        }
+
+        // New geometry needs a layer to tell importers which elements to use
+        if node.find_child("Layer").is_none() {
+            let mut layer = RawNode::new("Layer", vec!(OwnedProperty::I32(0)));
+            layer.children.push(RawNode::new("Version", vec!(OwnedProperty::I32(100))));
+            for element in &["LayerElementNormal", "LayerElementMaterial", "LayerElementUV"] {
+                if node.find_child(element).is_none() {
+                    continue;
+                }
+
+                let mut layer_element = RawNode::new("LayerElement", Vec::new());
+                layer_element.children.push(
+                    RawNode::new("Type", vec!(OwnedProperty::String(element.to_string())))
+                );
+                layer_element.children.push(
+                    RawNode::new("TypedIndex", vec!(OwnedProperty::I32(0)))
+                );
+                layer.children.push(layer_element);
+            }
+            node.children.push(layer);
         }
     }
 
-    pub fn triangles(&self) -> Vec<[([f32; 3], [f32; 3], [f32; 2]); 3]> {
-        // If our assumptions are right, normals and uvs should have the same amount of entries
-        assert!(self.normals.len() == self.uvs.len());
+    /// The polygons without any area, which `triangles` skips.
+    pub fn degenerate_polygons(&self) -> Vec<usize> {
+        self.polygons.iter().enumerate()
+            .filter(|&(_, poly)| {
+                let positions: Option<Vec<_>> = poly.iter()
+                    .map(|i| self.vertices.get(*i as usize).cloned())
+                    .collect();
+                // Polygons with vertex indices out of range are reported by `triangles` instead
+                positions.map(|p| !has_area(&p)).unwrap_or(false)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Splits all polygons into triangles, carrying over the normals and UVs of every corner.
+    /// Polygons without any area are skipped.
+    pub fn triangles(&self) -> Result<Vec<Triangle>, Error> {
+        // Normals and uvs are flattened to one entry for every polygon vertex
+        let polygon_vertices: usize = self.polygons.iter().map(|p| p.len()).sum();
+        if self.normals.len() != polygon_vertices || self.uvs.len() != polygon_vertices {
+            return Err(Error::WrongNodeLayout(format!(
+                "Geometry has {} polygon vertices but {} normals and {} uvs",
+                polygon_vertices, self.normals.len(), self.uvs.len()
+            )));
+        }
 
         let mut triangles = Vec::new();
 
-        // Go through all polygons
+        // Go through all polygons, keeping track of where their polygon vertices start
+        let mut offset = 0;
         for (poly_num, poly) in self.polygons.iter().enumerate() {
-            // Make sure this is a triangle, our current method of getting the normals and uvs
-            // doesn't make sense otherwise.
-            assert!(poly.len() == 3); // TODO: Improve error handling
-
-            // Go through the indices in this polygon
-            let mut triangle: [([f32; 3], [f32; 3], [f32; 2]); 3] = Default::default();
-            for (index_num, index) in poly.iter().enumerate() {
-                triangle[index_num] = (
-                    self.vertices[*index as usize],
-                    self.normals[poly_num * 3 + index_num],
-                    self.uvs[poly_num * 3 + index_num],
-                );
+            // Find the vertex positions and use them to figure out the triangles
+            let mut positions = Vec::new();
+            for index in poly {
+                let position = self.vertices.get(*index as usize)
+                    .ok_or_else(|| Error::InvalidPolygon(
+                        poly_num, format!("Vertex index {} out of range", index)
+                    ))?;
+                positions.push(*position);
+            }
+
+            // Faces without any area don't add anything to the model, they're skipped instead of
+            // failing the whole geometry
+            if !has_area(&positions) {
+                offset += poly.len();
+                continue;
+            }
+            let corners = triangulate(&positions)
+                .map_err(|e| Error::InvalidPolygon(poly_num, e.into()))?;
+
+            // Turn the corners into actual triangle data
+            for corner in corners {
+                let mut triangle: [TriangleVertex; 3] = Default::default();
+                for (vertex, poly_vertex) in triangle.iter_mut().zip(corner.iter()) {
+                    *vertex = TriangleVertex {
+                        index: poly[*poly_vertex],
+                        position: positions[*poly_vertex],
+                        normal: self.normals[offset + poly_vertex],
+                        uv: self.uvs[offset + poly_vertex],
+                    };
+                }
+                triangles.push(Triangle {
+                    material: self.materials.get(poly_num).cloned(),
+                    vertices: triangle,
+                });
             }
-            triangles.push(triangle);
+
+            offset += poly.len();
         }
 
-        triangles
+        Ok(triangles)
+    }
+}
+
+fn write_layer_element(
+    node: &mut RawNode, name: &str, mapping: &str, data_name: &str, indices_name: &str,
+    data: OwnedProperty,
+) {
+    // Keep the existing element if there is one, so we don't lose its name and version
+    let mut element = node.find_child(name).cloned().unwrap_or_else(|| {
+        let mut element = RawNode::new(name, vec!(OwnedProperty::I32(0)));
+        element.children.push(RawNode::new("Version", vec!(OwnedProperty::I32(101))));
+        element.children.push(RawNode::new("Name", vec!(OwnedProperty::String("".into()))));
+        element
+    });
+
+    // Materials are always indices, other elements we store directly
+    let reference = if indices_name.is_empty() { "IndexToDirect" } else { "Direct" };
+    element.set_child(RawNode::new(
+        "MappingInformationType", vec!(OwnedProperty::String(mapping.into()))
+    ));
+    element.set_child(RawNode::new(
+        "ReferenceInformationType", vec!(OwnedProperty::String(reference.into()))
+    ));
+    element.set_child(RawNode::new(data_name, vec!(data)));
+    if !indices_name.is_empty() {
+        element.remove_child(indices_name);
     }
+
+    node.set_child(element);
 }
 
 fn flatten_mapping_to_vertices<T: Copy>(
@@ -151,6 +314,34 @@ fn flatten_mapping_to_vertices<T: Copy>(
     }
 }
 
+fn flatten_materials_to_polygons(
+    node: &RawNode, polygon_count: usize
+) -> Result<Vec<u32>, Error> {
+    let materials: Vec<u32> = node.find_child("Materials")
+        .and_then(|n| n.properties.first())
+        .and_then(|p| p.get_vec_i32())
+        .ok_or_else(|| Error::WrongNodeLayout("LayerElementMaterial has no Materials".into()))?
+        .iter().map(|v| *v as u32).collect();
+
+    let mapping = node.find_child("MappingInformationType")
+        .and_then(|n| n.properties.first())
+        .and_then(|p| p.get_string())
+        .ok_or_else(|| Error::WrongNodeLayout(
+            "LayerElementMaterial has no MappingInformationType".into()
+        ))?;
+    match mapping.as_str() {
+        // A single material is used for the entire geometry
+        "AllSame" => Ok(vec![materials.first().cloned().unwrap_or(0); polygon_count]),
+        // Every polygon has its own material
+        "ByPolygon" if materials.len() == polygon_count => Ok(materials),
+        "ByPolygon" => Err(Error::WrongNodeLayout(format!(
+            "Geometry has {} polygons but {} materials", polygon_count, materials.len()
+        ))),
+        // We don't know this type of mapping yet
+        other => Err(Error::WrongNodeLayout(format!("Unknown material mapping type {}", other))),
+    }
+}
+
 fn node_to_vector3s(node: &RawNode) -> Vec<[f32; 3]> {
     let mut vectors = Vec::new();
 
@@ -172,3 +363,121 @@ fn node_to_vector2s(node: &RawNode) -> Vec<[f32; 2]> {
 
     vectors
 }
+
+#[cfg(test)]
+mod tests {
+    use {RawNode, OwnedProperty, Error};
+    use super::*;
+
+    #[test]
+    fn it_carries_corner_data_through_triangulation() {
+        let geometry = Geometry {
+            vertices: vec!([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]),
+            polygons: vec!(vec!(0, 1, 2), vec!(0, 1, 2, 3)),
+            normals: (0..7).map(|i| [i as f32, 0.0, 0.0]).collect(),
+            uvs: (0..7).map(|i| [0.0, i as f32]).collect(),
+            materials: vec!(0, 1),
+        };
+
+        let triangles = geometry.triangles().unwrap();
+
+        assert!(triangles.len() == 3);
+        assert!(triangles[1].material == Some(1));
+        for triangle in &triangles[1..] {
+            for vertex in &triangle.vertices {
+                // Every corner of the quad should still have the normal and uv of that corner
+                let poly_vertex = 3 + vertex.index as usize;
+                assert!(vertex.normal[0] == poly_vertex as f32);
+                assert!(vertex.uv[1] == poly_vertex as f32);
+                assert!(vertex.position == geometry.vertices[vertex.index as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn it_writes_geometry_that_reads_back() {
+        let geometry = Geometry {
+            vertices: vec!([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]),
+            polygons: vec!(vec!(0, 1, 2), vec!(0, 2, 3)),
+            normals: vec!([0.0, 0.0, 1.0]; 6),
+            uvs: (0..6).map(|i| [0.5, i as f32]).collect(),
+            materials: vec!(1, 0),
+        };
+        let mut node = RawNode::new("Geometry", vec!(
+            OwnedProperty::I64(1),
+            OwnedProperty::String("Geometry".into()),
+            OwnedProperty::String("Mesh".into()),
+        ));
+
+        geometry.write_to_node(&mut node);
+
+        assert!(Geometry::from_node(&node).unwrap() == geometry);
+    }
+
+    #[test]
+    fn it_skips_degenerate_polygons() {
+        let geometry = Geometry {
+            vertices: vec!([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [1.0, 1.0, 0.0]),
+            polygons: vec!(vec!(0, 1), vec!(0, 1, 2), vec!(0, 1, 3)),
+            normals: (0..8).map(|i| [i as f32, 0.0, 0.0]).collect(),
+            uvs: vec!([0.0, 0.0]; 8),
+            materials: vec!(0, 1, 2),
+        };
+
+        let triangles = geometry.triangles().unwrap();
+
+        assert!(geometry.degenerate_polygons() == vec!(0, 1));
+        assert!(triangles.len() == 1);
+        assert!(triangles[0].material == Some(2));
+        assert!(triangles[0].vertices[0].normal[0] == 5.0);
+    }
+
+    #[test]
+    fn it_refuses_vertex_indices_out_of_range() {
+        let geometry = Geometry {
+            vertices: vec!([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            polygons: vec!(vec!(0, 1, 2)),
+            normals: vec!([0.0, 0.0, 1.0]; 3),
+            uvs: vec!([0.0, 0.0]; 3),
+            materials: Vec::new(),
+        };
+
+        assert!(geometry.degenerate_polygons().is_empty());
+        match geometry.triangles() {
+            Err(Error::InvalidPolygon(0, _)) => {},
+            _ => panic!("Didn't receive right error"),
+        }
+    }
+
+    #[test]
+    fn it_refuses_unknown_material_mappings() {
+        let geometry = Geometry {
+            vertices: vec!([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]),
+            polygons: vec!(vec!(0, 1, 2)),
+            normals: vec!([0.0, 0.0, 1.0]; 3),
+            uvs: vec!([0.0, 0.0]; 3),
+            materials: vec!(0),
+        };
+        let mut node = RawNode::new("Geometry", vec!(
+            OwnedProperty::I64(1),
+            OwnedProperty::String("Geometry".into()),
+            OwnedProperty::String("Mesh".into()),
+        ));
+        geometry.write_to_node(&mut node);
+
+        for (mapping, materials) in [("ByEdge", vec!(0)), ("ByPolygon", vec!(0, 1))] {
+            let mut node = node.clone();
+            let mut element = node.find_child("LayerElementMaterial").unwrap().clone();
+            element.set_child(RawNode::new(
+                "MappingInformationType", vec!(OwnedProperty::String(mapping.into()))
+            ));
+            element.set_child(RawNode::new("Materials", vec!(OwnedProperty::VecI32(materials))));
+            node.set_child(element);
+
+            match Geometry::from_node(&node) {
+                Err(Error::WrongNodeLayout(_)) => {},
+                _ => panic!("Didn't receive right error"),
+            }
+        }
+    }
+}
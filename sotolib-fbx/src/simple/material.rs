@@ -0,0 +1,93 @@
+use {RawNode, OwnedProperty};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Material {
+    pub shading_model: String,
+}
+
+impl Material {
+    pub fn from_node(node: &RawNode) -> Self {
+        let shading_model = node.find_child("ShadingModel")
+            .and_then(|n| n.properties[0].get_string())
+            .cloned()
+            .unwrap_or_default();
+
+        Material {
+            shading_model,
+        }
+    }
+
+    /// Writes the material data into a "Material" node, replacing the data already in there.
+    pub fn write_to_node(&self, node: &mut RawNode) {
+        node.set_child(string_node("ShadingModel", &self.shading_model));
+    }
+}
+
+/// A texture, connected to the material property it's used for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Texture {
+    /// Absolute path of the texture file on the machine the FBX was exported on.
+    pub file_name: String,
+    /// Path of the texture file relative to the FBX.
+    pub relative_file_name: String,
+}
+
+impl Texture {
+    pub fn from_node(node: &RawNode) -> Self {
+        Texture {
+            file_name: child_string(node, "FileName"),
+            relative_file_name: child_string(node, "RelativeFilename"),
+        }
+    }
+
+    /// Writes the texture data into a "Texture" node, replacing the data already in there.
+    pub fn write_to_node(&self, node: &mut RawNode) {
+        node.set_child(string_node("FileName", &self.file_name));
+        node.set_child(string_node("RelativeFilename", &self.relative_file_name));
+    }
+
+    /// Gets the file name of the texture without directories or extension.
+    pub fn file_stem(&self) -> Option<String> {
+        let path = if !self.relative_file_name.is_empty() {
+            &self.relative_file_name
+        } else {
+            &self.file_name
+        };
+
+        // The path may be from any platform, so don't rely on the current platform's separators
+        let file = path.rsplit(['/', '\\']).next().unwrap_or("");
+        let stem = file.split('.').next().unwrap_or("");
+        if stem.is_empty() {
+            None
+        } else {
+            Some(stem.to_string())
+        }
+    }
+}
+
+fn child_string(node: &RawNode, name: &str) -> String {
+    node.find_child(name)
+        .and_then(|n| n.properties.first())
+        .and_then(|p| p.get_string())
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn string_node(name: &str, value: &str) -> RawNode {
+    RawNode::new(name, vec!(OwnedProperty::String(value.into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_texture_file_stems() {
+        let texture = Texture {
+            file_name: "C:\\Users\\artist\\textures\\crate_diffuse.tga".into(),
+            relative_file_name: "".into(),
+        };
+
+        assert!(texture.file_stem() == Some("crate_diffuse".into()));
+    }
+}
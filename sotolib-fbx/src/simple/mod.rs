@@ -1,22 +1,29 @@
 mod animation;
+mod deformer;
 mod geometry;
+mod material;
 mod model;
 mod object;
 mod properties;
-
-pub use self::animation::{AnimationCurve};
-pub use self::geometry::{Geometry};
-pub use self::model::{Model, ModelProperties};
+mod settings;
+mod triangulate;
+
+pub use self::animation::{AnimationCurve, AnimationKey, Interpolation, TIME_SECOND};
+pub use self::deformer::{Skin, Cluster};
+pub use self::geometry::{Geometry, Triangle, TriangleVertex};
+pub use self::material::{Material, Texture};
+pub use self::model::{Model, ModelProperties, RotationOrder, InheritType};
 pub use self::object::{Object, ObjectType};
 pub use self::properties::{Property, Properties};
+pub use self::settings::{GlobalSettings, SignedAxis};
 
 use std::collections::{HashMap};
-use {RawFbx, Error};
+use {RawFbx, RawNode, OwnedProperty, Error};
 
 pub type ObjectId = i64;
 
 /// Represents a connection within the FBX file. Connections are laid out (Child, Parent).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Connection {
     /// Object ID to Object ID connections.
     ObjectObject(ObjectId, ObjectId),
@@ -28,6 +35,7 @@ pub enum Connection {
 
 #[derive(Debug, Default)]
 pub struct SimpleFbx {
+    pub global_settings: GlobalSettings,
     pub objects: HashMap<ObjectId, Object>,
     pub connections: Vec<Connection>,
 }
@@ -39,11 +47,62 @@ impl SimpleFbx {
 
     pub fn from_raw(fbx: &RawFbx) -> Result<Self, Error> {
         Ok(SimpleFbx {
+            global_settings: get_global_settings(fbx)?,
             objects: get_objects(fbx)?,
             connections: get_connections(fbx),
         })
     }
 
+    /// Lowers the objects and connections back into raw nodes, using `base` for everything else
+    /// in the file. `base` should be the file this was read from, objects and connections that
+    /// didn't change are then written exactly as they were.
+    pub fn to_raw(&self, base: &RawFbx) -> RawFbx {
+        let empty_objects = RawNode::new("Objects", Vec::new());
+        let empty_connections = RawNode::new("Connections", Vec::new());
+        let base_objects = base.nodes.iter().find(|n| n.name == "Objects")
+            .unwrap_or(&empty_objects);
+        let base_connections = base.nodes.iter().find(|n| n.name == "Connections")
+            .unwrap_or(&empty_connections);
+
+        // Objects that were already in the file keep their place, new ones are added after
+        let mut objects = RawNode::new("Objects", Vec::new());
+        for node in &base_objects.children {
+            let id = node.properties[0].get_i64().unwrap();
+            if let Some(object) = self.objects.get(&id) {
+                objects.children.push(object.to_node(Some(node)));
+            }
+        }
+        let mut new_objects: Vec<_> = self.objects.values()
+            .filter(|o| o.class != ObjectType::Root)
+            .filter(|o| !base_objects.children.iter()
+                .any(|n| n.properties[0].get_i64() == Some(o.id)))
+            .collect();
+        new_objects.sort_by_key(|o| o.id);
+        objects.children.extend(new_objects.into_iter().map(|o| o.to_node(None)));
+
+        let connections = lower_connections(&self.connections, base_connections);
+
+        // Replace the nodes in the base, and update the definitions if the objects changed
+        let mut fbx = base.clone();
+        if objects != *base_objects {
+            if let Some(definitions) = fbx.nodes.iter_mut().find(|n| n.name == "Definitions") {
+                update_definitions(definitions, base_objects, &objects);
+            }
+        }
+        set_node(&mut fbx.nodes, objects);
+        set_node(&mut fbx.nodes, connections);
+
+        // Only touch the settings if they changed, so we don't add anything that wasn't there
+        if get_global_settings(base).ok().as_ref() != Some(&self.global_settings) {
+            let mut node = fbx.nodes.iter().find(|n| n.name == "GlobalSettings").cloned()
+                .unwrap_or_else(|| RawNode::new("GlobalSettings", Vec::new()));
+            self.global_settings.write_to_node(&mut node);
+            set_node(&mut fbx.nodes, node);
+        }
+
+        fbx
+    }
+
     pub fn new_object(&mut self, class: ObjectType) -> ObjectId {
         // Find an unused id for this object
         let mut id = 1;
@@ -106,6 +165,17 @@ impl SimpleFbx {
         None
     }
 
+    /// Gets all objects another object is linked to as a child, an object can have multiple
+    /// parents like a bone that's the child of both its parent bone and a skin cluster.
+    pub fn parents_of(&self, id: ObjectId) -> Vec<ObjectId> {
+        self.connections.iter()
+            .filter_map(|connection| match *connection {
+                Connection::ObjectObject(child, parent) if child == id => Some(parent),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn driven_properties_of(&self, driven: ObjectId) -> Vec<DrivenProperty> {
         let mut vec = Vec::new();
 
@@ -159,6 +229,14 @@ pub struct DrivenProperty {
     pub driven: ObjectId,
 }
 
+fn get_global_settings(fbx: &RawFbx) -> Result<GlobalSettings, Error> {
+    // Files without settings use the defaults
+    match fbx.nodes.iter().find(|n| n.name == "GlobalSettings") {
+        Some(node) => GlobalSettings::from_node(node),
+        None => Ok(GlobalSettings::default()),
+    }
+}
+
 fn get_objects(fbx: &RawFbx) -> Result<HashMap<i64, Object>, Error> {
     // Get the node for objects itself
     let objects = fbx.nodes.iter().find(|n| n.name == "Objects").unwrap();
@@ -180,29 +258,155 @@ fn get_connections(fbx: &RawFbx) -> Vec<Connection> {
 
     // Go through all the nodes in there
     for node in &connections.children {
-        let con = match node.properties[0].get_string().unwrap().as_str() {
-            "OO" => Connection::ObjectObject(
-                node.properties[1].get_i64().unwrap(),
-                node.properties[2].get_i64().unwrap(),
-            ),
-            "OP" => Connection::ObjectProperty(
-                node.properties[1].get_i64().unwrap(),
-                node.properties[2].get_i64().unwrap(),
-                node.properties[3].get_string().unwrap().clone(),
-            ),
-            other => Connection::NotSupported(other.to_string())
-        };
-        con_vec.push(con);
+        con_vec.push(node_to_connection(node));
     }
 
     con_vec
 }
 
+fn node_to_connection(node: &RawNode) -> Connection {
+    match node.properties[0].get_string().unwrap().as_str() {
+        "OO" => Connection::ObjectObject(
+            node.properties[1].get_i64().unwrap(),
+            node.properties[2].get_i64().unwrap(),
+        ),
+        "OP" => Connection::ObjectProperty(
+            node.properties[1].get_i64().unwrap(),
+            node.properties[2].get_i64().unwrap(),
+            node.properties[3].get_string().unwrap().clone(),
+        ),
+        other => Connection::NotSupported(other.to_string())
+    }
+}
+
+fn lower_connections(connections: &[Connection], base: &RawNode) -> RawNode {
+    // If nothing changed, keep the connections as they were
+    let base_connections: Vec<_> = base.children.iter().map(node_to_connection).collect();
+    if connections == base_connections.as_slice() {
+        return base.clone();
+    }
+
+    // We don't know anything about unsupported connections, so re-use the original ones in order
+    let mut unsupported = base.children.iter()
+        .zip(base_connections.iter())
+        .filter(|&(_, c)| matches!(*c, Connection::NotSupported(_)))
+        .map(|(n, _)| n);
+
+    let mut node = RawNode::new("Connections", Vec::new());
+    for connection in connections {
+        let child = match *connection {
+            Connection::ObjectObject(child, parent) => RawNode::new("C", vec!(
+                OwnedProperty::String("OO".into()),
+                OwnedProperty::I64(child),
+                OwnedProperty::I64(parent),
+            )),
+            Connection::ObjectProperty(child, parent, ref property) => RawNode::new("C", vec!(
+                OwnedProperty::String("OP".into()),
+                OwnedProperty::I64(child),
+                OwnedProperty::I64(parent),
+                OwnedProperty::String(property.clone()),
+            )),
+            Connection::NotSupported(_) => match unsupported.next() {
+                Some(original) => original.clone(),
+                None => continue,
+            },
+        };
+        node.children.push(child);
+    }
+
+    node
+}
+
+fn update_definitions(definitions: &mut RawNode, old_objects: &RawNode, new_objects: &RawNode) {
+    let count = |objects: &RawNode, name: &str| {
+        objects.children.iter().filter(|n| n.name == name).count() as i32
+    };
+    let mut total_difference = 0;
+
+    // Every object type has its own count
+    for object_type in definitions.children.iter_mut().filter(|n| n.name == "ObjectType") {
+        let name = object_type.properties[0].get_string().unwrap().clone();
+        let difference = count(new_objects, &name) - count(old_objects, &name);
+        if let Some(count_node) = object_type.find_child_mut("Count") {
+            let old = count_node.properties[0].get_i32().unwrap_or(0);
+            count_node.properties[0] = OwnedProperty::I32(old + difference);
+            total_difference += difference;
+        }
+    }
+
+    // And there's one with the total count
+    if let Some(count_node) = definitions.find_child_mut("Count") {
+        let old = count_node.properties[0].get_i32().unwrap_or(0);
+        count_node.properties[0] = OwnedProperty::I32(old + total_difference);
+    }
+}
+
+fn set_node(nodes: &mut Vec<RawNode>, node: RawNode) {
+    if let Some(existing) = nodes.iter_mut().find(|n| n.name == node.name) {
+        *existing = node;
+        return;
+    }
+    nodes.push(node);
+}
 
 #[cfg(test)]
 mod tests {
+    use std::fs::File;
+    use std::io::{BufReader};
+    use WriteOptions;
     use super::*;
 
+    #[test]
+    fn it_lowers_unchanged_fbx_losslessly() {
+        let raw = read_test_cubes();
+        let fbx = SimpleFbx::from_raw(&raw).unwrap();
+
+        let lowered = fbx.to_raw(&raw);
+
+        assert!(lowered == raw);
+    }
+
+    #[test]
+    fn it_lowers_modified_fbx() {
+        let raw = read_test_cubes();
+        let mut fbx = SimpleFbx::from_raw(&raw).unwrap();
+
+        // Change an existing object, and add a new one
+        let material_id = *fbx.objects.iter()
+            .find(|&(_, o)| o.class.as_material().is_some())
+            .unwrap().0;
+        {
+            let material = fbx.objects.get_mut(&material_id).unwrap();
+            material.name = "renamed\u{0}\u{1}Material".into();
+            material.class = ObjectType::Material(Material { shading_model: "lambert".into() });
+            material.properties.insert("Opacity".into(), Property {
+                name: "Opacity".into(),
+                values: vec!(OwnedProperty::F64(0.5)),
+            });
+        }
+        let texture = fbx.new_object(ObjectType::Texture(Texture {
+            file_name: "C:\\textures\\crate.tga".into(),
+            relative_file_name: "crate.tga".into(),
+        }));
+        fbx.connect_property_object(material_id, "DiffuseColor", texture);
+        assert!(fbx.global_settings.frame_rate() == Some(24.0));
+        fbx.global_settings.unit_scale_factor = 100.0;
+
+        // Write it out and read it back in
+        let mut data = Vec::new();
+        fbx.to_raw(&raw).write(&mut data, &WriteOptions::default()).unwrap();
+        let written = SimpleFbx::from_raw(&RawFbx::parse(data.as_slice()).unwrap()).unwrap();
+
+        assert!(written.objects == fbx.objects);
+        assert!(written.connections == fbx.connections);
+        assert!(written.global_settings == fbx.global_settings);
+    }
+
+    fn read_test_cubes() -> RawFbx {
+        let file = BufReader::new(File::open("../example/test_cubes.fbx").unwrap());
+        RawFbx::parse(file).unwrap()
+    }
+
     #[test]
     fn it_connects_parents_to_children() {
         let mut fbx = SimpleFbx::new();
@@ -1,7 +1,7 @@
 use simple::{Properties};
 use {RawNode};
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Model {
 }
 
@@ -12,6 +12,69 @@ impl Model {
     }
 }
 
+/// The order Euler rotation angles are applied in, the first axis is applied first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RotationOrder {
+    XYZ,
+    XZY,
+    YZX,
+    YXZ,
+    ZXY,
+    ZYX,
+    /// Spherical XYZ, only used for interpolation, evaluates the same as XYZ.
+    SphericXYZ,
+}
+
+impl RotationOrder {
+    pub fn from_value(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(RotationOrder::XYZ),
+            1 => Some(RotationOrder::XZY),
+            2 => Some(RotationOrder::YZX),
+            3 => Some(RotationOrder::YXZ),
+            4 => Some(RotationOrder::ZXY),
+            5 => Some(RotationOrder::ZYX),
+            6 => Some(RotationOrder::SphericXYZ),
+            _ => None,
+        }
+    }
+
+    /// Gets the axes in the order they're applied, 0 for X, 1 for Y, 2 for Z.
+    pub fn axes(&self) -> [usize; 3] {
+        match *self {
+            RotationOrder::XYZ | RotationOrder::SphericXYZ => [0, 1, 2],
+            RotationOrder::XZY => [0, 2, 1],
+            RotationOrder::YZX => [1, 2, 0],
+            RotationOrder::YXZ => [1, 0, 2],
+            RotationOrder::ZXY => [2, 0, 1],
+            RotationOrder::ZYX => [2, 1, 0],
+        }
+    }
+}
+
+/// How a model inherits the transformation of its parent. The lowercase letters are the
+/// model's own rotation and scale, the uppercase letters the parent's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InheritType {
+    /// The parent's scale is applied after the model's rotation.
+    RrSs,
+    /// The parent's full transformation is applied, the default in most applications.
+    RSrs,
+    /// The parent's scale is not inherited, like Maya's segment scale compensation.
+    Rrs,
+}
+
+impl InheritType {
+    pub fn from_value(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(InheritType::RrSs),
+            1 => Some(InheritType::RSrs),
+            2 => Some(InheritType::Rrs),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ModelProperties {
     pub translation: [f32; 3],
@@ -23,6 +86,16 @@ pub struct ModelProperties {
     pub rotation_pivot: [f32; 3],
     pub scale_offset: [f32; 3],
     pub scale_pivot: [f32; 3],
+    /// Order of the "Lcl Rotation" angles. Only used if rotation_active is set.
+    pub rotation_order: RotationOrder,
+    /// If the rotation order, pre-rotation and post-rotation are used, if not set those are
+    /// treated as XYZ and no rotation.
+    pub rotation_active: bool,
+    pub inherit_type: InheritType,
+    /// Transformation applied to the geometry attached to the model, but not to child models.
+    pub geometric_translation: [f32; 3],
+    pub geometric_rotation: [f32; 3],
+    pub geometric_scale: [f32; 3],
 }
 
 impl ModelProperties {
@@ -71,6 +144,34 @@ impl ModelProperties {
             scale_pivot = piv.to_vector3();
         }
 
+        // Enums and flags are stored as integers
+        let int = |name: &str| {
+            properties.get(name)
+                .and_then(|p| p.values.first())
+                .and_then(|v| v.get_i32())
+        };
+        let rotation_order = int("RotationOrder")
+            .and_then(RotationOrder::from_value)
+            .unwrap_or(RotationOrder::XYZ);
+        let rotation_active = int("RotationActive").map(|v| v != 0).unwrap_or(false);
+        let inherit_type = int("InheritType")
+            .and_then(InheritType::from_value)
+            .unwrap_or(InheritType::RSrs);
+
+        // And finally the transformation for the geometry only
+        let mut geometric_translation: [f32; 3] = Default::default();
+        if let Some(trans) = properties.get("GeometricTranslation") {
+            geometric_translation = trans.to_vector3();
+        }
+        let mut geometric_rotation: [f32; 3] = Default::default();
+        if let Some(rot) = properties.get("GeometricRotation") {
+            geometric_rotation = rot.to_vector3();
+        }
+        let mut geometric_scale: [f32; 3] = [1.0, 1.0, 1.0];
+        if let Some(sca) = properties.get("GeometricScaling") {
+            geometric_scale = sca.to_vector3();
+        }
+
         ModelProperties {
             translation: translation,
             pre_rotation: pre_rotation,
@@ -81,6 +182,12 @@ impl ModelProperties {
             rotation_pivot: rotation_pivot,
             scale_offset: scale_offset,
             scale_pivot: scale_pivot,
+            rotation_order,
+            rotation_active,
+            inherit_type,
+            geometric_translation,
+            geometric_rotation,
+            geometric_scale,
         }
     }
 }
@@ -106,5 +213,26 @@ mod tests {
         let properties = ModelProperties::from_generic(&properties);
 
         assert!(properties.translation == expected_translation);
+        assert!(properties.rotation_order == RotationOrder::XYZ);
+        assert!(!properties.rotation_active);
+        assert!(properties.inherit_type == InheritType::RSrs);
+    }
+
+    #[test]
+    fn it_reads_rotation_settings() {
+        let mut properties = Properties::new();
+        for &(name, value) in &[("RotationOrder", 4), ("RotationActive", 1), ("InheritType", 2)] {
+            properties.insert(name.into(), Property {
+                name: name.into(),
+                values: vec!(OwnedProperty::I32(value)),
+            });
+        }
+
+        let properties = ModelProperties::from_generic(&properties);
+
+        assert!(properties.rotation_order == RotationOrder::ZXY);
+        assert!(properties.rotation_order.axes() == [2, 0, 1]);
+        assert!(properties.rotation_active);
+        assert!(properties.inherit_type == InheritType::Rrs);
     }
 }
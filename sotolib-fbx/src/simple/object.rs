@@ -1,7 +1,9 @@
-use simple::{Geometry, Model, Property, Properties, AnimationCurve};
-use {RawNode, Error};
+use simple::{
+    Geometry, Model, Property, Properties, AnimationCurve, Skin, Cluster, Material, Texture,
+};
+use {RawNode, OwnedProperty, Error};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Object {
     pub id: i64,
     pub name: String,
@@ -49,14 +51,27 @@ impl Object {
                 ObjectType::AnimationCurveNode
             },
             "AnimationCurve" => {
-                ObjectType::AnimationCurve(AnimationCurve::from_node(node))
+                ObjectType::AnimationCurve(AnimationCurve::from_node(node)?)
+            },
+            "Deformer" => {
+                match node.properties[2].get_string().map(|v| v.as_str()) {
+                    Some("Skin") => ObjectType::Skin(Skin::from_node(node)),
+                    Some("Cluster") => ObjectType::Cluster(Cluster::from_node(node)?),
+                    _ => ObjectType::Other(node.name.clone()),
+                }
             },
             "Geometry" => {
-                ObjectType::Geometry(Geometry::from_node(node))
+                ObjectType::Geometry(Geometry::from_node(node)?)
             }
             "Model" => {
                 ObjectType::Model(Model::from_node(node))
             },
+            "Material" => {
+                ObjectType::Material(Material::from_node(node))
+            },
+            "Texture" => {
+                ObjectType::Texture(Texture::from_node(node))
+            },
             _ => {
                 ObjectType::Other(node.name.clone())
             },
@@ -69,16 +84,81 @@ impl Object {
             name: name,
         })
     }
+
+    /// Lowers the object back into a node. If `original` is the node this object was read from,
+    /// anything the object doesn't know about is kept, and if nothing changed the node is
+    /// returned as-is.
+    pub fn to_node(&self, original: Option<&RawNode>) -> RawNode {
+        // If the object didn't change, we don't have to touch anything
+        if let Some(original) = original {
+            if Object::from_node(original).ok().as_ref() == Some(self) {
+                return original.clone();
+            }
+        }
+
+        let mut node = original.cloned().unwrap_or_else(|| RawNode::new(
+            self.class.type_name(),
+            vec!(
+                OwnedProperty::I64(0),
+                OwnedProperty::String("".into()),
+                OwnedProperty::String(self.class.sub_type_name().into()),
+            ),
+        ));
+        node.properties[0] = OwnedProperty::I64(self.id);
+        node.properties[1] = OwnedProperty::String(self.name.clone());
+
+        // Keep properties that were already there in the same order with the same types
+        let mut props_node = node.find_child("Properties70").cloned()
+            .unwrap_or_else(|| RawNode::new("Properties70", Vec::new()));
+        let mut children = Vec::new();
+        for child in &props_node.children {
+            let name = child.properties[0].get_string().unwrap();
+            if let Some(property) = self.properties.get(name) {
+                children.push(property.to_node(Some(child)));
+            }
+        }
+        let mut new_properties: Vec<_> = self.properties.values()
+            .filter(|p| !props_node.children.iter()
+                .any(|c| c.properties[0].get_string() == Some(&p.name)))
+            .collect();
+        new_properties.sort_by(|a, b| a.name.cmp(&b.name));
+        children.extend(new_properties.into_iter().map(|p| p.to_node(None)));
+        props_node.children = children;
+        if props_node.children.is_empty() {
+            node.remove_child("Properties70");
+        } else {
+            node.set_child(props_node);
+        }
+
+        // Finally the data specific to the type of object
+        match self.class {
+            ObjectType::AnimationCurve(ref curve) => curve.write_to_node(&mut node),
+            ObjectType::Cluster(ref cluster) => cluster.write_to_node(&mut node),
+            ObjectType::Geometry(ref geometry) => geometry.write_to_node(&mut node),
+            ObjectType::Material(ref material) => material.write_to_node(&mut node),
+            ObjectType::Texture(ref texture) => texture.write_to_node(&mut node),
+            _ => {},
+        }
+
+        node
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ObjectType {
     AnimationStack,
     AnimationLayer,
     AnimationCurveNode,
     AnimationCurve(AnimationCurve),
+    /// Skin deformer, a child of the geometry it deforms.
+    Skin(Skin),
+    /// Skin cluster deformer, a child of a skin, with the bone it binds as its child.
+    Cluster(Cluster),
     Geometry(Geometry),
     Model(Model),
+    Material(Material),
+    /// Texture, connected to the material property it's used for.
+    Texture(Texture),
     /// Virtual object representing the root of the file.
     Root,
     /// Currently not implemented object type.
@@ -92,13 +172,28 @@ impl ObjectType {
             ObjectType::AnimationLayer => "AnimationLayer",
             ObjectType::AnimationCurveNode => "AnimationCurveNode",
             ObjectType::AnimationCurve(_) => "AnimationCurve",
+            ObjectType::Skin(_) => "Deformer",
+            ObjectType::Cluster(_) => "Deformer",
             ObjectType::Geometry(_) => "Geometry",
             ObjectType::Model(_) => "Model",
+            ObjectType::Material(_) => "Material",
+            ObjectType::Texture(_) => "Texture",
             ObjectType::Root => "Root", // This really should never be used but here we go
             ObjectType::Other(ref t) => &t,
         }.into()
     }
 
+    /// Gets the sub-type stored as the third property of new object nodes.
+    pub fn sub_type_name(&self) -> &'static str {
+        match *self {
+            ObjectType::Skin(_) => "Skin",
+            ObjectType::Cluster(_) => "Cluster",
+            ObjectType::Geometry(_) => "Mesh",
+            ObjectType::Model(_) => "Null",
+            _ => "",
+        }
+    }
+
     pub fn as_animation_curve(&self) -> Option<&AnimationCurve> {
         if let &ObjectType::AnimationCurve(ref value) = self {
             Some(value)
@@ -106,6 +201,30 @@ impl ObjectType {
             None
         }
     }
+
+    pub fn as_material(&self) -> Option<&Material> {
+        if let ObjectType::Material(ref value) = *self {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_texture(&self) -> Option<&Texture> {
+        if let ObjectType::Texture(ref value) = *self {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_cluster(&self) -> Option<&Cluster> {
+        if let ObjectType::Cluster(ref value) = *self {
+            Some(value)
+        } else {
+            None
+        }
+    }
 }
 
 #[cfg(test)]
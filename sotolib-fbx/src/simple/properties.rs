@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use {RawNode, OwnedProperty, Error};
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Property {
     pub name: String,
     pub values: Vec<OwnedProperty>
@@ -25,6 +25,46 @@ impl Property {
         })
     }
 
+    /// Creates a "P" node for this property. The type and flags are taken from `original` if
+    /// given, otherwise they're guessed from the values.
+    pub fn to_node(&self, original: Option<&RawNode>) -> RawNode {
+        let header = if let Some(original) = original {
+            original.properties[1..4].to_vec()
+        } else {
+            let (type_name, label, flags) = self.guess_type();
+            vec!(
+                OwnedProperty::String(type_name.into()),
+                OwnedProperty::String(label.into()),
+                OwnedProperty::String(flags.into()),
+            )
+        };
+
+        let mut properties = vec!(OwnedProperty::String(self.name.clone()));
+        properties.extend(header);
+        properties.extend(self.values.iter().cloned());
+        RawNode::new("P", properties)
+    }
+
+    fn guess_type(&self) -> (&'static str, &'static str, &'static str) {
+        // Transform properties are animatable and have their own types
+        match self.name.as_str() {
+            "Lcl Translation" => return ("Lcl Translation", "", "A"),
+            "Lcl Rotation" => return ("Lcl Rotation", "", "A"),
+            "Lcl Scaling" => return ("Lcl Scaling", "", "A"),
+            _ => {},
+        }
+
+        match (self.values.len(), self.values.first()) {
+            (1, Some(&OwnedProperty::Bool(_))) => ("bool", "", ""),
+            (1, Some(&OwnedProperty::I32(_))) => ("int", "Integer", ""),
+            (1, Some(&OwnedProperty::I64(_))) => ("KTime", "Time", ""),
+            (1, Some(&OwnedProperty::String(_))) => ("KString", "", ""),
+            (1, _) => ("double", "Number", ""),
+            (3, _) => ("Vector3D", "Vector", ""),
+            _ => ("object", "", ""),
+        }
+    }
+
     pub fn to_vector3(&self) -> [f32; 3] {
         [self.values[0].get_f32().unwrap(),
         self.values[1].get_f32().unwrap(),
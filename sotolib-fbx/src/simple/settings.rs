@@ -0,0 +1,250 @@
+use simple::{Property, Properties};
+use {RawNode, OwnedProperty, Error};
+
+/// An axis with a direction, as used by the axis system in the global settings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SignedAxis {
+    /// 0 for X, 1 for Y, 2 for Z.
+    pub axis: usize,
+    /// Either 1 or -1.
+    pub sign: i32,
+}
+
+impl SignedAxis {
+    pub fn new(axis: usize, sign: i32) -> Self {
+        SignedAxis {
+            axis,
+            sign,
+        }
+    }
+
+    /// Parses an axis like "Y" or "-Z".
+    pub fn parse(value: &str) -> Option<Self> {
+        let (sign, axis) = if let Some(axis) = value.strip_prefix('-') {
+            (-1, axis)
+        } else {
+            (1, value.strip_prefix('+').unwrap_or(value))
+        };
+
+        match axis {
+            "X" | "x" => Some(SignedAxis::new(0, sign)),
+            "Y" | "y" => Some(SignedAxis::new(1, sign)),
+            "Z" | "z" => Some(SignedAxis::new(2, sign)),
+            _ => None,
+        }
+    }
+
+    pub fn to_vector(&self) -> [f32; 3] {
+        let mut vector = [0.0; 3];
+        vector[self.axis] = self.sign as f32;
+        vector
+    }
+}
+
+/// The document-wide settings in the "GlobalSettings" node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlobalSettings {
+    pub up_axis: SignedAxis,
+    /// The axis pointing out of the front of the scene, towards the viewer.
+    pub front_axis: SignedAxis,
+    /// The axis pointing right when looking at the front of the scene.
+    pub coord_axis: SignedAxis,
+    /// Size of one unit in the file in centimeters.
+    pub unit_scale_factor: f64,
+    /// FBX time mode enum value, 0 means the application's default and 14 means custom.
+    pub time_mode: i32,
+    /// Frame rate used when the time mode is custom.
+    pub custom_frame_rate: f64,
+}
+
+impl Default for GlobalSettings {
+    fn default() -> Self {
+        // Y-up in centimeters, what the FBX SDK defaults to
+        GlobalSettings {
+            up_axis: SignedAxis::new(1, 1),
+            front_axis: SignedAxis::new(2, 1),
+            coord_axis: SignedAxis::new(0, 1),
+            unit_scale_factor: 1.0,
+            time_mode: 0,
+            custom_frame_rate: -1.0,
+        }
+    }
+}
+
+impl GlobalSettings {
+    pub fn from_node(node: &RawNode) -> Result<Self, Error> {
+        let properties: Properties = if let Some(props_node) = node.find_child("Properties70") {
+            let p: Result<Vec<_>, _> = props_node.children.iter().map(Property::from_node).collect();
+            p?.into_iter().map(|p| (p.name.clone(), p)).collect()
+        } else {
+            Properties::new()
+        };
+        let int = |name: &str, default: i32| {
+            properties.get(name)
+                .and_then(|p| p.values.first())
+                .and_then(|v| v.get_i32())
+                .unwrap_or(default)
+        };
+        let double = |name: &str, default: f64| {
+            properties.get(name)
+                .and_then(|p| p.values.first())
+                .and_then(|v| v.get_f64())
+                .unwrap_or(default)
+        };
+        let axis = |name: &str, default: SignedAxis| -> Result<SignedAxis, Error> {
+            let axis = int(name, default.axis as i32);
+            let sign = int(&format!("{}Sign", name), default.sign);
+            if !(0..3).contains(&axis) || (sign != 1 && sign != -1) {
+                return Err(Error::WrongNodeLayout(format!(
+                    "GlobalSettings {} has invalid axis {} with sign {}", name, axis, sign
+                )));
+            }
+            Ok(SignedAxis::new(axis as usize, sign))
+        };
+
+        let defaults = GlobalSettings::default();
+        Ok(GlobalSettings {
+            up_axis: axis("UpAxis", defaults.up_axis)?,
+            front_axis: axis("FrontAxis", defaults.front_axis)?,
+            coord_axis: axis("CoordAxis", defaults.coord_axis)?,
+            unit_scale_factor: double("UnitScaleFactor", defaults.unit_scale_factor),
+            time_mode: int("TimeMode", defaults.time_mode),
+            custom_frame_rate: double("CustomFrameRate", defaults.custom_frame_rate),
+        })
+    }
+
+    /// Writes the settings into a "GlobalSettings" node, keeping any other properties in there.
+    pub fn write_to_node(&self, node: &mut RawNode) {
+        let mut props_node = node.find_child("Properties70").cloned()
+            .unwrap_or_else(|| RawNode::new("Properties70", Vec::new()));
+
+        let values = vec!(
+            ("UpAxis", OwnedProperty::I32(self.up_axis.axis as i32)),
+            ("UpAxisSign", OwnedProperty::I32(self.up_axis.sign)),
+            ("FrontAxis", OwnedProperty::I32(self.front_axis.axis as i32)),
+            ("FrontAxisSign", OwnedProperty::I32(self.front_axis.sign)),
+            ("CoordAxis", OwnedProperty::I32(self.coord_axis.axis as i32)),
+            ("CoordAxisSign", OwnedProperty::I32(self.coord_axis.sign)),
+            ("UnitScaleFactor", OwnedProperty::F64(self.unit_scale_factor)),
+            ("TimeMode", OwnedProperty::I32(self.time_mode)),
+            ("CustomFrameRate", OwnedProperty::F64(self.custom_frame_rate)),
+        );
+        for (name, value) in values {
+            let property = Property {
+                name: name.into(),
+                values: vec!(value),
+            };
+
+            // Keep the type of properties already in there, the guessed types are wrong for enums
+            match props_node.children.iter_mut()
+                .find(|c| c.properties[0].get_string().map(|n| n.as_str()) == Some(name)) {
+                Some(child) => *child = property.to_node(Some(child)),
+                None => {
+                    let mut child = property.to_node(None);
+                    if name == "TimeMode" {
+                        child.properties[1] = OwnedProperty::String("enum".into());
+                        child.properties[2] = OwnedProperty::String("".into());
+                    }
+                    props_node.children.push(child);
+                },
+            }
+        }
+
+        node.set_child(props_node);
+    }
+
+    /// Gets the frame rate the file was authored at, if it's not the application's default.
+    pub fn frame_rate(&self) -> Option<f32> {
+        match self.time_mode {
+            1 => Some(120.0),
+            2 => Some(100.0),
+            3 => Some(60.0),
+            4 => Some(50.0),
+            5 => Some(48.0),
+            6 | 7 => Some(30.0),
+            8 | 9 => Some(29.97),
+            10 => Some(25.0),
+            11 => Some(24.0),
+            12 => Some(1000.0),
+            13 => Some(23.976),
+            14 if self.custom_frame_rate > 0.0 => Some(self.custom_frame_rate as f32),
+            15 => Some(96.0),
+            16 => Some(72.0),
+            17 => Some(59.94),
+            18 => Some(119.88),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {RawNode, OwnedProperty, Error};
+    use super::*;
+
+    #[test]
+    fn it_parses_max_settings() {
+        let node = settings_node(vec!(
+            int_property("UpAxis", 2), int_property("FrontAxis", 1),
+            int_property("FrontAxisSign", -1), int_property("TimeMode", 14),
+            double_property("UnitScaleFactor", 2.54), double_property("CustomFrameRate", 15.0),
+        ));
+
+        let settings = GlobalSettings::from_node(&node).unwrap();
+
+        assert!(settings.up_axis.to_vector() == [0.0, 0.0, 1.0]);
+        assert!(settings.front_axis.to_vector() == [0.0, -1.0, 0.0]);
+        assert!(settings.coord_axis.to_vector() == [1.0, 0.0, 0.0]);
+        assert!(settings.unit_scale_factor == 2.54);
+        assert!(settings.frame_rate() == Some(15.0));
+    }
+
+    #[test]
+    fn it_refuses_invalid_axes() {
+        let node = settings_node(vec!(int_property("UpAxis", 3)));
+
+        match GlobalSettings::from_node(&node) {
+            Err(Error::WrongNodeLayout(_)) => {},
+            _ => panic!("Didn't receive right error"),
+        }
+    }
+
+    #[test]
+    fn it_writes_settings_that_read_back() {
+        let mut node = settings_node(vec!(int_property("UpAxis", 2)));
+        let mut settings = GlobalSettings::from_node(&node).unwrap();
+        settings.up_axis = SignedAxis::parse("-Y").unwrap();
+        settings.time_mode = 10;
+
+        settings.write_to_node(&mut node);
+
+        assert!(GlobalSettings::from_node(&node).unwrap() == settings);
+        assert!(settings.frame_rate() == Some(25.0));
+    }
+
+    fn settings_node(properties: Vec<RawNode>) -> RawNode {
+        let mut props_node = RawNode::new("Properties70", Vec::new());
+        props_node.children = properties;
+        let mut node = RawNode::new("GlobalSettings", Vec::new());
+        node.children.push(props_node);
+        node
+    }
+
+    fn int_property(name: &str, value: i32) -> RawNode {
+        property_node(name, "int", "Integer", OwnedProperty::I32(value))
+    }
+
+    fn double_property(name: &str, value: f64) -> RawNode {
+        property_node(name, "double", "Number", OwnedProperty::F64(value))
+    }
+
+    fn property_node(name: &str, type_name: &str, label: &str, value: OwnedProperty) -> RawNode {
+        RawNode::new("P", vec!(
+            OwnedProperty::String(name.into()),
+            OwnedProperty::String(type_name.into()),
+            OwnedProperty::String(label.into()),
+            OwnedProperty::String("".into()),
+            value,
+        ))
+    }
+}
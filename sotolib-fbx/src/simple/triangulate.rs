@@ -0,0 +1,215 @@
+//! Ear clipping triangulation for polygons that may be concave.
+
+const EPSILON: f32 = 1e-6;
+
+/// Splits a planar polygon into triangles, returned as indices into `points`. Triangles keep the
+/// winding order of the polygon.
+pub fn triangulate(points: &[[f32; 3]]) -> Result<Vec<[usize; 3]>, &'static str> {
+    if points.len() < 3 {
+        return Err("Polygon has less than 3 vertices");
+    }
+    if !has_area(points) {
+        return Err("Polygon has no area");
+    }
+    if points.len() == 3 {
+        return Ok(vec!([0, 1, 2]));
+    }
+
+    // The polygon's normal tells us which plane it's in
+    let normal = newell_normal(points);
+    let length = dot(normal, normal).sqrt();
+
+    // Flatten the polygon onto the plane most aligned with its normal, as long as the direction
+    // of the normal is taken into account the polygon's winding stays positive in 2D
+    let abs = [normal[0].abs(), normal[1].abs(), normal[2].abs()];
+    let (u, v, sign) = if abs[0] >= abs[1] && abs[0] >= abs[2] {
+        (1, 2, normal[0].signum())
+    } else if abs[1] >= abs[2] {
+        (2, 0, normal[1].signum())
+    } else {
+        (0, 1, normal[2].signum())
+    };
+    let flat: Vec<[f32; 2]> = points.iter().map(|p| [p[u], p[v] * sign]).collect();
+
+    // Scale the tolerance by the polygon's size so tiny and huge polygons both work
+    let epsilon = EPSILON * length;
+
+    // Keep clipping ears until there's only one triangle left
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let mut clipped = None;
+        let mut collinear = None;
+
+        for i in 0..count {
+            let prev = remaining[(i + count - 1) % count];
+            let cur = remaining[i];
+            let next = remaining[(i + 1) % count];
+
+            // Only convex corners can be ears, reflex ones would add area outside the polygon
+            let corner = cross_2d(flat[prev], flat[cur], flat[next]);
+            if corner.abs() <= epsilon {
+                collinear = collinear.or(Some(i));
+                continue;
+            }
+            if corner < 0.0 {
+                continue;
+            }
+
+            // If another vertex is inside this corner, clipping it would overlap the polygon
+            let contains_other = remaining.iter()
+                .filter(|&&o| o != prev && o != cur && o != next)
+                .filter(|&&o| flat[o] != flat[prev] && flat[o] != flat[cur] && flat[o] != flat[next])
+                .any(|&o| in_triangle(flat[o], flat[prev], flat[cur], flat[next]));
+            if contains_other {
+                continue;
+            }
+
+            clipped = Some(i);
+            triangles.push([prev, cur, next]);
+            break;
+        }
+
+        // If there are no ears left, the only valid thing left to remove are vertices on a
+        // straight edge, which don't add any area anyways
+        match clipped.or(collinear) {
+            Some(i) => { remaining.remove(i); },
+            None => return Err("Polygon is self-intersecting"),
+        }
+    }
+
+    // Whatever's left is the final triangle, unless it doesn't have any area
+    if cross_2d(flat[remaining[0]], flat[remaining[1]], flat[remaining[2]]).abs() > epsilon {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+
+    Ok(triangles)
+}
+
+/// Checks if a polygon has any area. The tolerance is relative to the squared lengths of the
+/// polygon's edges, so tiny and huge polygons both work.
+pub fn has_area(points: &[[f32; 3]]) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+
+    // The normal's length is twice the area, which like the squared edges scales quadratically
+    let normal = newell_normal(points);
+    let edges: f32 = points.iter().enumerate().map(|(i, a)| {
+        let edge = sub(points[(i + 1) % points.len()], *a);
+        dot(edge, edge)
+    }).sum();
+    dot(normal, normal).sqrt() > EPSILON * edges
+}
+
+fn newell_normal(points: &[[f32; 3]]) -> [f32; 3] {
+    // Work relative to the first point, polygons far away from the origin lose precision otherwise
+    let origin = points[0];
+    let mut normal = [0.0; 3];
+    for (i, a) in points.iter().enumerate() {
+        let a = sub(*a, origin);
+        let b = sub(points[(i + 1) % points.len()], origin);
+        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    normal
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross_2d(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - b[1]) - (b[1] - a[1]) * (c[0] - b[0])
+}
+
+fn in_triangle(p: [f32; 2], a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool {
+    cross_2d(a, b, p) >= 0.0 && cross_2d(b, c, p) >= 0.0 && cross_2d(c, a, p) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_quads_in_two() {
+        let quad = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+
+        let triangles = triangulate(&quad).unwrap();
+
+        assert!(triangles.len() == 2);
+        assert!((total_area(&quad, &triangles) - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn it_triangulates_concave_polygons() {
+        // An arrow shape, fanning from the first vertex would create a triangle outside of it
+        let arrow = [
+            [0.0, 0.0, 0.0], [2.0, 1.0, 0.0], [4.0, 0.0, 0.0], [2.0, 3.0, 0.0],
+        ];
+
+        let triangles = triangulate(&arrow).unwrap();
+
+        assert!(triangles.len() == 2);
+        assert!((total_area(&arrow, &triangles) - 4.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn it_triangulates_polygons_facing_away() {
+        // An L shape on the YZ plane, wound clockwise when looked at from +X
+        let l_shape = [
+            [0.0, 0.0, 0.0], [0.0, 0.0, 2.0], [0.0, 1.0, 2.0],
+            [0.0, 1.0, 1.0], [0.0, 2.0, 1.0], [0.0, 2.0, 0.0],
+        ];
+
+        let triangles = triangulate(&l_shape).unwrap();
+
+        assert!(triangles.len() == 4);
+        assert!((total_area(&l_shape, &triangles) - 3.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn it_refuses_degenerate_polygons() {
+        let line = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [3.0, 0.0, 0.0]];
+
+        assert!(triangulate(&line[..2]).is_err());
+        assert!(triangulate(&line[..3]).is_err());
+        assert!(triangulate(&line).is_err());
+        assert!(triangulate(&[line[0], line[1], line[1]]).is_err());
+    }
+
+    #[test]
+    fn it_accepts_small_triangles() {
+        // A millimeter sized triangle in a file using meters, far away from the origin
+        let triangle = [[100.0, 100.0, 0.0], [100.001, 100.0, 0.0], [100.0, 100.001, 0.0]];
+
+        assert!(has_area(&triangle));
+        assert!(triangulate(&triangle).unwrap().len() == 1);
+
+        // The same triangle squashed into a sliver doesn't have any area worth keeping
+        let sliver = [[0.0, 0.0, 0.0], [0.001, 0.0, 0.0], [0.002, 1e-12, 0.0]];
+        assert!(!has_area(&sliver));
+    }
+
+    fn total_area(points: &[[f32; 3]], triangles: &[[usize; 3]]) -> f32 {
+        triangles.iter().map(|t| {
+            let a = points[t[0]];
+            let b = points[t[1]];
+            let c = points[t[2]];
+            let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let cross = [
+                ab[1] * ac[2] - ab[2] * ac[1],
+                ab[2] * ac[0] - ab[0] * ac[2],
+                ab[0] * ac[1] - ab[1] * ac[0],
+            ];
+            dot(cross, cross).sqrt() / 2.0
+        }).sum()
+    }
+}
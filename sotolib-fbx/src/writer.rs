@@ -0,0 +1,278 @@
+use std::io::{Write};
+
+use fbx_direct::common::OwnedProperty;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+use raw::BINARY_MAGIC;
+use {RawFbx, RawNode, Error};
+
+/// Arrays smaller than this many bytes aren't worth compressing.
+const COMPRESSION_THRESHOLD: usize = 128;
+
+/// Magic values the FBX SDK expects in the footer of binary files.
+const FOOTER_ID: [u8; 16] = [
+    0xfa, 0xbc, 0xab, 0x09, 0xd0, 0xc8, 0xd4, 0x66, 0xb1, 0x76, 0xfb, 0x83, 0x1c, 0xf7, 0x26, 0x7e,
+];
+const FOOTER_MAGIC: [u8; 16] = [
+    0xf8, 0x5a, 0x8c, 0x6a, 0xde, 0xf5, 0xd9, 0x7e, 0xec, 0xe9, 0x0c, 0xe3, 0x75, 0x8f, 0x29, 0x0b,
+];
+
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// FBX version to write, for example 7400 for FBX 7.4. Versions from 7500 on use 64-bit
+    /// offsets.
+    pub version: u32,
+    /// Compress larger arrays using zlib.
+    pub compress_arrays: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            version: 7400,
+            compress_arrays: true,
+        }
+    }
+}
+
+impl RawFbx {
+    /// Writes the nodes out as a binary FBX file.
+    pub fn write<W: Write>(&self, mut target: W, options: &WriteOptions) -> Result<(), Error> {
+        if options.version < 7000 || options.version >= 8000 {
+            return Err(Error::UnsupportedVersion(options.version));
+        }
+
+        // Node records contain absolute offsets, so build up the file in memory first
+        let mut writer = Writer {
+            buffer: Vec::new(),
+            options,
+        };
+
+        // The header, which has the version in it
+        writer.buffer.extend_from_slice(BINARY_MAGIC);
+        writer.buffer.extend_from_slice(&[0x1a, 0x00]);
+        writer.buffer.extend_from_slice(&options.version.to_le_bytes());
+
+        // All the nodes, followed by an empty record to end the implicit root node
+        for node in &self.nodes {
+            writer.write_node(node)?;
+        }
+        writer.write_null_record();
+
+        // And finally the footer, padded to 16 bytes
+        writer.buffer.extend_from_slice(&FOOTER_ID);
+        writer.buffer.extend_from_slice(&[0; 4]);
+        let padding = 16 - writer.buffer.len() % 16;
+        writer.buffer.extend(std::iter::repeat_n(0, padding));
+        writer.buffer.extend_from_slice(&options.version.to_le_bytes());
+        writer.buffer.extend_from_slice(&[0; 120]);
+        writer.buffer.extend_from_slice(&FOOTER_MAGIC);
+
+        target.write_all(&writer.buffer).map_err(Error::Io)
+    }
+}
+
+struct Writer<'a> {
+    buffer: Vec<u8>,
+    options: &'a WriteOptions,
+}
+
+impl<'a> Writer<'a> {
+    fn is_wide(&self) -> bool {
+        self.options.version >= 7500
+    }
+
+    fn write_node(&mut self, node: &RawNode) -> Result<(), Error> {
+        if node.name.len() > 255 {
+            return Err(Error::WrongNodeLayout(format!("Node name \"{}\" is too long", node.name)));
+        }
+
+        // Write the record header, the offsets get filled in once we know them
+        let header = self.buffer.len();
+        self.write_offset(0);
+        self.write_offset(node.properties.len() as u64);
+        self.write_offset(0);
+        self.buffer.push(node.name.len() as u8);
+        self.buffer.extend_from_slice(node.name.as_bytes());
+
+        // Write the properties and keep track of how long they were
+        let properties_start = self.buffer.len();
+        for property in &node.properties {
+            self.write_property(property)?;
+        }
+        let properties_length = (self.buffer.len() - properties_start) as u64;
+        self.patch_offset(header + self.offset_size() * 2, properties_length)?;
+
+        // Child nodes need an empty record to mark the end of the list, nodes without properties
+        // need one as well to be read back correctly
+        for child in &node.children {
+            self.write_node(child)?;
+        }
+        if !node.children.is_empty() || node.properties.is_empty() {
+            self.write_null_record();
+        }
+
+        // Now that we're at the end, we know where the node ends
+        let end = self.buffer.len() as u64;
+        self.patch_offset(header, end)
+    }
+
+    fn write_property(&mut self, property: &OwnedProperty) -> Result<(), Error> {
+        match *property {
+            OwnedProperty::Bool(v) => {
+                // 'Y' is true, 'T' is false
+                self.buffer.push(b'C');
+                self.buffer.push(if v { b'Y' } else { b'T' });
+            },
+            OwnedProperty::I16(v) => self.write_scalar(b'Y', &v.to_le_bytes()),
+            OwnedProperty::I32(v) => self.write_scalar(b'I', &v.to_le_bytes()),
+            OwnedProperty::I64(v) => self.write_scalar(b'L', &v.to_le_bytes()),
+            OwnedProperty::F32(v) => self.write_scalar(b'F', &v.to_le_bytes()),
+            OwnedProperty::F64(v) => self.write_scalar(b'D', &v.to_le_bytes()),
+            OwnedProperty::VecBool(ref v) => {
+                let data: Vec<u8> = v.iter().map(|v| *v as u8).collect();
+                self.write_array(b'b', v.len(), &data)?;
+            },
+            OwnedProperty::VecI32(ref v) => {
+                let data: Vec<u8> = v.iter().flat_map(|v| v.to_le_bytes()).collect();
+                self.write_array(b'i', v.len(), &data)?;
+            },
+            OwnedProperty::VecI64(ref v) => {
+                let data: Vec<u8> = v.iter().flat_map(|v| v.to_le_bytes()).collect();
+                self.write_array(b'l', v.len(), &data)?;
+            },
+            OwnedProperty::VecF32(ref v) => {
+                let data: Vec<u8> = v.iter().flat_map(|v| v.to_le_bytes()).collect();
+                self.write_array(b'f', v.len(), &data)?;
+            },
+            OwnedProperty::VecF64(ref v) => {
+                let data: Vec<u8> = v.iter().flat_map(|v| v.to_le_bytes()).collect();
+                self.write_array(b'd', v.len(), &data)?;
+            },
+            OwnedProperty::String(ref v) => self.write_blob(b'S', v.as_bytes())?,
+            OwnedProperty::Binary(ref v) => self.write_blob(b'R', v)?,
+        }
+
+        Ok(())
+    }
+
+    fn write_scalar(&mut self, type_code: u8, data: &[u8]) {
+        self.buffer.push(type_code);
+        self.buffer.extend_from_slice(data);
+    }
+
+    fn write_blob(&mut self, type_code: u8, data: &[u8]) -> Result<(), Error> {
+        self.buffer.push(type_code);
+        self.buffer.extend_from_slice(&to_u32(data.len())?.to_le_bytes());
+        self.buffer.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn write_array(&mut self, type_code: u8, length: usize, data: &[u8]) -> Result<(), Error> {
+        let compress = self.options.compress_arrays && data.len() >= COMPRESSION_THRESHOLD;
+        let compressed;
+        let (encoding, data) = if compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).map_err(Error::Io)?;
+            compressed = encoder.finish().map_err(Error::Io)?;
+            (1u32, compressed.as_slice())
+        } else {
+            (0u32, data)
+        };
+
+        self.buffer.push(type_code);
+        self.buffer.extend_from_slice(&to_u32(length)?.to_le_bytes());
+        self.buffer.extend_from_slice(&encoding.to_le_bytes());
+        self.buffer.extend_from_slice(&to_u32(data.len())?.to_le_bytes());
+        self.buffer.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn write_null_record(&mut self) {
+        let size = self.offset_size() * 3 + 1;
+        self.buffer.extend(std::iter::repeat_n(0, size));
+    }
+
+    fn offset_size(&self) -> usize {
+        if self.is_wide() { 8 } else { 4 }
+    }
+
+    fn write_offset(&mut self, value: u64) {
+        if self.is_wide() {
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        } else {
+            self.buffer.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+    }
+
+    fn patch_offset(&mut self, position: usize, value: u64) -> Result<(), Error> {
+        if self.is_wide() {
+            self.buffer[position..position + 8].copy_from_slice(&value.to_le_bytes());
+        } else {
+            let value = to_u32(value as usize)?;
+            self.buffer[position..position + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+fn to_u32(value: usize) -> Result<u32, Error> {
+    if value > u32::MAX as usize {
+        return Err(Error::WrongNodeLayout(format!(
+            "Value {} is too large for the FBX version", value
+        )));
+    }
+    Ok(value as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader};
+    use fbx_direct::common::OwnedProperty;
+    use {RawFbx, RawNode};
+    use super::*;
+
+    #[test]
+    fn it_round_trips_example_fbx() {
+        let file = BufReader::new(File::open("../example/test_cubes.fbx").unwrap());
+        let fbx = RawFbx::parse(file).unwrap();
+
+        for version in &[7400, 7500] {
+            let mut data = Vec::new();
+            fbx.write(&mut data, &WriteOptions { version: *version, compress_arrays: true }).unwrap();
+            let written = RawFbx::parse(data.as_slice()).unwrap();
+
+            assert!(written == fbx);
+        }
+    }
+
+    #[test]
+    fn it_writes_uncompressed_arrays() {
+        let fbx = RawFbx {
+            nodes: vec!(RawNode {
+                name: "Values".into(),
+                properties: vec!(
+                    OwnedProperty::VecF64((0..100).map(|v| v as f64).collect()),
+                    OwnedProperty::Bool(true),
+                ),
+                children: vec!(RawNode {
+                    name: "Empty".into(),
+                    properties: Vec::new(),
+                    children: Vec::new(),
+                }),
+            }),
+        };
+
+        let mut compressed = Vec::new();
+        fbx.write(&mut compressed, &WriteOptions::default()).unwrap();
+        let mut uncompressed = Vec::new();
+        fbx.write(&mut uncompressed, &WriteOptions { compress_arrays: false, ..Default::default() })
+            .unwrap();
+
+        assert!(compressed.len() < uncompressed.len());
+        assert!(RawFbx::parse(uncompressed.as_slice()).unwrap() == fbx);
+        assert!(RawFbx::parse(compressed.as_slice()).unwrap() == fbx);
+    }
+}
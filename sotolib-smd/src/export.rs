@@ -66,6 +66,27 @@ impl SmdExportExt for Smd {
         }
         writeln!(target, "end")?;
 
+        // Vertex animation is optional, only write it if we actually have any
+        if !self.vertex_animation_frames.is_empty() {
+            writeln!(target, "vertexanimation")?;
+            for (num, frame) in &self.vertex_animation_frames {
+                writeln!(target, "time {}", num)?;
+                for (vertex_id, vertex) in &frame.vertices {
+                    writeln!(target,
+                        "{}   {} {} {}   {} {} {}",
+                        vertex_id,
+                        vertex.position[0],
+                        vertex.position[1],
+                        vertex.position[2],
+                        vertex.normal[0],
+                        vertex.normal[1],
+                        vertex.normal[2],
+                    )?;
+                }
+            }
+            writeln!(target, "end")?;
+        }
+
         Ok(())
     }
 }
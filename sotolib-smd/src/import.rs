@@ -0,0 +1,385 @@
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+use {
+    Smd, SmdBone, SmdLink, SmdVertex, SmdTriangle, SmdAnimationFrameBone,
+    SmdVertexAnimationVertex, BoneId, Error,
+};
+
+pub trait SmdImportExt: Sized {
+    fn import<R: Read>(source: R) -> Result<Self, Error>;
+}
+
+impl SmdImportExt for Smd {
+    fn import<R: Read>(source: R) -> Result<Self, Error> {
+        let mut lines = Lines::read(source)?;
+        let mut smd = Smd::new();
+
+        // The header needs to be the first thing in the file
+        let (line, header) = lines.next_required("version header")?;
+        if header.split_whitespace().collect::<Vec<_>>() != ["version", "1"] {
+            return Err(Error::Parse(line, format!("Expected \"version 1\", found \"{}\"", header)));
+        }
+
+        // After that, the file is made up of blocks which we can read one by one
+        while let Some((line, block)) = lines.next_line() {
+            match block {
+                "nodes" => parse_nodes(&mut lines, &mut smd)?,
+                "skeleton" => parse_skeleton(&mut lines, &mut smd)?,
+                "triangles" => parse_triangles(&mut lines, &mut smd)?,
+                "vertexanimation" => parse_vertex_animation(&mut lines, &mut smd)?,
+                other => return Err(Error::Parse(line, format!("Unknown block \"{}\"", other))),
+            }
+        }
+
+        Ok(smd)
+    }
+}
+
+/// The meaningful lines of a SMD file, with comments and blank lines removed.
+struct Lines {
+    lines: Vec<(usize, String)>,
+    position: usize,
+}
+
+impl Lines {
+    fn read<R: Read>(source: R) -> Result<Self, Error> {
+        let mut reader = BufReader::new(source);
+        let mut lines = Vec::new();
+        let mut buffer = Vec::new();
+        let mut line_number = 0;
+
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer)? == 0 {
+                break;
+            }
+            line_number += 1;
+
+            // Older exporters don't always write UTF-8, so don't fail on material and bone names
+            let text = String::from_utf8_lossy(&buffer);
+            let text = text.trim();
+            if text.is_empty() || text.starts_with("//") {
+                continue;
+            }
+
+            lines.push((line_number, text.to_string()));
+        }
+
+        Ok(Lines {
+            lines,
+            position: 0,
+        })
+    }
+
+    fn next_line(&mut self) -> Option<(usize, &str)> {
+        let line = self.lines.get(self.position)?;
+        self.position += 1;
+        Some((line.0, line.1.as_str()))
+    }
+
+    fn next_required(&mut self, expected: &str) -> Result<(usize, &str), Error> {
+        let last_line = self.lines.last().map(|l| l.0).unwrap_or(0);
+        self.next_line().ok_or_else(|| Error::Parse(
+            last_line, format!("Unexpected end of file, expected {}", expected)
+        ))
+    }
+}
+
+fn parse_nodes(lines: &mut Lines, smd: &mut Smd) -> Result<(), Error> {
+    loop {
+        let (line, text) = lines.next_required("bone or \"end\" in \"nodes\"")?;
+        if text == "end" {
+            return Ok(());
+        }
+
+        // Bone names are quoted, but they may contain spaces so we can't just split on those
+        let (id, rest) = split_token(text);
+        let id: BoneId = parse_value(line, id, "bone id")?;
+        let rest = rest.trim_start();
+        let (name, rest) = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"')
+                .ok_or_else(|| Error::Parse(line, "Unterminated bone name".into()))?;
+            (&quoted[..end], &quoted[end + 1..])
+        } else {
+            split_token(rest)
+        };
+        let parent: BoneId = parse_value(line, rest.trim(), "parent bone id")?;
+
+        // Make sure the bone hierarchy makes sense
+        if smd.bones.iter().any(|b| b.id == id) {
+            return Err(Error::Parse(line, format!("Bone id {} exists multiple times", id)));
+        }
+        let parent = if parent < 0 { None } else { Some(parent) };
+        if let Some(parent) = parent {
+            if !smd.bones.iter().any(|b| b.id == parent) {
+                return Err(Error::Parse(line, format!("Unknown parent bone id {}", parent)));
+            }
+        }
+
+        smd.bones.push(SmdBone {
+            id,
+            name: name.to_string(),
+            parent,
+        });
+    }
+}
+
+fn parse_skeleton(lines: &mut Lines, smd: &mut Smd) -> Result<(), Error> {
+    let mut frame = None;
+
+    loop {
+        let (line, text) = lines.next_required("\"time\", bone or \"end\" in \"skeleton\"")?;
+        if text == "end" {
+            return Ok(());
+        }
+
+        // Check if this is the start of a new frame
+        if let Some(time) = parse_time(line, text)? {
+            frame = Some(time);
+            continue;
+        }
+        let frame = frame.ok_or_else(|| Error::Parse(
+            line, "Bone transform found before any \"time\"".into()
+        ))?;
+
+        // It's not, so it's a bone transform
+        let values = split_values(line, text, 7, "bone transform")?;
+        let bone: BoneId = parse_value(line, values[0], "bone id")?;
+        if !smd.bones.iter().any(|b| b.id == bone) {
+            return Err(Error::Parse(line, format!("Unknown bone id {}", bone)));
+        }
+
+        smd.set_animation(frame, bone, SmdAnimationFrameBone {
+            translation: parse_vector3(line, &values[1..4])?,
+            rotation: parse_vector3(line, &values[4..7])?,
+        });
+    }
+}
+
+fn parse_triangles(lines: &mut Lines, smd: &mut Smd) -> Result<(), Error> {
+    loop {
+        // Every triangle starts with its material
+        let (_, material) = lines.next_required("material or \"end\" in \"triangles\"")?;
+        if material == "end" {
+            return Ok(());
+        }
+        let material = material.to_string();
+
+        // Then it's followed by exactly three vertices
+        let mut vertices: [SmdVertex; 3] = Default::default();
+        for vertex in &mut vertices {
+            let (line, text) = lines.next_required("triangle vertex")?;
+            *vertex = parse_vertex(line, text)?;
+        }
+
+        smd.triangles.push(SmdTriangle {
+            material,
+            vertices,
+        });
+    }
+}
+
+fn parse_vertex(line: usize, text: &str) -> Result<SmdVertex, Error> {
+    let values: Vec<_> = text.split_whitespace().collect();
+    if values.len() < 9 {
+        return Err(Error::Parse(line, format!(
+            "Expected at least 9 values for triangle vertex, found {}", values.len()
+        )));
+    }
+
+    // Weight links are optional, but if they're there they need to match the count
+    let mut links = Vec::new();
+    if values.len() > 9 {
+        let count: usize = parse_value(line, values[9], "link count")?;
+        let expected = count.checked_mul(2).and_then(|n| n.checked_add(10))
+            .ok_or_else(|| Error::Parse(line, format!("Link count {} is too large", count)))?;
+        if values.len() != expected {
+            return Err(Error::Parse(line, format!(
+                "Expected {} values for triangle vertex with {} links, found {}",
+                expected, count, values.len()
+            )));
+        }
+
+        for link in values[10..].chunks(2) {
+            links.push(SmdLink {
+                bone: parse_value(line, link[0], "link bone id")?,
+                weight: parse_value(line, link[1], "link weight")?,
+            });
+        }
+    }
+
+    Ok(SmdVertex {
+        parent_bone: parse_value(line, values[0], "parent bone id")?,
+        position: parse_vector3(line, &values[1..4])?,
+        normal: parse_vector3(line, &values[4..7])?,
+        uv: [
+            parse_value(line, values[7], "number")?,
+            parse_value(line, values[8], "number")?,
+        ],
+        links,
+    })
+}
+
+fn parse_vertex_animation(lines: &mut Lines, smd: &mut Smd) -> Result<(), Error> {
+    let mut frame = None;
+
+    loop {
+        let (line, text) = lines.next_required("\"time\", vertex or \"end\" in \"vertexanimation\"")?;
+        if text == "end" {
+            return Ok(());
+        }
+
+        // Check if this is the start of a new frame
+        if let Some(time) = parse_time(line, text)? {
+            frame = Some(time);
+            continue;
+        }
+        let frame = frame.ok_or_else(|| Error::Parse(
+            line, "Vertex found before any \"time\"".into()
+        ))?;
+
+        // It's not, so it's a vertex
+        let values = split_values(line, text, 7, "vertex animation vertex")?;
+        let vertex_id = parse_value(line, values[0], "vertex id")?;
+        smd.set_vertex_animation(frame, vertex_id, SmdVertexAnimationVertex {
+            position: parse_vector3(line, &values[1..4])?,
+            normal: parse_vector3(line, &values[4..7])?,
+        });
+    }
+}
+
+/// Returns the frame number if this line is a "time" line.
+fn parse_time(line: usize, text: &str) -> Result<Option<i32>, Error> {
+    let (keyword, rest) = split_token(text);
+    if keyword != "time" {
+        return Ok(None);
+    }
+
+    parse_value(line, rest.trim(), "time").map(Some)
+}
+
+fn split_token(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], &text[i..]),
+        None => (text, ""),
+    }
+}
+
+fn split_values<'a>(
+    line: usize, text: &'a str, count: usize, what: &str
+) -> Result<Vec<&'a str>, Error> {
+    let values: Vec<_> = text.split_whitespace().collect();
+    if values.len() != count {
+        return Err(Error::Parse(line, format!(
+            "Expected {} values for {}, found {}", count, what, values.len()
+        )));
+    }
+    Ok(values)
+}
+
+fn parse_vector3(line: usize, values: &[&str]) -> Result<[f32; 3], Error> {
+    Ok([
+        parse_value(line, values[0], "number")?,
+        parse_value(line, values[1], "number")?,
+        parse_value(line, values[2], "number")?,
+    ])
+}
+
+fn parse_value<T: FromStr>(line: usize, text: &str, what: &str) -> Result<T, Error> {
+    text.parse().map_err(|_| Error::Parse(line, format!("Invalid {} \"{}\"", what, text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use {SmdExportExt, SmdVertexAnimationVertex, Error};
+    use super::*;
+
+    #[test]
+    fn it_imports_exported_smd() {
+        let mut smd = Smd::new();
+        let root = smd.new_bone("root bone", None).unwrap().id;
+        let child = smd.new_bone("child", Some(root)).unwrap().id;
+        smd.set_animation(0, root, SmdAnimationFrameBone {
+            translation: [1.0, 2.0, 3.0],
+            rotation: [0.5, 0.25, -0.5],
+        });
+        smd.set_animation(0, child, SmdAnimationFrameBone {
+            translation: [0.0, 1.5, 0.0],
+            rotation: [0.0, 0.0, 0.0],
+        });
+        let mut vertices: [SmdVertex; 3] = Default::default();
+        vertices[1].links.push(SmdLink { bone: root, weight: 0.25 });
+        vertices[1].links.push(SmdLink { bone: child, weight: 0.75 });
+        vertices[2].position = [4.0, 5.0, 6.0];
+        smd.triangles.push(SmdTriangle {
+            material: "some_material".into(),
+            vertices,
+        });
+        smd.set_vertex_animation(1, 2, SmdVertexAnimationVertex {
+            position: [1.0, 1.0, 1.0],
+            normal: [0.0, 0.0, 1.0],
+        });
+
+        let mut data = Vec::new();
+        smd.export(&mut data).unwrap();
+        let imported = Smd::import(data.as_slice()).unwrap();
+
+        assert!(imported == smd);
+    }
+
+    #[test]
+    fn it_skips_comments_and_unquoted_names() {
+        let data = "// Some header comment\nversion 1\nnodes\n0 root -1\n\nend\n";
+
+        let smd = Smd::import(data.as_bytes()).unwrap();
+
+        assert!(smd.bones.len() == 1);
+        assert!(smd.bones[0].name == "root");
+        assert!(smd.bones[0].parent.is_none());
+    }
+
+    #[test]
+    fn it_reports_line_numbers() {
+        let data = "version 1\nnodes\n0 \"root\" -1\nend\nskeleton\ntime 0\n0 1 2 3 4 5\nend\n";
+
+        let result = Smd::import(data.as_bytes());
+
+        match result {
+            Err(Error::Parse(line, _)) => assert!(line == 7),
+            _ => panic!("Didn't receive parse error"),
+        }
+    }
+
+    #[test]
+    fn it_refuses_unknown_bones() {
+        let data = "version 1\nnodes\n0 \"root\" -1\n1 \"child\" 4\nend\n";
+
+        let result = Smd::import(data.as_bytes());
+
+        match result {
+            Err(Error::Parse(line, _)) => assert!(line == 4),
+            _ => panic!("Didn't receive parse error"),
+        }
+    }
+
+    #[test]
+    fn it_refuses_unterminated_blocks() {
+        let data = "version 1\ntriangles\nmaterial\n0 0 0 0 0 0 1 0 0\n";
+
+        assert!(Smd::import(data.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn it_refuses_huge_link_counts() {
+        let data = format!(
+            "version 1\nnodes\n0 \"root\" -1\nend\ntriangles\nmaterial\n\
+             0 0 0 0 0 0 1 0 0 {} 0 1\n", usize::MAX
+        );
+
+        match Smd::import(data.as_bytes()) {
+            Err(Error::Parse(line, _)) => assert!(line == 7),
+            _ => panic!("Didn't receive parse error"),
+        }
+    }
+}
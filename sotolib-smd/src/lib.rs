@@ -1,24 +1,59 @@
 mod export;
+mod import;
 
 pub use export::SmdExportExt;
+pub use import::SmdImportExt;
 
 use std::collections::{BTreeMap};
+use std::fmt::{self, Display, Formatter};
+use std::io;
 
 pub type BoneId = i32;
 
-#[derive(Clone)]
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(usize, String), // Line, Message
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "IO Error",
+            Error::Parse(_, _) => "SMD Parse Error",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "IO error: {}", e),
+            Error::Parse(line, ref message) => write!(f, "Line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct SmdBone {
     pub id: BoneId,
     pub name: String,
     pub parent: Option<BoneId>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct SmdLink {
     pub bone: BoneId,
     pub weight: f32,
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct SmdVertex {
     pub parent_bone: BoneId,
     pub position: [f32; 3],
@@ -27,26 +62,42 @@ pub struct SmdVertex {
     pub links: Vec<SmdLink>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct SmdTriangle {
     pub material: String,
     pub vertices: [SmdVertex; 3],
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct SmdAnimationFrameBone {
     pub translation: [f32; 3],
     pub rotation: [f32; 3],
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct SmdAnimationFrame {
     pub bones: BTreeMap<BoneId, SmdAnimationFrameBone>
 }
 
-#[derive(Default)]
+/// A single vertex in a vertex animation frame. Frames store these by the vertex's index in the
+/// reference model's triangles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmdVertexAnimationVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SmdVertexAnimationFrame {
+    pub vertices: BTreeMap<i32, SmdVertexAnimationVertex>
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Smd {
     pub bones: Vec<SmdBone>,
     pub animation_frames: BTreeMap<i32, SmdAnimationFrame>,
     pub triangles: Vec<SmdTriangle>,
+    pub vertex_animation_frames: BTreeMap<i32, SmdVertexAnimationFrame>,
 }
 
 impl Smd {
@@ -55,6 +106,7 @@ impl Smd {
             bones: Vec::new(),
             animation_frames: BTreeMap::new(),
             triangles: Vec::new(),
+            vertex_animation_frames: BTreeMap::new(),
         }
     }
 
@@ -94,4 +146,11 @@ impl Smd {
             .or_insert_with(|| SmdAnimationFrame::default());
         frame.bones.insert(bone_id, bone);
     }
+
+    /// Sets the state of a vertex at a specific flex frame, overwriting anything previously there.
+    pub fn set_vertex_animation(&mut self, frame: i32, vertex_id: i32, vertex: SmdVertexAnimationVertex) {
+        let frame = self.vertex_animation_frames.entry(frame)
+            .or_default();
+        frame.vertices.insert(vertex_id, vertex);
+    }
 }